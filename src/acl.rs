@@ -0,0 +1,439 @@
+//! Users and the commands and keys each of them may access.
+//!
+//! A user is described by a list of rules, the same as in `ACL SETUSER` and
+//! ACL files, e.g. `on >password ~metrics:* +@read -destroy`. Command rules
+//! are kept in order and the last one matching a command decides whether it
+//! may run, so `+@all -destroy` allows everything but DESTROY. Passwords are
+//! only kept as SHA-256 hashes.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use sha2::{Digest, Sha256};
+
+use command::{self, Command, Handler};
+use glob;
+
+/// The user connections start as.
+pub const DEFAULT_USER: &str = "default";
+
+/// The categories that `+@category` and `-@category` rules can refer to.
+const CATEGORIES: &[&str] = &[
+    "all", "read", "write", "blocking", "pubsub", "transaction", "connection", "admin", "dangerous",
+];
+
+const PUBSUB_COMMANDS: &[&str] = &["SUBSCRIBE", "UNSUBSCRIBE", "PSUBSCRIBE", "PUNSUBSCRIBE", "PUBLISH"];
+const TRANSACTION_COMMANDS: &[&str] = &["MULTI", "EXEC", "DISCARD", "WATCH", "UNWATCH"];
+const CONNECTION_COMMANDS: &[&str] = &["PING", "AUTH", "SELECT"];
+const ADMIN_COMMANDS: &[&str] = &["ACL", "CONFIG", "SLOWLOG", "MONITOR", "CLIENT"];
+const DANGEROUS_COMMANDS: &[&str] = &["DESTROY", "FLUSHDB", "FLUSHALL", "SWAPDB", "ACL", "CONFIG", "INFO", "SLOWLOG", "MONITOR", "CLIENT"];
+
+/// Returns true if the command belongs to the category.
+fn in_category(cmd: &Command, category: &str) -> bool {
+    match category {
+        "all" => true,
+        "read" => matches!(cmd.handler, Handler::Read(_)),
+        "write" => matches!(cmd.handler, Handler::Write(_) | Handler::Databases(_) | Handler::Blocking(_)),
+        "blocking" => matches!(cmd.handler, Handler::Blocking(_)),
+        "pubsub" => PUBSUB_COMMANDS.contains(&cmd.name),
+        "transaction" => TRANSACTION_COMMANDS.contains(&cmd.name),
+        "connection" => CONNECTION_COMMANDS.contains(&cmd.name),
+        "admin" => ADMIN_COMMANDS.contains(&cmd.name),
+        "dangerous" => DANGEROUS_COMMANDS.contains(&cmd.name),
+        _ => false,
+    }
+}
+
+/// Returns the SHA-256 hash of a password as lowercase hex.
+fn hash_password(password: &str) -> String {
+    Sha256::digest(password.as_bytes()).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Compares two byte strings in a time that depends only on the length of
+/// `input`, so timing replies doesn't reveal how much of `secret` matched.
+fn constant_time_eq(input: &[u8], secret: &[u8]) -> bool {
+    if secret.is_empty() {
+        return input.is_empty();
+    }
+
+    let mut diff = (input.len() != secret.len()) as u8;
+    for (i, byte) in input.iter().enumerate() {
+        diff |= byte ^ secret[i % secret.len()];
+    }
+    diff == 0
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct User {
+    pub name: String,
+
+    /// Whether the user can authenticate.
+    pub enabled: bool,
+
+    /// Whether any password is accepted.
+    pub nopass: bool,
+
+    /// The SHA-256 hashes of the passwords, in hex.
+    passwords: Vec<String>,
+
+    /// The glob-style patterns of the keys the user may access.
+    keys: Vec<String>,
+
+    /// Whether each command or `@category` is allowed, in the order the
+    /// rules were given.
+    commands: Vec<(bool, String)>,
+}
+
+impl User {
+    /// Creates a disabled user that can't run any command.
+    pub fn new(name: &str) -> Self {
+        User {
+            name: name.to_string(),
+            enabled: false,
+            nopass: false,
+            passwords: Vec::new(),
+            keys: Vec::new(),
+            commands: Vec::new(),
+        }
+    }
+
+    /// Applies a rule, replying with the reason if it's invalid.
+    pub fn apply(&mut self, rule: &str) -> Result<(), &'static str> {
+        match rule.to_lowercase().as_ref() {
+            "on" => self.enabled = true,
+            "off" => self.enabled = false,
+            "nopass" => {
+                self.nopass = true;
+                self.passwords.clear();
+            },
+            "resetpass" => {
+                self.nopass = false;
+                self.passwords.clear();
+            },
+            "allkeys" => self.keys = vec!["*".to_string()],
+            "resetkeys" => self.keys.clear(),
+            "allcommands" => self.commands = vec![(true, "@all".to_string())],
+            "nocommands" => self.commands = vec![(false, "@all".to_string())],
+            "reset" => *self = User::new(&self.name),
+            _ => return self.apply_with_argument(rule),
+        }
+        Ok(())
+    }
+
+    fn apply_with_argument(&mut self, rule: &str) -> Result<(), &'static str> {
+        let (prefix, argument) = match rule.chars().next() {
+            Some(prefix) => (prefix, &rule[prefix.len_utf8()..]),
+            None => return Err("Syntax error"),
+        };
+
+        match prefix {
+            '>' => self.add_password(hash_password(argument)),
+            '<' => self.remove_password(&hash_password(argument))?,
+            '#' => self.add_password(parse_hash(argument)?),
+            '!' => self.remove_password(&parse_hash(argument)?)?,
+            '~' => if !self.keys.iter().any(|key| key == argument) {
+                self.keys.push(argument.to_string());
+            },
+            '+' | '-' => {
+                let name = argument.to_lowercase();
+                let known = match name.starts_with('@') {
+                    true => CATEGORIES.contains(&&name[1..]),
+                    false => command::lookup(&name.to_uppercase()).is_some(),
+                };
+                if !known {
+                    return Err("Unknown command or category name in ACL");
+                }
+
+                if name == "@all" {
+                    self.commands.clear();
+                }
+                self.commands.push((prefix == '+', name));
+            },
+            _ => return Err("Syntax error"),
+        }
+        Ok(())
+    }
+
+    fn add_password(&mut self, hash: String) {
+        self.nopass = false;
+        if !self.passwords.contains(&hash) {
+            self.passwords.push(hash);
+        }
+    }
+
+    fn remove_password(&mut self, hash: &str) -> Result<(), &'static str> {
+        let len = self.passwords.len();
+        self.passwords.retain(|password| password != hash);
+        if self.passwords.len() == len {
+            return Err("The password you are trying to remove from the user does not exist");
+        }
+        Ok(())
+    }
+
+    /// Returns true if the user is enabled and the password is one of its
+    /// passwords.
+    pub fn check_password(&self, password: &str) -> bool {
+        if !self.enabled {
+            return false;
+        }
+
+        let hash = hash_password(password);
+        // Check every password, so the time taken doesn't reveal which one
+        // matched.
+        self.passwords.iter().fold(self.nopass, |matched, password| {
+            constant_time_eq(hash.as_bytes(), password.as_bytes()) | matched
+        })
+    }
+
+    /// Returns true if the last rule matching the command allows it.
+    pub fn can_run(&self, cmd: &Command) -> bool {
+        self.commands.iter().rev()
+            .find(|(_, name)| match name.starts_with('@') {
+                true => in_category(cmd, &name[1..]),
+                false => name.eq_ignore_ascii_case(cmd.name),
+            })
+            .is_some_and(|&(allowed, _)| allowed)
+    }
+
+    /// Returns true if the key matches one of the patterns of the user.
+    pub fn can_access(&self, key: &str) -> bool {
+        self.keys.iter().any(|pattern| glob::matches(pattern.as_bytes(), key.as_bytes()))
+    }
+
+    /// Returns the rules that recreate the user, as listed by `ACL LIST`.
+    pub fn describe(&self) -> String {
+        let mut rules = vec![
+            "user".to_string(),
+            self.name.clone(),
+            if self.enabled { "on" } else { "off" }.to_string(),
+        ];
+
+        if self.nopass {
+            rules.push("nopass".to_string());
+        }
+        rules.extend(self.passwords.iter().map(|hash| format!("#{}", hash)));
+        rules.extend(self.keys.iter().map(|key| format!("~{}", key)));
+
+        if self.commands.is_empty() {
+            rules.push("-@all".to_string());
+        }
+        rules.extend(self.commands.iter().map(|(allowed, name)| {
+            format!("{}{}", if *allowed { '+' } else { '-' }, name)
+        }));
+
+        rules.join(" ")
+    }
+}
+
+/// Parses the hex SHA-256 hash of a password.
+fn parse_hash(s: &str) -> Result<String, &'static str> {
+    if s.len() != 64 || !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err("The password hash must be exactly 64 characters and contain only lowercase hexadecimal characters");
+    }
+    Ok(s.to_lowercase())
+}
+
+/// The users known to the server.
+#[derive(Debug)]
+pub struct Acl {
+    users: BTreeMap<String, User>,
+}
+
+impl Acl {
+    /// Creates the default user, which can run every command on every key.
+    /// Without a password, connections are authenticated as it right away.
+    pub fn new(requirepass: Option<&str>) -> Self {
+        let mut user = User::new(DEFAULT_USER);
+        user.enabled = true;
+        user.nopass = true;
+        user.keys = vec!["*".to_string()];
+        user.commands = vec![(true, "@all".to_string())];
+        if let Some(password) = requirepass {
+            user.add_password(hash_password(password));
+        }
+
+        let mut users = BTreeMap::new();
+        users.insert(DEFAULT_USER.to_string(), user);
+        Acl { users }
+    }
+
+    pub fn user(&self, name: &str) -> Option<&User> {
+        self.users.get(name)
+    }
+
+    pub fn users(&self) -> impl Iterator<Item = &User> {
+        self.users.values()
+    }
+
+    /// Applies the rules to the user, creating it if it doesn't exist. If a
+    /// rule is invalid, the user is left unchanged and the rule is returned
+    /// with the reason.
+    pub fn set_user<'a>(&mut self, name: &str, rules: &'a [String]) -> Result<(), (&'a str, &'static str)> {
+        let mut user = self.users.get(name).cloned().unwrap_or_else(|| User::new(name));
+        for rule in rules {
+            user.apply(rule).map_err(|reason| (rule.as_ref(), reason))?;
+        }
+
+        self.users.insert(name.to_string(), user);
+        Ok(())
+    }
+
+    /// Removes a user, returning false if it doesn't exist.
+    pub fn del_user(&mut self, name: &str) -> bool {
+        self.users.remove(name).is_some()
+    }
+
+    /// Replaces the users with those of an ACL file, where each line has the
+    /// form `user <name> <rule> ...`. Blank lines and lines starting with `#`
+    /// are ignored. Unless the file defines the default user, it's kept.
+    ///
+    /// Nothing changes if the file has any error.
+    pub fn load(&mut self, path: &Path) -> Result<(), String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Couldn't read ACL file {}: {}", path.display(), e))?;
+
+        let mut acl = Acl { users: BTreeMap::new() };
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let words: Vec<String> = line.split_whitespace().map(String::from).collect();
+            if words.len() < 2 || words[0] != "user" {
+                return Err(format!("{}:{}: Lines must start with 'user <name>'", path.display(), i + 1));
+            }
+            if acl.users.contains_key(&words[1]) {
+                return Err(format!("{}:{}: Duplicate user '{}'", path.display(), i + 1, words[1]));
+            }
+
+            acl.set_user(&words[1], &words[2..]).map_err(|(rule, reason)| {
+                format!("{}:{}: Error in rule '{}': {}", path.display(), i + 1, rule, reason)
+            })?;
+        }
+
+        if !acl.users.contains_key(DEFAULT_USER) {
+            acl.users.insert(DEFAULT_USER.to_string(), self.users[DEFAULT_USER].clone());
+        }
+
+        *self = acl;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    fn cmd(name: &str) -> &'static Command {
+        command::lookup(name).unwrap()
+    }
+
+    fn user(rules: &str) -> User {
+        let mut user = User::new("test");
+        for rule in rules.split_whitespace() {
+            user.apply(rule).unwrap();
+        }
+        user
+    }
+
+    #[test]
+    fn check_constant_time_eq() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secreT", b"secret"));
+        assert!(!constant_time_eq(b"secretsecret", b"secret"));
+        assert!(!constant_time_eq(b"sec", b"secret"));
+        assert!(!constant_time_eq(b"", b"secret"));
+        assert!(constant_time_eq(b"", b""));
+        assert!(!constant_time_eq(b"a", b""));
+    }
+
+    #[test]
+    fn passwords() {
+        let mut user = user("on >secret >other");
+        assert!(user.check_password("secret"));
+        assert!(user.check_password("other"));
+        assert!(!user.check_password("wrong"));
+
+        user.apply("<secret").unwrap();
+        assert!(!user.check_password("secret"));
+        assert!(user.apply("<secret").is_err());
+
+        user.apply("off").unwrap();
+        assert!(!user.check_password("other"));
+
+        let hash = hash_password("hashed");
+        let user = self::user(&format!("on #{}", hash));
+        assert!(user.check_password("hashed"));
+        assert!(!user.describe().contains("hashed "));
+
+        let user = self::user("on nopass");
+        assert!(user.check_password("anything"));
+    }
+
+    #[test]
+    fn command_rules() {
+        let user = user("+@read -hgetall +destroy");
+        assert!(user.can_run(cmd("GET")));
+        assert!(!user.can_run(cmd("HGETALL")));
+        assert!(user.can_run(cmd("DESTROY")));
+        assert!(!user.can_run(cmd("SET")));
+
+        let user = self::user("+@all -@dangerous +flushdb");
+        assert!(user.can_run(cmd("SET")));
+        assert!(!user.can_run(cmd("DESTROY")));
+        assert!(user.can_run(cmd("FLUSHDB")));
+
+        let mut user = User::new("test");
+        assert_eq!(user.apply("+nonexistent"), Err("Unknown command or category name in ACL"));
+        assert_eq!(user.apply("-@nonexistent"), Err("Unknown command or category name in ACL"));
+        assert_eq!(user.apply("bogus"), Err("Syntax error"));
+    }
+
+    #[test]
+    fn key_patterns() {
+        let user = user("~metrics:* ~cache");
+        assert!(user.can_access("metrics:cpu"));
+        assert!(user.can_access("cache"));
+        assert!(!user.can_access("cache:1"));
+
+        assert!(self::user("allkeys").can_access("anything"));
+        assert!(!self::user("allkeys resetkeys").can_access("anything"));
+    }
+
+    #[test]
+    fn describe_user() {
+        let user = user("on nopass ~metrics:* +@read -hgetall");
+        assert_eq!(user.describe(), "user test on nopass ~metrics:* +@read -hgetall");
+        assert_eq!(User::new("test").describe(), "user test off -@all");
+    }
+
+    #[test]
+    fn set_user_is_atomic() {
+        let mut acl = Acl::new(None);
+        let rules = vec!["on".to_string(), "+bogus".to_string()];
+        assert_eq!(acl.set_user("test", &rules), Err(("+bogus", "Unknown command or category name in ACL")));
+        assert!(acl.user("test").is_none());
+    }
+
+    #[test]
+    fn load_file() {
+        let path = env::temp_dir().join(format!("hanbaiki-acl-{}.acl", std::process::id()));
+        fs::write(&path, "# analytics\nuser analytics on >secret ~metrics:* +@read\n\n").unwrap();
+
+        let mut acl = Acl::new(Some("admin"));
+        acl.load(&path).unwrap();
+        assert!(acl.user("analytics").unwrap().check_password("secret"));
+        assert!(acl.user(DEFAULT_USER).unwrap().check_password("admin"));
+
+        fs::write(&path, "user analytics on +bogus\n").unwrap();
+        assert!(acl.load(&path).unwrap_err().ends_with(":1: Error in rule '+bogus': Unknown command or category name in ACL"));
+        assert!(acl.user("analytics").is_some());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
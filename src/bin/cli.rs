@@ -3,12 +3,12 @@ extern crate hanbaiki;
 #[macro_use]
 extern crate clap;
 
-use std::net::{TcpStream, SocketAddr};
 use std::io;
 use std::io::{Write};
 
 use hanbaiki::{RespWriter, RespReader};
 use hanbaiki::Value;
+use hanbaiki::client::{self, Stream};
 use hanbaiki::client::config::Config;
 
 use clap::{App, Arg};
@@ -17,6 +17,11 @@ fn main() {
     let matches = App::new("Hanbaiki CLI")
         .version(crate_version!())
         .about("This is a CLI for the simple key-value store Hanbaiki.")
+        .arg(Arg::with_name("CONFIG")
+            .help("Read options from the specified file. Flags override them")
+            .takes_value(true)
+            .long("config")
+            .short("c"))
         .arg(Arg::with_name("PORT")
             .help("Specify a custom port. Default: 6363")
             .takes_value(true)
@@ -27,20 +32,44 @@ fn main() {
             .takes_value(true)
             .long("bind")
             .short("b"))
+        .arg(Arg::with_name("SOCKET")
+            .help("Connect to the Unix socket at the specified path instead")
+            .takes_value(true)
+            .long("socket")
+            .short("s"))
+        .arg(Arg::with_name("TLS")
+            .help("Connect using TLS")
+            .long("tls"))
+        .arg(Arg::with_name("CACERT")
+            .help("The PEM CA certificate to verify the server with")
+            .takes_value(true)
+            .long("cacert"))
+        .arg(Arg::with_name("CERT")
+            .help("The PEM client certificate, if the server verifies clients")
+            .takes_value(true)
+            .long("cert"))
+        .arg(Arg::with_name("KEY")
+            .help("The PEM private key of the client certificate")
+            .takes_value(true)
+            .long("key"))
+        .arg(Arg::with_name("SNI")
+            .help("The name the server certificate must be valid for. Default: the IP")
+            .takes_value(true)
+            .long("sni"))
         .get_matches();
 
-    let config = Config::new(matches);
+    let config = Config::new(matches).unwrap_or_else(|e| {
+        eprintln!("Invalid configuration: {}", e);
+        std::process::exit(1);
+    });
 
-    let address = SocketAddr::new(config.ip, config.port);
-    let mut stream = TcpStream::connect(address)
+    let mut stream = client::connect(&config)
         .expect("Couldn't connect to the server...");
 
-    stream.set_nodelay(true).expect("set_nodelay failed");
-
     start_repl(&mut stream);
 }
 
-fn start_repl(stream: &mut TcpStream) {
+fn start_repl(stream: &mut Stream) {
     loop {
         print!("> ");
         io::stdout().flush().unwrap();
@@ -55,10 +84,10 @@ fn start_repl(stream: &mut TcpStream) {
     }
 }
 
-fn process_command(command: &str, stream: &mut TcpStream) {
+fn process_command(command: &str, stream: &mut Stream) {
     let v: Vec<&str> = command.split_whitespace().collect();
 
-    if v.len() == 0 { return }
+    if v.is_empty() { return }
 
     let serialized = RespWriter::to_array(&v);
 
@@ -68,24 +97,48 @@ fn process_command(command: &str, stream: &mut TcpStream) {
 
     read(stream);
 
-    if v.len() == 1 {
-        let cmd = v[0].to_ascii_uppercase();
-        if cmd == "EXIT" || cmd == "QUIT" {
-            std::process::exit(0);
+    let cmd = v[0].to_ascii_uppercase();
+    if v.len() == 1 && (cmd == "EXIT" || cmd == "QUIT") {
+        std::process::exit(0);
+    }
+
+    // The server replies once per channel, then pushes published messages.
+    if cmd == "SUBSCRIBE" || cmd == "PSUBSCRIBE" {
+        println!("Reading messages... (press Ctrl-C to quit)");
+        loop {
+            read(stream);
         }
     }
 }
 
-fn read(stream: &mut TcpStream) {
+fn read(stream: &mut Stream) {
     let mut reader = RespReader::new();
     reader.frame_message(stream).unwrap();
     let response = reader.value;
 
-    match response {
-        Value::SimpleString(s) => println!("{}", s),
-        Value::Error(s) => println!("(error) {}", s),
-        Value::Integer(i) => println!("(integer) {}", i),
-        Value::BulkString(s) => println!("\"{}\"", s),
-        _ => unreachable!(),
+    println!("{}", format_value(response, 0));
+}
+
+/// Formats a response for display, numbering the elements of arrays and
+/// indenting nested arrays by `indent` spaces.
+fn format_value(value: Value, indent: usize) -> String {
+    match value {
+        Value::SimpleString(s) => s,
+        Value::Error(s) => format!("(error) {}", s),
+        Value::Integer(i) => format!("(integer) {}", i),
+        Value::BulkString(s) => format!("\"{}\"", s),
+        Value::Nil => "(nil)".to_string(),
+        Value::Array(ref values) if values.is_empty() => "(empty array)".to_string(),
+        Value::Array(values) => {
+            let width = values.len().to_string().len();
+            let lines: Vec<String> = values.into_iter().enumerate().map(|(i, v)| {
+                let prefix = format!("{:>width$}) ", i + 1, width = width);
+                let padding = if i == 0 { 0 } else { indent };
+                let element = format_value(v, indent + prefix.len());
+                format!("{:padding$}{}{}", "", prefix, element, padding = padding)
+            }).collect();
+            lines.join("\n")
+        },
+        Value::Null => unreachable!(),
     }
 }
@@ -1,29 +1,97 @@
 use std::net::IpAddr;
+use std::path::PathBuf;
 
-use clap::{ArgMatches, ErrorKind};
+use clap::ArgMatches;
+
+use config::{ConfigError, Options};
+
+use super::TlsConfig;
+
+/// The directives of the CLI and the names of the matching arguments.
+const DIRECTIVES: &[(&str, &str)] = &[
+    ("port", "PORT"),
+    ("bind", "IP"),
+    ("tls", "TLS"),
+    ("cacert", "CACERT"),
+    ("cert", "CERT"),
+    ("key", "KEY"),
+    ("sni", "SNI"),
+    ("socket", "SOCKET"),
+];
 
 #[derive(Debug)]
 pub struct Config {
     pub ip: IpAddr,
     pub port: u16,
+    pub tls: Option<TlsConfig>,
+
+    /// The Unix socket to connect to instead of the IP and port.
+    pub socket: Option<PathBuf>,
 }
 
 impl Config {
-    pub fn new(matches: ArgMatches) -> Self {
-        let port = value_t!(matches, "PORT", u16).unwrap_or_else(|e| {
-            if e.kind == ErrorKind::ValueValidation {
-                println!("Specified port value is invalid, using default 6363.");
+    pub fn new(matches: ArgMatches) -> Result<Self, ConfigError> {
+        let options = Options::new(&matches, DIRECTIVES)?;
+
+        let port = options.parse("port")?.unwrap_or(6363);
+        let ip = options.parse("bind")?.unwrap_or_else(|| "127.0.0.1".parse().unwrap());
+
+        let tls = options.flag("tls")?;
+        for directive in &["cacert", "cert", "key", "sni"] {
+            if options.is_set(directive) && !tls {
+                return Err(ConfigError::new(format!("{} requires tls to be enabled", directive)));
             }
-            6363
-        });
+        }
+        options.require("cert", "key")?;
+        options.require("key", "cert")?;
 
-        let ip = value_t!(matches, "IP", IpAddr).unwrap_or_else(|e| {
-            if e.kind == ErrorKind::ValueValidation {
-                println!("Specified IP address is invalid, using default 127.0.0.1.");
+        let tls = if tls {
+            if !options.is_set("cacert") {
+                return Err(ConfigError::new("tls requires cacert to be set".to_string()));
             }
-            "127.0.0.1".parse().unwrap()
-        });
+            Some(TlsConfig {
+                ca_cert_file: options.path("cacert").unwrap(),
+                cert_file: options.path("cert"),
+                key_file: options.path("key"),
+                server_name: options.string("sni"),
+            })
+        } else {
+            None
+        };
+
+        let socket = options.path("socket");
+        if socket.is_some() && tls.is_some() {
+            return Err(ConfigError::new("TLS isn't supported over a Unix socket".to_string()));
+        }
+
+        Ok(Config { ip, port, tls, socket })
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use clap::{App, Arg};
+
+    fn config(args: &[&str]) -> Result<Config, ConfigError> {
+        let mut app = App::new("test").arg(Arg::with_name("CONFIG").long("config").takes_value(true));
+        for &(directive, arg) in DIRECTIVES {
+            app = app.arg(Arg::with_name(arg).long(directive).takes_value(directive != "tls"));
+        }
+        Config::new(app.get_matches_from(Some(&"test").into_iter().chain(args)))
+    }
+
+    #[test]
+    fn tls_options() {
+        let tls = config(&["--tls", "--cacert", "ca.crt", "--sni", "localhost"]).unwrap().tls.unwrap();
+        assert_eq!(tls.ca_cert_file, PathBuf::from("ca.crt"));
+        assert_eq!(tls.server_name, Some("localhost".to_string()));
 
-        Config { ip, port }
+        let error = |args: &[&str]| config(args).unwrap_err().to_string();
+        assert_eq!(error(&["--tls"]), "tls requires cacert to be set");
+        assert_eq!(error(&["--cacert", "ca.crt"]), "cacert requires tls to be enabled");
+        assert_eq!(error(&["--tls", "--cacert", "ca.crt", "--cert", "c.crt"]), "cert requires key to be set");
+        assert_eq!(error(&["--tls", "--cacert", "ca.crt", "--socket", "h.sock"]), "TLS isn't supported over a Unix socket");
     }
 }
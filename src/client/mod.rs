@@ -1 +1,95 @@
+//! Connecting to a server, over TLS if configured.
+
 pub mod config;
+
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+
+use tls::{self, TlsStream};
+
+use self::config::Config;
+
+/// A connection to a server.
+pub enum Stream {
+    Tcp(TcpStream),
+    Tls(TlsStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+/// Connects to the server in the configuration, through its Unix socket if
+/// one is given.
+pub fn connect(config: &Config) -> io::Result<Stream> {
+    #[cfg(unix)]
+    {
+        if let Some(path) = &config.socket {
+            return UnixStream::connect(path).map(Stream::Unix);
+        }
+    }
+
+    let address = SocketAddr::new(config.ip, config.port);
+    let stream = TcpStream::connect(address)?;
+    stream.set_nodelay(true)?;
+
+    let tls = match &config.tls {
+        Some(tls) => tls,
+        None => return Ok(Stream::Tcp(stream)),
+    };
+
+    let identity = match (&tls.cert_file, &tls.key_file) {
+        (Some(cert_file), Some(key_file)) => Some((cert_file.as_ref(), key_file.as_ref())),
+        _ => None,
+    };
+    let tls_config = tls::client_config(&tls.ca_cert_file, identity)?;
+    let server_name = tls.server_name.clone().unwrap_or_else(|| config.ip.to_string());
+    Ok(Stream::Tls(TlsStream::connect(tls_config, &server_name, stream)?))
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => stream.read(buf),
+            Stream::Tls(stream) => stream.read(buf),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => stream.write(buf),
+            Stream::Tls(stream) => stream.write(buf),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => stream.flush(),
+            Stream::Tls(stream) => stream.flush(),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.flush(),
+        }
+    }
+}
+
+/// The TLS settings of a client.
+#[derive(Debug)]
+pub struct TlsConfig {
+    /// The CA certificate the server certificate must be signed by.
+    pub ca_cert_file: PathBuf,
+
+    /// The certificate and key to present if the server verifies clients.
+    pub cert_file: Option<PathBuf>,
+    pub key_file: Option<PathBuf>,
+
+    /// The name the server certificate must be valid for. Defaults to the
+    /// IP address connected to.
+    pub server_name: Option<String>,
+}
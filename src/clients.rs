@@ -0,0 +1,143 @@
+//! The registry of client connections, which CLIENT LIST and CLIENT KILL
+//! look through.
+
+use std::collections::BTreeMap;
+use std::net::Shutdown;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Instant;
+
+use socket::Socket;
+
+/// What other connections can see of a client.
+pub struct Client {
+    pub id: u64,
+
+    /// The address of the client, as `ip:port` or the path of the Unix
+    /// socket followed by the client ID.
+    pub addr: String,
+
+    /// The name set by CLIENT SETNAME, empty if none.
+    pub name: Mutex<String>,
+
+    created: Instant,
+
+    /// Milliseconds between `created` and the last command.
+    active: AtomicU64,
+
+    /// The database selected after the last command, and its name.
+    db: AtomicUsize,
+    command: Mutex<&'static str>,
+
+    /// The socket of the connection, shut down to kill it and checked to tell
+    /// whether the client disconnected while a command is waiting.
+    socket: Option<Socket>,
+}
+
+impl Client {
+    pub fn new(id: u64, addr: String, socket: Option<Socket>) -> Self {
+        Client {
+            id,
+            addr,
+            name: Mutex::new(String::new()),
+            created: Instant::now(),
+            active: AtomicU64::new(0),
+            db: AtomicUsize::new(0),
+            command: Mutex::new("NULL"),
+            socket,
+        }
+    }
+
+    /// Records a command run by the client.
+    pub fn touch(&self, db: usize, command: &'static str) {
+        self.active.store(self.created.elapsed().as_millis() as u64, Ordering::Relaxed);
+        self.db.store(db, Ordering::Relaxed);
+        *self.command.lock().unwrap() = command;
+    }
+
+    pub fn socket(&self) -> Option<&Socket> {
+        self.socket.as_ref()
+    }
+
+    /// Closes the connection. The reading thread sees it closed and ends the
+    /// session.
+    pub fn kill(&self) {
+        if let Some(socket) = &self.socket {
+            let _ = socket.shutdown(Shutdown::Both);
+        }
+    }
+
+    /// Describes the client as a line of CLIENT LIST, with its age and idle
+    /// time in seconds.
+    pub fn describe(&self) -> String {
+        let elapsed = self.created.elapsed();
+        let idle = (elapsed.as_millis() as u64).saturating_sub(self.active.load(Ordering::Relaxed)) / 1000;
+        format!(
+            "id={} addr={} name={} age={} idle={} db={} cmd={}",
+            self.id,
+            self.addr,
+            self.name.lock().unwrap(),
+            elapsed.as_secs(),
+            idle,
+            self.db.load(Ordering::Relaxed),
+            self.command.lock().unwrap().to_lowercase(),
+        )
+    }
+}
+
+/// The connected clients by ID.
+#[derive(Default)]
+pub struct Clients {
+    clients: Mutex<BTreeMap<u64, Arc<Client>>>,
+}
+
+impl Clients {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Adds a client, replacing any with the same ID.
+    pub fn register(&self, client: Arc<Client>) {
+        self.clients.lock().unwrap().insert(client.id, client);
+    }
+
+    pub fn unregister(&self, id: u64) {
+        self.clients.lock().unwrap().remove(&id);
+    }
+
+    /// Closes the connection of the client with the ID, if any.
+    pub fn kill(&self, id: u64) {
+        if let Some(client) = self.clients.lock().unwrap().get(&id) {
+            client.kill();
+        }
+    }
+
+    /// Returns the clients ordered by ID.
+    pub fn all(&self) -> Vec<Arc<Client>> {
+        self.clients.lock().unwrap().values().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn register_and_describe() {
+        let clients = Clients::new();
+        clients.register(Arc::new(Client::new(1, "127.0.0.1:5000".to_string(), None)));
+        clients.register(Arc::new(Client::new(2, "127.0.0.1:5001".to_string(), None)));
+
+        let all = clients.all();
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].describe(), "id=1 addr=127.0.0.1:5000 name= age=0 idle=0 db=0 cmd=null");
+
+        all[1].touch(3, "SELECT");
+        *all[1].name.lock().unwrap() = "worker".to_string();
+        assert_eq!(all[1].describe(), "id=2 addr=127.0.0.1:5001 name=worker age=0 idle=0 db=3 cmd=select");
+
+        clients.unregister(1);
+        assert_eq!(clients.all().iter().map(|client| client.id).collect::<Vec<_>>(), vec![2]);
+    }
+}
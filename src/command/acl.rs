@@ -0,0 +1,113 @@
+use respwriter::RespWriter;
+use response::Response;
+use session::Session;
+
+use acl::DEFAULT_USER;
+
+use super::{CommandError, Result};
+
+/// ACL SETUSER username [rule ...]
+/// ACL DELUSER username [username ...]
+/// ACL LIST
+/// ACL WHOAMI
+/// ACL LOAD
+pub fn acl(session: &mut Session, args: &[String]) -> Result<Response> {
+    match (args[1].to_uppercase().as_ref(), args.len()) {
+        ("SETUSER", n) if n >= 3 => setuser(session, &args[2], &args[3..]),
+        ("DELUSER", n) if n >= 3 => deluser(session, &args[2..]),
+        ("LIST", 2) => list(session),
+        ("WHOAMI", 2) => Ok(Response::KeepAlive(RespWriter::to_bulk_string(&session.user))),
+        ("LOAD", 2) => load(session),
+        _ => Err(CommandError::Syntax),
+    }
+}
+
+/// Creates or modifies a user. If any rule is invalid, nothing changes.
+fn setuser(session: &mut Session, name: &str, rules: &[String]) -> Result<Response> {
+    let mut acl = session.shared.acl.write().unwrap();
+    acl.set_user(name, rules).map_err(|(rule, reason)| CommandError::InvalidAclRule(rule.to_string(), reason))?;
+    Ok(Response::build_ok())
+}
+
+/// Replies with the number of users removed. Connections authenticated as
+/// them can't run commands anymore.
+fn deluser(session: &mut Session, names: &[String]) -> Result<Response> {
+    if names.iter().any(|name| name == DEFAULT_USER) {
+        return Err(CommandError::DeleteDefaultUser);
+    }
+
+    let mut acl = session.shared.acl.write().unwrap();
+    let deleted = names.iter().filter(|name| acl.del_user(name)).count();
+    Ok(Response::KeepAlive(RespWriter::to_signed_integer(deleted as i64)))
+}
+
+fn list(session: &mut Session) -> Result<Response> {
+    let acl = session.shared.acl.read().unwrap();
+    let users: Vec<String> = acl.users().map(|user| RespWriter::to_bulk_string(&user.describe())).collect();
+    Ok(Response::KeepAlive(RespWriter::to_raw_array(&users)))
+}
+
+/// Reloads the users from the ACL file the server was started with.
+fn load(session: &mut Session) -> Result<Response> {
+    let path = session.shared.config.read().unwrap().aclfile.clone().ok_or(CommandError::NoAclFile)?;
+    let mut acl = session.shared.acl.write().unwrap();
+    acl.load(&path).map_err(CommandError::AclLoad)?;
+    Ok(Response::build_ok())
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use command::test_args as args;
+    use command::test_session;
+    use session::Shared;
+    use std::sync::Arc;
+    use store::Store;
+
+    #[test]
+    fn setuser_and_list() {
+        let mut session = test_session(&Arc::new(Shared::new(vec![Store::new()])));
+
+        let response = acl(&mut session, &args("ACL SETUSER analytics on nopass ~metrics:* +@read"));
+        assert_eq!(response, Ok(Response::build_ok()));
+        let response = acl(&mut session, &args("ACL SETUSER analytics -hgetall"));
+        assert_eq!(response, Ok(Response::build_ok()));
+
+        let response = acl(&mut session, &args("ACL SETUSER analytics +bogus"));
+        let reason = "Unknown command or category name in ACL";
+        assert_eq!(response, Err(CommandError::InvalidAclRule("+bogus".to_string(), reason)));
+
+        let expected = RespWriter::to_array(&[
+            "user analytics on nopass ~metrics:* +@read -hgetall",
+            "user default on nopass ~* +@all",
+        ]);
+        assert_eq!(acl(&mut session, &args("ACL LIST")), Ok(Response::KeepAlive(expected)));
+    }
+
+    #[test]
+    fn deluser_command() {
+        let mut session = test_session(&Arc::new(Shared::new(vec![Store::new()])));
+        acl(&mut session, &args("ACL SETUSER analytics")).unwrap();
+
+        let response = acl(&mut session, &args("ACL DELUSER analytics missing"));
+        assert_eq!(response, Ok(Response::KeepAlive(RespWriter::to_signed_integer(1))));
+        let response = acl(&mut session, &args("ACL DELUSER default"));
+        assert_eq!(response, Err(CommandError::DeleteDefaultUser));
+    }
+
+    #[test]
+    fn whoami_command() {
+        let mut session = test_session(&Arc::new(Shared::new(vec![Store::new()])));
+        let response = acl(&mut session, &args("ACL WHOAMI"));
+        assert_eq!(response, Ok(Response::KeepAlive(RespWriter::to_bulk_string("default"))));
+    }
+
+    #[test]
+    fn invalid_subcommand() {
+        let mut session = test_session(&Arc::new(Shared::new(vec![Store::new()])));
+        assert_eq!(acl(&mut session, &args("ACL LIST extra")), Err(CommandError::Syntax));
+        assert_eq!(acl(&mut session, &args("ACL BOGUS")), Err(CommandError::Syntax));
+        assert_eq!(acl(&mut session, &args("ACL LOAD")), Err(CommandError::NoAclFile));
+    }
+}
@@ -0,0 +1,82 @@
+use acl::DEFAULT_USER;
+use response::Response;
+use session::Session;
+
+use super::{CommandError, Result};
+
+/// AUTH [username] password
+///
+/// Authenticates the connection as the user, or as the default user if no
+/// username is given.
+pub fn auth(session: &mut Session, args: &[String]) -> Result<Response> {
+    let (name, password) = match args.len() {
+        2 => (DEFAULT_USER, &args[1]),
+        3 => (args[1].as_ref(), &args[2]),
+        _ => return Err(CommandError::Syntax),
+    };
+
+    let acl = session.shared.acl.read().unwrap();
+    let user = acl.user(name);
+    if args.len() == 2 && user.is_some_and(|user| user.nopass) {
+        return Err(CommandError::NoPassword);
+    }
+    if !user.is_some_and(|user| user.check_password(password)) {
+        return Err(CommandError::WrongPass);
+    }
+    drop(acl);
+
+    session.user = name.to_string();
+    session.authenticated = true;
+    Ok(Response::build_ok())
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use acl::Acl;
+    use command::test_args as args;
+    use command::test_session;
+    use session::Shared;
+    use std::sync::{Arc, RwLock};
+    use store::Store;
+
+    fn new_session(requirepass: Option<&str>) -> Session {
+        let mut shared = Shared::new(vec![Store::new()]);
+        shared.acl = RwLock::new(Acl::new(requirepass));
+        let rules = args("on >analytics ~metrics:* +@read");
+        shared.acl.write().unwrap().set_user("analytics", &rules).unwrap();
+
+        test_session(&Arc::new(shared))
+    }
+
+    #[test]
+    fn auth_command() {
+        let mut session = new_session(Some("secret"));
+        assert!(!session.authenticated);
+
+        assert_eq!(auth(&mut session, &args("AUTH wrong")), Err(CommandError::WrongPass));
+        assert!(!session.authenticated);
+
+        assert_eq!(auth(&mut session, &args("AUTH secret")), Ok(Response::build_ok()));
+        assert!(session.authenticated);
+        assert_eq!(session.user, DEFAULT_USER);
+    }
+
+    #[test]
+    fn auth_with_username() {
+        let mut session = new_session(Some("secret"));
+
+        assert_eq!(auth(&mut session, &args("AUTH analytics secret")), Err(CommandError::WrongPass));
+        assert_eq!(auth(&mut session, &args("AUTH missing analytics")), Err(CommandError::WrongPass));
+        assert_eq!(auth(&mut session, &args("AUTH analytics analytics")), Ok(Response::build_ok()));
+        assert_eq!(session.user, "analytics");
+    }
+
+    #[test]
+    fn auth_without_password() {
+        let mut session = new_session(None);
+        assert!(session.authenticated);
+        assert_eq!(auth(&mut session, &args("AUTH secret")), Err(CommandError::NoPassword));
+    }
+}
@@ -0,0 +1,307 @@
+use respwriter::RespWriter;
+use response::Response;
+use store::{Data, Store};
+
+use super::{CommandError, Result, normalize_range, parse_int};
+
+/// The highest bit offset, which limits a bitmap to 512MB.
+const MAX_BIT_OFFSET: i64 = (1 << 32) - 1;
+
+fn get_bytes<'a>(store: &'a Store, key: &str) -> Result<&'a [u8]> {
+    match store.get(key) {
+        Some(Data::String(value)) => Ok(value),
+        Some(_) => Err(CommandError::WrongType),
+        None => Ok(&[]),
+    }
+}
+
+fn parse_offset(s: &str) -> Result<usize> {
+    match parse_int(s) {
+        Ok(offset) if (0..=MAX_BIT_OFFSET).contains(&offset) => Ok(offset as usize),
+        _ => Err(CommandError::InvalidBitOffset),
+    }
+}
+
+fn parse_bit(s: &str) -> Result<u8> {
+    match s {
+        "0" => Ok(0),
+        "1" => Ok(1),
+        _ => Err(CommandError::InvalidBit),
+    }
+}
+
+/// Parses the optional `start end` byte range that follows the arguments at
+/// `args[..i]`, returning the normalized range and whether an end was given.
+fn parse_byte_range(args: &[String], i: usize, len: usize) -> Result<(Option<(usize, usize)>, bool)> {
+    let start = match args.get(i) {
+        Some(start) => parse_int(start)?,
+        None => return Ok((normalize_range(0, -1, len), false)),
+    };
+
+    let end = match args.get(i + 1) {
+        Some(end) => Some(parse_int(end)?),
+        None => None,
+    };
+
+    if args.len() > i + 2 {
+        return Err(CommandError::Syntax);
+    }
+
+    Ok((normalize_range(start, end.unwrap_or(-1), len), end.is_some()))
+}
+
+/// Returns the bit at `offset`, where bit 0 is the most significant bit of
+/// the first byte.
+fn bit_at(bytes: &[u8], offset: usize) -> u8 {
+    match bytes.get(offset / 8) {
+        Some(byte) => (byte >> (7 - offset % 8)) & 1,
+        None => 0,
+    }
+}
+
+/// SETBIT key offset value
+///
+/// Grows the string with zero bytes as needed and replies with the bit that
+/// was previously stored at the offset.
+pub fn setbit(store: &mut Store, args: &[String]) -> Result<Response> {
+    let offset = parse_offset(&args[2])?;
+    let bit = parse_bit(&args[3])?;
+
+    let bytes = match store.get_or_insert_with(&args[1], || Data::String(vec![])) {
+        Data::String(value) => value,
+        _ => return Err(CommandError::WrongType),
+    };
+
+    let index = offset / 8;
+    if bytes.len() <= index {
+        bytes.resize(index + 1, 0);
+    }
+
+    let previous = bit_at(bytes, offset);
+    let mask = 1 << (7 - offset % 8);
+    if bit == 1 {
+        bytes[index] |= mask;
+    } else {
+        bytes[index] &= !mask;
+    }
+    store.touch(&args[1]);
+
+    Ok(Response::KeepAlive(RespWriter::to_signed_integer(previous as i64)))
+}
+
+/// GETBIT key offset
+pub fn getbit(store: &Store, args: &[String]) -> Result<Response> {
+    let offset = parse_offset(&args[2])?;
+    let bytes = get_bytes(store, &args[1])?;
+    Ok(Response::KeepAlive(RespWriter::to_signed_integer(bit_at(bytes, offset) as i64)))
+}
+
+/// BITCOUNT key [start end]
+///
+/// The range is in bytes and may be negative to count from the end.
+pub fn bitcount(store: &Store, args: &[String]) -> Result<Response> {
+    if args.len() == 3 {
+        return Err(CommandError::Syntax);
+    }
+
+    let bytes = get_bytes(store, &args[1])?;
+    let count = match parse_byte_range(args, 2, bytes.len())?.0 {
+        Some((start, end)) => bytes[start..=end].iter().map(|b| b.count_ones()).sum(),
+        None => 0,
+    };
+
+    Ok(Response::KeepAlive(RespWriter::to_signed_integer(count as i64)))
+}
+
+/// BITPOS key bit [start [end]]
+///
+/// Replies with the position of the first bit set to `bit`, or -1 if there's
+/// none. When looking for a clear bit without an end, the string is treated
+/// as padded with zeros, so the position after the last byte is returned.
+pub fn bitpos(store: &Store, args: &[String]) -> Result<Response> {
+    let bit = parse_bit(&args[2])?;
+    let bytes = get_bytes(store, &args[1])?;
+
+    let (range, has_end) = parse_byte_range(args, 3, bytes.len())?;
+    let (start, end) = match range {
+        Some(range) => range,
+        None if bit == 0 && bytes.is_empty() && !has_end => {
+            return Ok(Response::KeepAlive(RespWriter::to_signed_integer(0)));
+        },
+        None => return Ok(Response::KeepAlive(RespWriter::to_signed_integer(-1))),
+    };
+
+    let skip = if bit == 1 { 0 } else { 0xff };
+    let position = bytes[start..=end].iter()
+        .position(|&b| b != skip)
+        .map(|i| {
+            let index = start + i;
+            let offset = (0..8).find(|&j| bit_at(bytes, index * 8 + j) == bit).unwrap();
+            (index * 8 + offset) as i64
+        });
+
+    let position = match position {
+        Some(p) => p,
+        None if bit == 0 && !has_end => ((end + 1) * 8) as i64,
+        None => -1,
+    };
+
+    Ok(Response::KeepAlive(RespWriter::to_signed_integer(position)))
+}
+
+/// BITOP AND|OR|XOR|NOT destkey key [key ...]
+///
+/// Stores the result in destkey and replies with its length. Shorter strings
+/// are treated as padded with zero bytes.
+pub fn bitop(store: &mut Store, args: &[String]) -> Result<Response> {
+    let op = args[1].to_ascii_uppercase();
+    let sources = &args[3..];
+
+    if op == "NOT" && sources.len() != 1 {
+        return Err(CommandError::Syntax);
+    }
+
+    let values = sources.iter()
+        .map(|key| get_bytes(store, key))
+        .collect::<Result<Vec<&[u8]>>>()?;
+    let len = values.iter().map(|v| v.len()).max().unwrap_or(0);
+
+    let byte_at = |value: &[u8], i: usize| value.get(i).cloned().unwrap_or(0);
+    let fold = |f: fn(u8, u8) -> u8| -> Vec<u8> {
+        (0..len).map(|i| {
+            values[1..].iter().fold(byte_at(values[0], i), |acc, v| f(acc, byte_at(v, i)))
+        }).collect()
+    };
+
+    let result = match op.as_ref() {
+        "AND" => fold(|a, b| a & b),
+        "OR" => fold(|a, b| a | b),
+        "XOR" => fold(|a, b| a ^ b),
+        "NOT" => values[0].iter().map(|b| !b).collect(),
+        _ => return Err(CommandError::Syntax),
+    };
+
+    store.remove(&args[2]);
+    if !result.is_empty() {
+        store.insert(args[2].clone(), Data::String(result));
+    }
+
+    Ok(Response::KeepAlive(RespWriter::to_signed_integer(len as i64)))
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use command::test_args as args;
+    use command::test_integer as integer;
+
+    fn bytes_of<'a>(store: &'a Store, key: &str) -> &'a [u8] {
+        get_bytes(store, key).unwrap()
+    }
+
+    #[test]
+    fn setbit_grows_value() {
+        let mut store = Store::new();
+
+        assert_eq!(setbit(&mut store, &args("SETBIT flags 7 1")), integer(0));
+        assert_eq!(bytes_of(&store, "flags"), &[0b0000_0001]);
+
+        assert_eq!(setbit(&mut store, &args("SETBIT flags 17 1")), integer(0));
+        assert_eq!(bytes_of(&store, "flags"), &[0b0000_0001, 0, 0b0100_0000]);
+
+        assert_eq!(setbit(&mut store, &args("SETBIT flags 7 0")), integer(1));
+        assert_eq!(bytes_of(&store, "flags"), &[0, 0, 0b0100_0000]);
+    }
+
+    #[test]
+    fn setbit_invalid_arguments() {
+        let mut store = Store::new();
+
+        assert_eq!(setbit(&mut store, &args("SETBIT flags -1 1")), Err(CommandError::InvalidBitOffset));
+        assert_eq!(setbit(&mut store, &args("SETBIT flags 4294967296 1")), Err(CommandError::InvalidBitOffset));
+        assert_eq!(setbit(&mut store, &args("SETBIT flags 0 2")), Err(CommandError::InvalidBit));
+        assert!(!store.contains_key("flags"));
+    }
+
+    #[test]
+    fn getbit_command() {
+        let mut store = Store::new();
+        store.insert("a".to_string(), Data::String(b"a".to_vec()));
+
+        // 'a' is 0b0110_0001
+        assert_eq!(getbit(&store, &args("GETBIT a 0")), integer(0));
+        assert_eq!(getbit(&store, &args("GETBIT a 1")), integer(1));
+        assert_eq!(getbit(&store, &args("GETBIT a 7")), integer(1));
+        assert_eq!(getbit(&store, &args("GETBIT a 100")), integer(0));
+        assert_eq!(getbit(&store, &args("GETBIT missing 0")), integer(0));
+    }
+
+    #[test]
+    fn bitcount_command() {
+        let mut store = Store::new();
+        store.insert("s".to_string(), Data::String(b"foobar".to_vec()));
+
+        assert_eq!(bitcount(&store, &args("BITCOUNT s")), integer(26));
+        assert_eq!(bitcount(&store, &args("BITCOUNT s 0 0")), integer(4));
+        assert_eq!(bitcount(&store, &args("BITCOUNT s 1 1")), integer(6));
+        assert_eq!(bitcount(&store, &args("BITCOUNT s -2 -1")), integer(7));
+        assert_eq!(bitcount(&store, &args("BITCOUNT s 5 1")), integer(0));
+        assert_eq!(bitcount(&store, &args("BITCOUNT missing")), integer(0));
+        assert_eq!(bitcount(&store, &args("BITCOUNT s 0")), Err(CommandError::Syntax));
+    }
+
+    #[test]
+    fn bitpos_command() {
+        let mut store = Store::new();
+        store.insert("s".to_string(), Data::String(vec![0xff, 0xf0, 0x00]));
+        store.insert("ones".to_string(), Data::String(vec![0xff, 0xff]));
+
+        assert_eq!(bitpos(&store, &args("BITPOS s 0")), integer(12));
+        assert_eq!(bitpos(&store, &args("BITPOS s 1 1")), integer(8));
+        assert_eq!(bitpos(&store, &args("BITPOS s 1 2")), integer(-1));
+        assert_eq!(bitpos(&store, &args("BITPOS s 0 2")), integer(16));
+
+        // without an end, the string is treated as padded with zeros
+        assert_eq!(bitpos(&store, &args("BITPOS ones 0")), integer(16));
+        assert_eq!(bitpos(&store, &args("BITPOS ones 0 0 -1")), integer(-1));
+
+        assert_eq!(bitpos(&store, &args("BITPOS missing 0")), integer(0));
+        assert_eq!(bitpos(&store, &args("BITPOS missing 1")), integer(-1));
+    }
+
+    #[test]
+    fn bitop_command() {
+        let mut store = Store::new();
+        store.insert("a".to_string(), Data::String(vec![0b1100, 0xff]));
+        store.insert("b".to_string(), Data::String(vec![0b1010]));
+
+        assert_eq!(bitop(&mut store, &args("BITOP AND dest a b")), integer(2));
+        assert_eq!(bytes_of(&store, "dest"), &[0b1000, 0]);
+
+        assert_eq!(bitop(&mut store, &args("BITOP OR dest a b")), integer(2));
+        assert_eq!(bytes_of(&store, "dest"), &[0b1110, 0xff]);
+
+        assert_eq!(bitop(&mut store, &args("BITOP XOR dest a b")), integer(2));
+        assert_eq!(bytes_of(&store, "dest"), &[0b0110, 0xff]);
+
+        assert_eq!(bitop(&mut store, &args("BITOP NOT dest b")), integer(1));
+        assert_eq!(bytes_of(&store, "dest"), &[0b1111_0101]);
+
+        assert_eq!(bitop(&mut store, &args("BITOP NOT dest a b")), Err(CommandError::Syntax));
+        assert_eq!(bitop(&mut store, &args("BITOP NAND dest a b")), Err(CommandError::Syntax));
+
+        assert_eq!(bitop(&mut store, &args("BITOP OR dest missing")), integer(0));
+        assert!(!store.contains_key("dest"));
+    }
+
+    #[test]
+    fn wrong_type() {
+        let mut store = Store::new();
+        store.insert("list".to_string(), Data::List(Default::default()));
+
+        assert_eq!(setbit(&mut store, &args("SETBIT list 0 1")), Err(CommandError::WrongType));
+        assert_eq!(bitcount(&store, &args("BITCOUNT list")), Err(CommandError::WrongType));
+        assert_eq!(bitop(&mut store, &args("BITOP AND dest list")), Err(CommandError::WrongType));
+    }
+}
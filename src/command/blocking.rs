@@ -0,0 +1,335 @@
+//! Commands that wait for another client to push to a list.
+//!
+//! A blocking command first tries to run like its non-blocking version. If
+//! there's nothing to pop, it registers a waiter on its keys and parks the
+//! connection thread. Every write command then serves the waiters on keys
+//! that now hold a list, in the order they started waiting, while it still
+//! holds the write lock, so no other client can take the element first.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use respwriter::RespWriter;
+use response::Response;
+use session::Session;
+use store::{Data, Store};
+
+use super::{BlockingFn, CommandError, Result};
+
+/// How often a waiting connection checks whether its client disconnected.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+struct Waiter {
+    db: usize,
+    pop: BlockingFn,
+    args: Vec<String>,
+    reply: Mutex<Option<Result<Response>>>,
+    served: Condvar,
+}
+
+impl Waiter {
+    /// The keys waited on: the source of BLMOVE, or all but the timeout for
+    /// BLPOP and BRPOP.
+    fn keys(&self) -> &[String] {
+        if self.args[0] == "BLMOVE" {
+            &self.args[1..2]
+        } else {
+            &self.args[1..self.args.len() - 1]
+        }
+    }
+}
+
+/// The waiters of each database and key, in the order they started waiting.
+#[derive(Default)]
+pub struct Blocked {
+    waiters: HashMap<(usize, String), VecDeque<Arc<Waiter>>>,
+}
+
+impl Blocked {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Returns the number of connections waiting.
+    pub fn clients(&self) -> usize {
+        let mut waiters: Vec<*const Waiter> = self.waiters.values().flatten().map(Arc::as_ptr).collect();
+        waiters.sort();
+        waiters.dedup();
+        waiters.len()
+    }
+
+    fn register(&mut self, waiter: &Arc<Waiter>) {
+        for key in waiter.keys() {
+            let queue = self.waiters.entry((waiter.db, key.clone())).or_default();
+            queue.push_back(Arc::clone(waiter));
+        }
+    }
+
+    fn remove(&mut self, waiter: &Arc<Waiter>) {
+        for key in waiter.keys() {
+            let key = (waiter.db, key.clone());
+            if let Some(queue) = self.waiters.get_mut(&key) {
+                queue.retain(|w| !Arc::ptr_eq(w, waiter));
+                if queue.is_empty() {
+                    self.waiters.remove(&key);
+                }
+            }
+        }
+    }
+}
+
+/// Serves the waiters on keys of database `db` that hold a list. Called
+/// after every write with the store still locked.
+pub fn serve(blocked: &Mutex<Blocked>, db: usize, store: &mut Store) {
+    let mut blocked = blocked.lock().unwrap();
+
+    // Serving BLMOVE pushes to another list, which may serve more waiters.
+    let mut served = true;
+    while served && !blocked.waiters.is_empty() {
+        served = false;
+
+        let ready: Vec<(usize, String)> = blocked.waiters.keys()
+            .filter(|&(waiter_db, key)| *waiter_db == db && matches!(store.get(key), Some(Data::List(_))))
+            .cloned()
+            .collect();
+
+        for key in ready {
+            while let Some(waiter) = blocked.waiters.get(&key).and_then(|queue| queue.front()).cloned() {
+                let reply = match (waiter.pop)(store, &waiter.args) {
+                    Ok(Some(reply)) => Ok(reply),
+                    Ok(None) => break,
+                    Err(e) => Err(e),
+                };
+
+                *waiter.reply.lock().unwrap() = Some(reply);
+                waiter.served.notify_one();
+                blocked.remove(&waiter);
+                served = true;
+            }
+        }
+    }
+}
+
+/// Serves the waiters of every database, with all of them locked.
+pub fn serve_all(blocked: &Mutex<Blocked>, stores: &mut [&mut Store]) {
+    for (db, store) in stores.iter_mut().enumerate() {
+        serve(blocked, db, store);
+    }
+}
+
+/// Parses a timeout in seconds, where 0 means no timeout.
+fn parse_timeout(s: &str) -> Result<Option<Duration>> {
+    let seconds: f64 = s.parse().map_err(|_| CommandError::InvalidTimeout)?;
+    if seconds == 0.0 {
+        return Ok(None);
+    }
+    // Fails on negative, infinite and too large values.
+    Duration::try_from_secs_f64(seconds).map(Some).map_err(|_| CommandError::InvalidTimeout)
+}
+
+/// The reply when nothing could be popped before the timeout.
+pub fn timeout_reply(name: &str) -> Response {
+    if name == "BLMOVE" {
+        Response::KeepAlive(RespWriter::null_bulk_string())
+    } else {
+        Response::KeepAlive(RespWriter::null_array())
+    }
+}
+
+/// Runs a blocking command, waiting until a push lets it pop, the timeout
+/// given as its last argument expires, or the client disconnects.
+pub fn execute(session: &mut Session, pop: BlockingFn, args: &[String]) -> Result<Response> {
+    let timeout = parse_timeout(&args[args.len() - 1])?;
+    let name = args[0].clone();
+    let shared = Arc::clone(&session.shared);
+    let db = session.db;
+
+    let waiter = {
+        let mut store = shared.write(db);
+        let reply = pop(&mut store, args)?;
+        serve(&shared.blocked, db, &mut store);
+        if let Some(reply) = reply {
+            return Ok(reply);
+        }
+
+        let waiter = Arc::new(Waiter { db, pop, args: args.to_vec(), reply: Mutex::new(None), served: Condvar::new() });
+        shared.blocked.lock().unwrap().register(&waiter);
+        waiter
+    };
+
+    // A deadline too far to represent is the same as none.
+    let deadline = timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+    loop {
+        let mut wait = POLL_INTERVAL;
+        if let Some(deadline) = deadline {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            wait = wait.min(deadline - now);
+        }
+
+        let reply = waiter.reply.lock().unwrap();
+        let (mut reply, _) = waiter.served.wait_timeout_while(reply, wait, |reply| reply.is_none()).unwrap();
+        if let Some(reply) = reply.take() {
+            return reply;
+        }
+        drop(reply);
+
+        if !session.is_connected() {
+            break;
+        }
+    }
+
+    // The waiter may have been served after the last check, until it's
+    // removed.
+    shared.blocked.lock().unwrap().remove(&waiter);
+    let reply = waiter.reply.lock().unwrap().take();
+    reply.unwrap_or_else(|| Ok(timeout_reply(&name)))
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use command::{lookup, test_args as args, test_session};
+    use session::Shared;
+    use std::thread;
+
+    fn run(shared: &Arc<Shared>, line: &str) -> Response {
+        let args = args(line);
+        lookup(&args[0]).unwrap().execute(&mut test_session(shared), &args)
+    }
+
+    /// Waits until `n` clients are blocked on `key`.
+    fn wait_for_waiters(shared: &Shared, key: &str, n: usize) {
+        let key = (0, key.to_string());
+        while shared.blocked.lock().unwrap().waiters.get(&key).map_or(0, |queue| queue.len()) < n {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn check_parse_timeout() {
+        assert_eq!(parse_timeout("0"), Ok(None));
+        assert_eq!(parse_timeout("0.5"), Ok(Some(Duration::from_millis(500))));
+        assert_eq!(parse_timeout("-1"), Err(CommandError::InvalidTimeout));
+        assert_eq!(parse_timeout("abc"), Err(CommandError::InvalidTimeout));
+        assert_eq!(parse_timeout("1e30"), Err(CommandError::InvalidTimeout));
+        assert_eq!(parse_timeout("inf"), Err(CommandError::InvalidTimeout));
+        assert_eq!(parse_timeout("-0"), Ok(None));
+    }
+
+    #[test]
+    fn pop_without_waiting() {
+        let shared = Arc::new(Shared::new(vec![Store::new()]));
+        run(&shared, "RPUSH jobs a");
+
+        let expected = Response::KeepAlive(RespWriter::to_array(&["jobs", "a"]));
+        assert_eq!(run(&shared, "BLPOP empty jobs 0"), expected);
+    }
+
+    #[test]
+    fn timeout() {
+        let shared = Arc::new(Shared::new(vec![Store::new()]));
+
+        let start = Instant::now();
+        assert_eq!(run(&shared, "BLPOP jobs 0.05"), timeout_reply("BLPOP"));
+        assert_eq!(run(&shared, "BLMOVE jobs done LEFT LEFT 0.05"), timeout_reply("BLMOVE"));
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert!(shared.blocked.lock().unwrap().waiters.is_empty());
+    }
+
+    #[test]
+    fn push_wakes_waiter() {
+        let shared = Arc::new(Shared::new(vec![Store::new()]));
+
+        let waiter = {
+            let shared = Arc::clone(&shared);
+            thread::spawn(move || run(&shared, "BRPOP jobs 5"))
+        };
+        wait_for_waiters(&shared, "jobs", 1);
+        run(&shared, "LPUSH jobs a b");
+
+        let expected = Response::KeepAlive(RespWriter::to_array(&["jobs", "a"]));
+        assert_eq!(waiter.join().unwrap(), expected);
+        assert_eq!(run(&shared, "LLEN jobs"), Response::KeepAlive(RespWriter::to_signed_integer(1)));
+    }
+
+    #[test]
+    fn waiters_are_served_in_order() {
+        let shared = Arc::new(Shared::new(vec![Store::new()]));
+
+        let mut waiters = vec![];
+        for i in 0..5 {
+            let shared_clone = Arc::clone(&shared);
+            waiters.push(thread::spawn(move || run(&shared_clone, "BLPOP jobs 5")));
+            wait_for_waiters(&shared, "jobs", i + 1);
+        }
+
+        run(&shared, "RPUSH jobs 0 1 2");
+        run(&shared, "RPUSH jobs 3");
+        run(&shared, "RPUSH jobs 4 5");
+
+        for (i, waiter) in waiters.into_iter().enumerate() {
+            let expected = Response::KeepAlive(RespWriter::to_array(&["jobs", &i.to_string()]));
+            assert_eq!(waiter.join().unwrap(), expected);
+        }
+        assert_eq!(run(&shared, "LLEN jobs"), Response::KeepAlive(RespWriter::to_signed_integer(1)));
+    }
+
+    #[test]
+    fn concurrent_pushes_and_waiters() {
+        let shared = Arc::new(Shared::new(vec![Store::new()]));
+        let (waiters, pushers) = (8, 4);
+
+        let handles: Vec<_> = (0..waiters).map(|_| {
+            let shared = Arc::clone(&shared);
+            thread::spawn(move || run(&shared, "BLPOP jobs other 5"))
+        }).collect();
+
+        for i in 0..pushers {
+            let shared = Arc::clone(&shared);
+            thread::spawn(move || {
+                for j in 0..waiters / pushers {
+                    run(&shared, &format!("RPUSH jobs {}-{}", i, j));
+                }
+            });
+        }
+
+        let mut elements: Vec<String> = handles.into_iter().map(|handle| match handle.join().unwrap() {
+            Response::KeepAlive(reply) => reply,
+            _ => panic!("Unexpected response"),
+        }).collect();
+        elements.sort();
+        elements.dedup();
+
+        // every element went to exactly one waiter
+        assert_eq!(elements.len(), waiters);
+        assert_eq!(run(&shared, "LLEN jobs"), Response::KeepAlive(RespWriter::to_signed_integer(0)));
+    }
+
+    #[test]
+    fn blmove_chain() {
+        let shared = Arc::new(Shared::new(vec![Store::new()]));
+
+        let mover = {
+            let shared = Arc::clone(&shared);
+            thread::spawn(move || run(&shared, "BLMOVE jobs processing RIGHT LEFT 5"))
+        };
+        wait_for_waiters(&shared, "jobs", 1);
+
+        let popper = {
+            let shared = Arc::clone(&shared);
+            thread::spawn(move || run(&shared, "BLPOP processing 5"))
+        };
+        wait_for_waiters(&shared, "processing", 1);
+
+        run(&shared, "RPUSH jobs a");
+
+        assert_eq!(mover.join().unwrap(), Response::KeepAlive(RespWriter::to_bulk_string("a")));
+        let expected = Response::KeepAlive(RespWriter::to_array(&["processing", "a"]));
+        assert_eq!(popper.join().unwrap(), expected);
+    }
+}
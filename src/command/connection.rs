@@ -0,0 +1,151 @@
+use std::sync::Arc;
+
+use clients::Client;
+use respwriter::RespWriter;
+use response::Response;
+use session::Session;
+
+use super::{CommandError, Result, parse_int};
+
+/// CLIENT ID
+/// CLIENT GETNAME
+/// CLIENT SETNAME name
+/// CLIENT LIST
+/// CLIENT KILL addr
+/// CLIENT KILL [ID id] [ADDR addr] [SKIPME yes/no]
+pub fn client(session: &mut Session, args: &[String]) -> Result<Response> {
+    match (args[1].to_uppercase().as_ref(), args.len()) {
+        ("ID", 2) => Ok(Response::KeepAlive(RespWriter::to_signed_integer(session.id as i64))),
+        ("GETNAME", 2) => {
+            let name = session.client.name.lock().unwrap();
+            let reply = if name.is_empty() {
+                RespWriter::null_bulk_string()
+            } else {
+                RespWriter::to_bulk_string(&name)
+            };
+            Ok(Response::KeepAlive(reply))
+        },
+        ("SETNAME", 3) => setname(session, &args[2]),
+        ("LIST", 2) => {
+            let list: String = session.shared.clients.all().iter()
+                .map(|client| client.describe() + "\n")
+                .collect();
+            Ok(Response::KeepAlive(RespWriter::to_bulk_string(&list)))
+        },
+        ("KILL", 3) => {
+            let addr = &args[2];
+            let clients = matching(session, |client| client.addr == *addr);
+            if clients.is_empty() {
+                return Err(CommandError::NoSuchClient);
+            }
+            clients.iter().for_each(|client| client.kill());
+            Ok(Response::build_ok())
+        },
+        ("KILL", n) if n >= 4 && n % 2 == 0 => kill(session, &args[2..]),
+        _ => Err(CommandError::Syntax),
+    }
+}
+
+/// Names the connection, or removes its name if empty. Names are shown by
+/// CLIENT LIST, which separates fields with spaces.
+fn setname(session: &mut Session, name: &str) -> Result<Response> {
+    if name.bytes().any(|b| !(b'!'..=b'~').contains(&b)) {
+        return Err(CommandError::InvalidClientName);
+    }
+    *session.client.name.lock().unwrap() = name.to_string();
+    Ok(Response::build_ok())
+}
+
+/// Kills the clients matching every filter, except the calling one unless
+/// SKIPME is no. Replies with the number of clients killed.
+fn kill(session: &mut Session, filters: &[String]) -> Result<Response> {
+    let mut id = None;
+    let mut addr = None;
+    let mut skipme = true;
+
+    for pair in filters.chunks(2) {
+        match pair[0].to_uppercase().as_ref() {
+            "ID" => id = Some(parse_int(&pair[1])? as u64),
+            "ADDR" => addr = Some(&pair[1]),
+            "SKIPME" => skipme = match pair[1].to_lowercase().as_ref() {
+                "yes" => true,
+                "no" => false,
+                _ => return Err(CommandError::Syntax),
+            },
+            _ => return Err(CommandError::Syntax),
+        }
+    }
+
+    let clients = matching(session, |client| {
+        id.is_none_or(|id| client.id == id)
+            && addr.is_none_or(|addr| client.addr == *addr)
+            && !(skipme && client.id == session.id)
+    });
+    clients.iter().for_each(|client| client.kill());
+    Ok(Response::KeepAlive(RespWriter::to_signed_integer(clients.len() as i64)))
+}
+
+fn matching<F: Fn(&Client) -> bool>(session: &Session, filter: F) -> Vec<Arc<Client>> {
+    session.shared.clients.all().into_iter().filter(|client| filter(client)).collect()
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use command::test_args as args;
+    use command::test_integer as integer;
+    use command::test_session;
+    use session::Shared;
+    use store::Store;
+
+    #[test]
+    fn id_and_name() {
+        let shared = Arc::new(Shared::new(vec![Store::new()]));
+        let mut session = test_session(&shared);
+
+        let id = session.id as i64;
+        assert_eq!(client(&mut session, &args("CLIENT ID")), integer(id));
+        assert_eq!(client(&mut session, &args("CLIENT GETNAME")), Ok(Response::KeepAlive(RespWriter::null_bulk_string())));
+
+        assert_eq!(client(&mut session, &args("CLIENT SETNAME worker")), Ok(Response::build_ok()));
+        assert_eq!(client(&mut session, &args("CLIENT GETNAME")), Ok(Response::KeepAlive(RespWriter::to_bulk_string("worker"))));
+
+        let name = vec!["CLIENT".to_string(), "SETNAME".to_string(), "a b".to_string()];
+        assert_eq!(client(&mut session, &name), Err(CommandError::InvalidClientName));
+    }
+
+    #[test]
+    fn list() {
+        let shared = Arc::new(Shared::new(vec![Store::new()]));
+        let mut session = test_session(&shared);
+        let other = test_session(&shared);
+        other.client.touch(0, "GET");
+
+        let expected = format!(
+            "id={} addr= name= age=0 idle=0 db=0 cmd=null\nid={} addr= name= age=0 idle=0 db=0 cmd=get\n",
+            session.id, other.id
+        );
+        assert_eq!(client(&mut session, &args("CLIENT LIST")), Ok(Response::KeepAlive(RespWriter::to_bulk_string(&expected))));
+
+        // dropping a session unregisters it
+        drop(other);
+        assert_eq!(shared.clients.all().len(), 1);
+    }
+
+    #[test]
+    fn kill_by_filters() {
+        let shared = Arc::new(Shared::new(vec![Store::new()]));
+        let mut session = test_session(&shared);
+        let other = test_session(&shared);
+        let id = session.id;
+
+        assert_eq!(client(&mut session, &args("CLIENT KILL 127.0.0.1:1")), Err(CommandError::NoSuchClient));
+        assert_eq!(client(&mut session, &args(&format!("CLIENT KILL ID {}", other.id))), integer(1));
+        assert_eq!(client(&mut session, &args(&format!("CLIENT KILL ID {}", id))), integer(0));
+        assert_eq!(client(&mut session, &args(&format!("CLIENT KILL ID {} SKIPME no", id))), integer(1));
+        assert_eq!(client(&mut session, &args("CLIENT KILL ADDR 127.0.0.1:1")), integer(0));
+        assert_eq!(client(&mut session, &args("CLIENT KILL SKIPME maybe")), Err(CommandError::Syntax));
+        assert_eq!(client(&mut session, &args("CLIENT KILL ID 1 ADDR")), Err(CommandError::Syntax));
+    }
+}
@@ -0,0 +1,138 @@
+use respwriter::RespWriter;
+use response::Response;
+use session::Session;
+use store::Store;
+
+use super::{CommandError, Result, parse_int};
+
+/// Parses a database index, checking it against the number of databases.
+fn parse_db(s: &str, databases: usize) -> Result<usize> {
+    match parse_int(s)? {
+        index if index >= 0 && (index as usize) < databases => Ok(index as usize),
+        _ => Err(CommandError::InvalidDbIndex),
+    }
+}
+
+/// SELECT index
+pub fn select(session: &mut Session, args: &[String]) -> Result<Response> {
+    session.db = parse_db(&args[1], session.shared.databases.len())?;
+    Ok(Response::build_ok())
+}
+
+/// MOVE key db
+///
+/// Moves a key from the selected database to another one. Replies with 1
+/// if it was moved, and 0 if it doesn't exist or already exists in the
+/// destination.
+pub fn move_key(stores: &mut [&mut Store], db: usize, args: &[String]) -> Result<Response> {
+    let destination = parse_db(&args[2], stores.len())?;
+    if destination == db {
+        return Err(CommandError::SameObject);
+    }
+
+    let key = &args[1];
+    if !stores[db].contains_key(key) || stores[destination].contains_key(key) {
+        return Ok(Response::KeepAlive(RespWriter::to_signed_integer(0)));
+    }
+
+    let data = stores[db].remove(key).unwrap();
+    stores[destination].insert(key.clone(), data);
+    Ok(Response::KeepAlive(RespWriter::to_signed_integer(1)))
+}
+
+/// SWAPDB index1 index2
+///
+/// Swaps the keys of two databases, so clients connected to one see the
+/// keys of the other.
+pub fn swapdb(stores: &mut [&mut Store], _db: usize, args: &[String]) -> Result<Response> {
+    let a = parse_db(&args[1], stores.len())?;
+    let b = parse_db(&args[2], stores.len())?;
+
+    if a != b {
+        let (low, high) = stores.split_at_mut(a.max(b));
+        low[a.min(b)].swap(high[0]);
+    }
+
+    Ok(Response::build_ok())
+}
+
+/// FLUSHALL
+///
+/// Removes the keys of every database.
+pub fn flushall(stores: &mut [&mut Store], _db: usize, _args: &[String]) -> Result<Response> {
+    for store in stores {
+        store.clear();
+    }
+    Ok(Response::build_ok())
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use command::test_args as args;
+    use command::test_integer as integer;
+    use command::test_session;
+    use session::Shared;
+    use std::sync::Arc;
+    use store::Data;
+
+    fn init_stores() -> Vec<Store> {
+        let mut stores = vec![Store::new(), Store::new(), Store::new()];
+        stores[0].insert("a".to_string(), Data::String(b"0".to_vec()));
+        stores[0].insert("b".to_string(), Data::String(b"0".to_vec()));
+        stores[1].insert("b".to_string(), Data::String(b"1".to_vec()));
+        stores
+    }
+
+    #[test]
+    fn select_command() {
+        let mut session = test_session(&Arc::new(Shared::new(init_stores())));
+
+        assert_eq!(select(&mut session, &args("SELECT 1")), Ok(Response::build_ok()));
+        assert_eq!(session.db, 1);
+        assert!(!session.store().read().unwrap().contains_key("a"));
+
+        assert_eq!(select(&mut session, &args("SELECT 3")), Err(CommandError::InvalidDbIndex));
+        assert_eq!(select(&mut session, &args("SELECT -1")), Err(CommandError::InvalidDbIndex));
+        assert_eq!(select(&mut session, &args("SELECT one")), Err(CommandError::NotInteger));
+        assert_eq!(session.db, 1);
+    }
+
+    #[test]
+    fn move_command() {
+        let mut stores = init_stores();
+        let mut stores: Vec<&mut Store> = stores.iter_mut().collect();
+
+        assert_eq!(move_key(&mut stores, 0, &args("MOVE a 2")), integer(1));
+        assert!(!stores[0].contains_key("a"));
+        assert!(stores[2].contains_key("a"));
+
+        assert_eq!(move_key(&mut stores, 0, &args("MOVE b 1")), integer(0));
+        assert_eq!(move_key(&mut stores, 0, &args("MOVE missing 1")), integer(0));
+        assert_eq!(move_key(&mut stores, 0, &args("MOVE b 0")), Err(CommandError::SameObject));
+        assert_eq!(move_key(&mut stores, 0, &args("MOVE b 3")), Err(CommandError::InvalidDbIndex));
+    }
+
+    #[test]
+    fn swapdb_command() {
+        let mut stores = init_stores();
+        let mut stores: Vec<&mut Store> = stores.iter_mut().collect();
+
+        assert_eq!(swapdb(&mut stores, 0, &args("SWAPDB 1 0")), Ok(Response::build_ok()));
+        assert_eq!(stores[0].len(), 1);
+        assert_eq!(stores[1].len(), 2);
+
+        assert_eq!(swapdb(&mut stores, 0, &args("SWAPDB 2 2")), Ok(Response::build_ok()));
+        assert_eq!(swapdb(&mut stores, 0, &args("SWAPDB 0 5")), Err(CommandError::InvalidDbIndex));
+    }
+
+    #[test]
+    fn flushall_command() {
+        let mut stores = init_stores();
+        let mut stores: Vec<&mut Store> = stores.iter_mut().collect();
+
+        assert_eq!(flushall(&mut stores, 0, &args("FLUSHALL")), Ok(Response::build_ok()));
+        assert!(stores.iter().all(|store| store.len() == 0));
+    }
+}
@@ -0,0 +1,264 @@
+use std::collections::HashMap;
+use std::str;
+
+use respwriter::RespWriter;
+use response::Response;
+use store::{Data, Store};
+
+use super::{CommandError, Result, parse_int, to_array, to_bulk_string};
+
+type Hash = HashMap<String, Vec<u8>>;
+
+fn get_hash<'a>(store: &'a Store, key: &str) -> Result<Option<&'a Hash>> {
+    match store.get(key) {
+        Some(Data::Hash(hash)) => Ok(Some(hash)),
+        Some(_) => Err(CommandError::WrongType),
+        None => Ok(None),
+    }
+}
+
+fn get_hash_mut<'a>(store: &'a mut Store, key: &str) -> Result<Option<&'a mut Hash>> {
+    match store.get_mut(key) {
+        Some(Data::Hash(hash)) => Ok(Some(hash)),
+        Some(_) => Err(CommandError::WrongType),
+        None => Ok(None),
+    }
+}
+
+fn hash_entry<'a>(store: &'a mut Store, key: &str) -> Result<&'a mut Hash> {
+    match store.get_or_insert_with(key, || Data::Hash(HashMap::new())) {
+        Data::Hash(hash) => Ok(hash),
+        _ => Err(CommandError::WrongType),
+    }
+}
+
+/// HSET key field value [field value ...]
+///
+/// Replies with the number of fields that were added, not counting the
+/// fields whose values were updated.
+pub fn set(store: &mut Store, args: &[String]) -> Result<Response> {
+    if !args.len().is_multiple_of(2) {
+        return Err(CommandError::WrongNumberOfArguments("HSET"));
+    }
+
+    let mut args = args.iter().skip(1);
+    let key = args.next().unwrap();
+    let hash = hash_entry(store, key)?;
+
+    let mut added = 0;
+    while let (Some(field), Some(value)) = (args.next(), args.next()) {
+        if hash.insert(field.clone(), value.as_bytes().to_vec()).is_none() {
+            added += 1;
+        }
+    }
+    store.touch(key);
+
+    Ok(Response::KeepAlive(RespWriter::to_signed_integer(added)))
+}
+
+/// HGET key field
+pub fn get(store: &Store, args: &[String]) -> Result<Response> {
+    let value = get_hash(store, &args[1])?.and_then(|hash| hash.get(&args[2]));
+
+    match value {
+        Some(value) => Ok(Response::KeepAlive(to_bulk_string(value))),
+        None => Ok(Response::KeepAlive(RespWriter::null_bulk_string())),
+    }
+}
+
+/// HMGET key field [field ...]
+pub fn mget(store: &Store, args: &[String]) -> Result<Response> {
+    let hash = get_hash(store, &args[1])?;
+
+    let values: Vec<String> = args[2..].iter()
+        .map(|field| match hash.and_then(|h| h.get(field)) {
+            Some(value) => to_bulk_string(value),
+            None => RespWriter::null_bulk_string(),
+        })
+        .collect();
+
+    Ok(Response::KeepAlive(RespWriter::to_raw_array(&values)))
+}
+
+/// HDEL key field [field ...]
+pub fn del(store: &mut Store, args: &[String]) -> Result<Response> {
+    let key = &args[1];
+
+    let removed = match get_hash_mut(store, key)? {
+        Some(hash) => args[2..].iter().filter(|field| hash.remove(*field).is_some()).count(),
+        None => 0,
+    };
+    if removed > 0 {
+        store.touch(key);
+        store.remove_if_empty(key);
+    }
+
+    Ok(Response::KeepAlive(RespWriter::to_signed_integer(removed as i64)))
+}
+
+/// HEXISTS key field
+pub fn exists(store: &Store, args: &[String]) -> Result<Response> {
+    let exists = get_hash(store, &args[1])?.is_some_and(|hash| hash.contains_key(&args[2]));
+    Ok(Response::KeepAlive(RespWriter::to_signed_integer(exists as i64)))
+}
+
+/// HLEN key
+pub fn len(store: &Store, args: &[String]) -> Result<Response> {
+    let len = get_hash(store, &args[1])?.map_or(0, |hash| hash.len());
+    Ok(Response::KeepAlive(RespWriter::to_signed_integer(len as i64)))
+}
+
+/// HKEYS key
+pub fn keys(store: &Store, args: &[String]) -> Result<Response> {
+    let fields: Vec<&str> = match get_hash(store, &args[1])? {
+        Some(hash) => hash.keys().map(|f| f.as_ref()).collect(),
+        None => vec![],
+    };
+
+    Ok(Response::KeepAlive(RespWriter::to_array(&fields)))
+}
+
+/// HVALS key
+pub fn vals(store: &Store, args: &[String]) -> Result<Response> {
+    match get_hash(store, &args[1])? {
+        Some(hash) => Ok(Response::KeepAlive(to_array(hash.values()))),
+        None => Ok(Response::KeepAlive(to_array(&[]))),
+    }
+}
+
+/// HGETALL key
+///
+/// Replies with a flat array of each field followed by its value.
+pub fn getall(store: &Store, args: &[String]) -> Result<Response> {
+    let mut elements = vec![];
+
+    if let Some(hash) = get_hash(store, &args[1])? {
+        for (field, value) in hash {
+            elements.push(RespWriter::to_bulk_string(field));
+            elements.push(to_bulk_string(value));
+        }
+    }
+
+    Ok(Response::KeepAlive(RespWriter::to_raw_array(&elements)))
+}
+
+/// HINCRBY key field increment
+pub fn incrby(store: &mut Store, args: &[String]) -> Result<Response> {
+    let increment = parse_int(&args[3])?;
+    let hash = hash_entry(store, &args[1])?;
+
+    let current = match hash.get(&args[2]) {
+        Some(value) => str::from_utf8(value).map_err(|_| CommandError::NotInteger)
+            .and_then(parse_int)?,
+        None => 0,
+    };
+
+    let value = current.checked_add(increment).ok_or(CommandError::Overflow)?;
+    hash.insert(args[2].clone(), value.to_string().into_bytes());
+    store.touch(&args[1]);
+
+    Ok(Response::KeepAlive(RespWriter::to_signed_integer(value)))
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use command::test_args as args;
+    use command::test_integer as integer;
+    use command::test_bulk as bulk;
+    use command::test_nil as nil;
+    use command::test_sorted_elements as sorted_elements;
+
+    fn init_hash() -> Store {
+        let mut store = Store::new();
+        set(&mut store, &args("HSET user name mikong lang rust")).unwrap();
+        store
+    }
+
+    #[test]
+    fn set_command() {
+        let mut store = init_hash();
+
+        assert_eq!(set(&mut store, &args("HSET user name mike age 30")), integer(1));
+        assert_eq!(get(&store, &args("HGET user name")), bulk("mike"));
+        assert_eq!(
+            set(&mut store, &args("HSET user name")),
+            Err(CommandError::WrongNumberOfArguments("HSET"))
+        );
+    }
+
+    #[test]
+    fn get_command() {
+        let store = init_hash();
+
+        assert_eq!(get(&store, &args("HGET user lang")), bulk("rust"));
+        assert_eq!(get(&store, &args("HGET user email")), nil());
+        assert_eq!(get(&store, &args("HGET missing name")), nil());
+    }
+
+    #[test]
+    fn mget_command() {
+        let store = init_hash();
+
+        let expected = RespWriter::to_raw_array(&[
+            RespWriter::to_bulk_string("rust"),
+            RespWriter::null_bulk_string(),
+        ]);
+        assert_eq!(mget(&store, &args("HMGET user lang email")), Ok(Response::KeepAlive(expected)));
+    }
+
+    #[test]
+    fn del_command() {
+        let mut store = init_hash();
+
+        assert_eq!(del(&mut store, &args("HDEL user name email")), integer(1));
+        assert_eq!(del(&mut store, &args("HDEL user lang")), integer(1));
+        assert!(!store.contains_key("user"));
+    }
+
+    #[test]
+    fn exists_and_len_commands() {
+        let store = init_hash();
+
+        assert_eq!(exists(&store, &args("HEXISTS user name")), integer(1));
+        assert_eq!(exists(&store, &args("HEXISTS user email")), integer(0));
+        assert_eq!(len(&store, &args("HLEN user")), integer(2));
+        assert_eq!(len(&store, &args("HLEN missing")), integer(0));
+    }
+
+    #[test]
+    fn keys_vals_getall_commands() {
+        let store = init_hash();
+
+        assert_eq!(sorted_elements(keys(&store, &args("HKEYS user"))), vec!["lang", "name"]);
+        assert_eq!(sorted_elements(vals(&store, &args("HVALS user"))), vec!["mikong", "rust"]);
+        assert_eq!(
+            sorted_elements(getall(&store, &args("HGETALL user"))),
+            vec!["lang", "mikong", "name", "rust"]
+        );
+        assert_eq!(getall(&store, &args("HGETALL missing")), Ok(Response::KeepAlive("*0\r\n".to_string())));
+    }
+
+    #[test]
+    fn incrby_command() {
+        let mut store = init_hash();
+
+        assert_eq!(incrby(&mut store, &args("HINCRBY user age 5")), integer(5));
+        assert_eq!(incrby(&mut store, &args("HINCRBY user age -7")), integer(-2));
+        assert_eq!(get(&store, &args("HGET user age")), bulk("-2"));
+        assert_eq!(incrby(&mut store, &args("HINCRBY user name 1")), Err(CommandError::NotInteger));
+
+        set(&mut store, &args(&format!("HSET user max {}", i64::MAX))).unwrap();
+        assert_eq!(incrby(&mut store, &args("HINCRBY user max 1")), Err(CommandError::Overflow));
+    }
+
+    #[test]
+    fn wrong_type() {
+        let mut store = Store::new();
+        store.insert("hello".to_string(), Data::String(b"world".to_vec()));
+
+        assert_eq!(set(&mut store, &args("HSET hello a b")), Err(CommandError::WrongType));
+        assert_eq!(get(&store, &args("HGET hello a")), Err(CommandError::WrongType));
+    }
+}
@@ -0,0 +1,126 @@
+use hyperloglog::HyperLogLog;
+use respwriter::RespWriter;
+use response::Response;
+use store::{Data, Store};
+
+use super::{CommandError, Result};
+
+fn get_hll<'a>(store: &'a Store, key: &str) -> Result<Option<&'a HyperLogLog>> {
+    match store.get(key) {
+        Some(Data::HyperLogLog(hll)) => Ok(Some(hll)),
+        Some(_) => Err(CommandError::WrongType),
+        None => Ok(None),
+    }
+}
+
+/// Merges the HyperLogLogs at `keys` into one, ignoring missing keys.
+fn union(store: &Store, keys: &[String]) -> Result<HyperLogLog> {
+    let mut result = HyperLogLog::new();
+    for key in keys {
+        if let Some(hll) = get_hll(store, key)? {
+            result.merge(hll);
+        }
+    }
+    Ok(result)
+}
+
+/// PFADD key [element ...]
+///
+/// Replies with 1 if the key was created or its estimate may have changed,
+/// and 0 otherwise.
+pub fn pfadd(store: &mut Store, args: &[String]) -> Result<Response> {
+    let created = !store.contains_key(&args[1]);
+
+    let hll = match store.get_or_insert_with(&args[1], || Data::HyperLogLog(HyperLogLog::new())) {
+        Data::HyperLogLog(hll) => hll,
+        _ => return Err(CommandError::WrongType),
+    };
+
+    let mut changed = created;
+    for element in &args[2..] {
+        changed |= hll.add(element.as_bytes());
+    }
+    if changed {
+        store.touch(&args[1]);
+    }
+
+    Ok(Response::KeepAlive(RespWriter::to_signed_integer(changed as i64)))
+}
+
+/// PFCOUNT key [key ...]
+///
+/// With multiple keys, replies with the estimated cardinality of their union.
+pub fn pfcount(store: &Store, args: &[String]) -> Result<Response> {
+    let count = if args.len() == 2 {
+        get_hll(store, &args[1])?.map_or(0, |hll| hll.count())
+    } else {
+        union(store, &args[1..])?.count()
+    };
+
+    Ok(Response::KeepAlive(RespWriter::to_signed_integer(count as i64)))
+}
+
+/// PFMERGE destkey [sourcekey ...]
+///
+/// Stores the union of the sources, including destkey if it exists.
+pub fn pfmerge(store: &mut Store, args: &[String]) -> Result<Response> {
+    let merged = union(store, &args[1..])?;
+    store.insert(args[1].clone(), Data::HyperLogLog(merged));
+    Ok(Response::build_ok())
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use command::test_args as args;
+    use command::test_integer as integer;
+
+    #[test]
+    fn pfadd_command() {
+        let mut store = Store::new();
+
+        assert_eq!(pfadd(&mut store, &args("PFADD visitors a b c")), integer(1));
+        assert_eq!(pfadd(&mut store, &args("PFADD visitors a b")), integer(0));
+        assert_eq!(pfadd(&mut store, &args("PFADD empty")), integer(1));
+        assert_eq!(pfadd(&mut store, &args("PFADD empty")), integer(0));
+    }
+
+    #[test]
+    fn pfcount_command() {
+        let mut store = Store::new();
+        pfadd(&mut store, &args("PFADD monday alice bob carol")).unwrap();
+        pfadd(&mut store, &args("PFADD tuesday bob dave")).unwrap();
+
+        assert_eq!(pfcount(&store, &args("PFCOUNT monday")), integer(3));
+        assert_eq!(pfcount(&store, &args("PFCOUNT monday tuesday")), integer(4));
+        assert_eq!(pfcount(&store, &args("PFCOUNT monday missing")), integer(3));
+        assert_eq!(pfcount(&store, &args("PFCOUNT missing")), integer(0));
+    }
+
+    #[test]
+    fn pfmerge_command() {
+        let mut store = Store::new();
+        pfadd(&mut store, &args("PFADD week alice")).unwrap();
+        pfadd(&mut store, &args("PFADD monday alice bob carol")).unwrap();
+        pfadd(&mut store, &args("PFADD tuesday bob dave")).unwrap();
+
+        assert_eq!(pfmerge(&mut store, &args("PFMERGE week monday tuesday")), Ok(Response::build_ok()));
+        assert_eq!(pfcount(&store, &args("PFCOUNT week")), integer(4));
+
+        assert_eq!(pfmerge(&mut store, &args("PFMERGE empty")), Ok(Response::build_ok()));
+        assert_eq!(pfcount(&store, &args("PFCOUNT empty")), integer(0));
+        assert!(store.contains_key("empty"));
+    }
+
+    #[test]
+    fn wrong_type() {
+        let mut store = Store::new();
+        store.insert("hello".to_string(), Data::String(b"world".to_vec()));
+        pfadd(&mut store, &args("PFADD hll a")).unwrap();
+
+        assert_eq!(pfadd(&mut store, &args("PFADD hello a")), Err(CommandError::WrongType));
+        assert_eq!(pfcount(&store, &args("PFCOUNT hll hello")), Err(CommandError::WrongType));
+        assert_eq!(pfmerge(&mut store, &args("PFMERGE hll hello")), Err(CommandError::WrongType));
+    }
+}
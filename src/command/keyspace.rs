@@ -0,0 +1,201 @@
+use respwriter::RespWriter;
+use response::Response;
+use store::{Data, Store};
+
+use super::{CommandError, Result, to_bulk_string};
+
+fn get_string<'a>(store: &'a Store, key: &str) -> Result<Option<&'a Vec<u8>>> {
+    match store.get(key) {
+        Some(Data::String(value)) => Ok(Some(value)),
+        Some(_) => Err(CommandError::WrongType),
+        None => Ok(None),
+    }
+}
+
+/// Serializes an optional value as a Bulk String or a Null Bulk String.
+fn to_optional_bulk_string(value: Option<Vec<u8>>) -> Response {
+    match value {
+        Some(value) => Response::KeepAlive(to_bulk_string(&value)),
+        None => Response::KeepAlive(RespWriter::null_bulk_string()),
+    }
+}
+
+/// SET key value [GET]
+///
+/// With GET, replies with the old value, or nil if the key didn't exist.
+pub fn set(store: &mut Store, args: &[String]) -> Result<Response> {
+    let get = match args.len() {
+        3 => false,
+        4 if args[3].eq_ignore_ascii_case("GET") => true,
+        _ => return Err(CommandError::Syntax),
+    };
+
+    // Without GET, any type is overwritten.
+    let old = if get { get_string(store, &args[1])?.cloned() } else { None };
+    store.insert(args[1].clone(), Data::String(args[2].as_bytes().to_vec()));
+
+    if get {
+        Ok(to_optional_bulk_string(old))
+    } else {
+        Ok(Response::build_ok())
+    }
+}
+
+pub fn get(store: &Store, args: &[String]) -> Result<Response> {
+    match store.get(&args[1]) {
+        Some(Data::String(value)) => Ok(Response::KeepAlive(to_bulk_string(value))),
+        Some(_) => Err(CommandError::WrongType),
+        None => Err(CommandError::KeyNotFound),
+    }
+}
+
+/// SETNX key value
+///
+/// Sets the key only if it doesn't exist. Replies with 1 if it was set.
+pub fn setnx(store: &mut Store, args: &[String]) -> Result<Response> {
+    if store.contains_key(&args[1]) {
+        return Ok(Response::KeepAlive(RespWriter::to_signed_integer(0)));
+    }
+
+    store.insert(args[1].clone(), Data::String(args[2].as_bytes().to_vec()));
+    Ok(Response::KeepAlive(RespWriter::to_signed_integer(1)))
+}
+
+/// GETSET key value
+///
+/// Sets the key and replies with the old value, or nil if it didn't exist.
+pub fn getset(store: &mut Store, args: &[String]) -> Result<Response> {
+    let old = get_string(store, &args[1])?.cloned();
+    store.insert(args[1].clone(), Data::String(args[2].as_bytes().to_vec()));
+    Ok(to_optional_bulk_string(old))
+}
+
+/// GETDEL key
+///
+/// Deletes the key and replies with its value, or nil if it didn't exist.
+pub fn getdel(store: &mut Store, args: &[String]) -> Result<Response> {
+    get_string(store, &args[1])?;
+    match store.remove(&args[1]) {
+        Some(Data::String(value)) => Ok(to_optional_bulk_string(Some(value))),
+        _ => Ok(to_optional_bulk_string(None)),
+    }
+}
+
+/// CAS key expected new
+///
+/// Sets the key to `new` only if its value is exactly `expected`. Replies
+/// with 1 if the value was swapped, and 0 otherwise, including when the key
+/// doesn't exist.
+pub fn cas(store: &mut Store, args: &[String]) -> Result<Response> {
+    let swapped = match get_string(store, &args[1])? {
+        Some(value) if value.as_slice() == args[2].as_bytes() => {
+            store.insert(args[1].clone(), Data::String(args[3].as_bytes().to_vec()));
+            true
+        },
+        _ => false,
+    };
+
+    Ok(Response::KeepAlive(RespWriter::to_signed_integer(swapped as i64)))
+}
+
+pub fn delete(store: &mut Store, args: &[String]) -> Result<Response> {
+    match store.remove(&args[1]) {
+        Some(_) => Ok(Response::build_ok()),
+        None => Err(CommandError::KeyNotFound),
+    }
+}
+
+pub fn exists(store: &Store, args: &[String]) -> Result<Response> {
+    let exists = store.contains_key(&args[1]) as i64;
+    Ok(Response::KeepAlive(RespWriter::to_signed_integer(exists)))
+}
+
+pub fn count(store: &Store, _args: &[String]) -> Result<Response> {
+    Ok(Response::KeepAlive(RespWriter::to_signed_integer(store.len() as i64)))
+}
+
+pub fn destroy(store: &mut Store, _args: &[String]) -> Result<Response> {
+    store.clear();
+    Ok(Response::build_ok())
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use command::test_args as args;
+    use command::test_integer as integer;
+    use command::test_bulk as bulk;
+    use command::test_nil as nil;
+
+    fn init_store() -> Store {
+        let mut store = Store::new();
+        store.insert("lock".to_string(), Data::String(b"owner1".to_vec()));
+        store
+    }
+
+    #[test]
+    fn set_with_get() {
+        let mut store = init_store();
+
+        assert_eq!(set(&mut store, &args("SET lock owner2 GET")), bulk("owner1"));
+        assert_eq!(set(&mut store, &args("SET other value get")), nil());
+        assert_eq!(set(&mut store, &args("SET lock owner3 NX")), Err(CommandError::Syntax));
+        assert_eq!(get(&store, &args("GET lock")), bulk("owner2"));
+    }
+
+    #[test]
+    fn setnx_command() {
+        let mut store = init_store();
+
+        assert_eq!(setnx(&mut store, &args("SETNX lock owner2")), integer(0));
+        assert_eq!(setnx(&mut store, &args("SETNX other value")), integer(1));
+        assert_eq!(get(&store, &args("GET lock")), bulk("owner1"));
+        assert_eq!(get(&store, &args("GET other")), bulk("value"));
+    }
+
+    #[test]
+    fn getset_command() {
+        let mut store = init_store();
+
+        assert_eq!(getset(&mut store, &args("GETSET lock owner2")), bulk("owner1"));
+        assert_eq!(getset(&mut store, &args("GETSET other value")), nil());
+        assert_eq!(get(&store, &args("GET lock")), bulk("owner2"));
+    }
+
+    #[test]
+    fn getdel_command() {
+        let mut store = init_store();
+
+        assert_eq!(getdel(&mut store, &args("GETDEL lock")), bulk("owner1"));
+        assert_eq!(getdel(&mut store, &args("GETDEL lock")), nil());
+        assert!(!store.contains_key("lock"));
+    }
+
+    #[test]
+    fn cas_command() {
+        let mut store = init_store();
+
+        assert_eq!(cas(&mut store, &args("CAS lock owner2 owner3")), integer(0));
+        assert_eq!(cas(&mut store, &args("CAS lock owner1 owner2")), integer(1));
+        assert_eq!(get(&store, &args("GET lock")), bulk("owner2"));
+        assert_eq!(cas(&mut store, &args("CAS missing a b")), integer(0));
+        assert!(!store.contains_key("missing"));
+    }
+
+    #[test]
+    fn wrong_type() {
+        let mut store = Store::new();
+        store.insert("list".to_string(), Data::List(Default::default()));
+
+        assert_eq!(set(&mut store, &args("SET list a GET")), Err(CommandError::WrongType));
+        assert_eq!(getset(&mut store, &args("GETSET list a")), Err(CommandError::WrongType));
+        assert_eq!(getdel(&mut store, &args("GETDEL list")), Err(CommandError::WrongType));
+        assert_eq!(cas(&mut store, &args("CAS list a b")), Err(CommandError::WrongType));
+        assert!(store.contains_key("list"));
+
+        // a plain SET overwrites any type
+        assert_eq!(set(&mut store, &args("SET list a")), Ok(Response::build_ok()));
+        assert_eq!(get(&store, &args("GET list")), Ok(Response::KeepAlive(to_bulk_string(b"a"))));
+    }
+}
@@ -0,0 +1,419 @@
+use std::collections::VecDeque;
+
+use respwriter::RespWriter;
+use response::Response;
+use store::{Data, Store};
+
+use super::{CommandError, Result, normalize_range, parse_int, to_array, to_bulk_string};
+
+type List = VecDeque<Vec<u8>>;
+
+fn get_list<'a>(store: &'a Store, key: &str) -> Result<Option<&'a List>> {
+    match store.get(key) {
+        Some(Data::List(list)) => Ok(Some(list)),
+        Some(_) => Err(CommandError::WrongType),
+        None => Ok(None),
+    }
+}
+
+fn get_list_mut<'a>(store: &'a mut Store, key: &str) -> Result<Option<&'a mut List>> {
+    match store.get_mut(key) {
+        Some(Data::List(list)) => Ok(Some(list)),
+        Some(_) => Err(CommandError::WrongType),
+        None => Ok(None),
+    }
+}
+
+/// Converts an index, which may be negative to count from the end, into a
+/// position in a list of `len` elements.
+fn list_index(index: i64, len: usize) -> Option<usize> {
+    let index = if index < 0 { len as i64 + index } else { index };
+    if index < 0 || index >= len as i64 {
+        None
+    } else {
+        Some(index as usize)
+    }
+}
+
+/// LPUSH key element [element ...]
+/// RPUSH key element [element ...]
+pub fn push(store: &mut Store, args: &[String]) -> Result<Response> {
+    let mut args = args.iter();
+    let command = args.next().unwrap();
+    let key = args.next().unwrap();
+
+    let list = match store.get_or_insert_with(key, || Data::List(VecDeque::new())) {
+        Data::List(list) => list,
+        _ => return Err(CommandError::WrongType),
+    };
+
+    for element in args {
+        if command == "LPUSH" {
+            list.push_front(element.as_bytes().to_vec());
+        } else {
+            list.push_back(element.as_bytes().to_vec());
+        }
+    }
+    let len = list.len();
+    store.touch(key);
+
+    Ok(Response::KeepAlive(RespWriter::to_signed_integer(len as i64)))
+}
+
+/// LPOP key
+/// RPOP key
+pub fn pop(store: &mut Store, args: &[String]) -> Result<Response> {
+    let key = &args[1];
+
+    let element = match get_list_mut(store, key)? {
+        Some(list) if args[0] == "LPOP" => list.pop_front(),
+        Some(list) => list.pop_back(),
+        None => None,
+    };
+    if element.is_some() {
+        store.touch(key);
+        store.remove_if_empty(key);
+    }
+
+    match element {
+        Some(element) => Ok(Response::KeepAlive(to_bulk_string(&element))),
+        None => Ok(Response::KeepAlive(RespWriter::null_bulk_string())),
+    }
+}
+
+/// LRANGE key start stop
+pub fn range(store: &Store, args: &[String]) -> Result<Response> {
+    let start = parse_int(&args[2])?;
+    let stop = parse_int(&args[3])?;
+
+    let list = match get_list(store, &args[1])? {
+        Some(list) => list,
+        None => return Ok(Response::KeepAlive(to_array(&[]))),
+    };
+
+    match normalize_range(start, stop, list.len()) {
+        Some((start, stop)) => Ok(Response::KeepAlive(to_array(list.range(start..=stop)))),
+        None => Ok(Response::KeepAlive(to_array(&[]))),
+    }
+}
+
+/// LLEN key
+pub fn len(store: &Store, args: &[String]) -> Result<Response> {
+    let len = get_list(store, &args[1])?.map_or(0, |list| list.len());
+    Ok(Response::KeepAlive(RespWriter::to_signed_integer(len as i64)))
+}
+
+/// LINDEX key index
+pub fn index(store: &Store, args: &[String]) -> Result<Response> {
+    let index = parse_int(&args[2])?;
+
+    let element = get_list(store, &args[1])?
+        .and_then(|list| list_index(index, list.len()).map(|i| &list[i]));
+
+    match element {
+        Some(element) => Ok(Response::KeepAlive(to_bulk_string(element))),
+        None => Ok(Response::KeepAlive(RespWriter::null_bulk_string())),
+    }
+}
+
+/// LSET key index element
+pub fn set(store: &mut Store, args: &[String]) -> Result<Response> {
+    let index = parse_int(&args[2])?;
+
+    let list = get_list_mut(store, &args[1])?.ok_or(CommandError::KeyNotFound)?;
+    let i = list_index(index, list.len()).ok_or(CommandError::IndexOutOfRange)?;
+    list[i] = args[3].as_bytes().to_vec();
+    store.touch(&args[1]);
+
+    Ok(Response::build_ok())
+}
+
+/// LREM key count element
+///
+/// Removes the first `count` occurrences of the element from the head if
+/// `count` is positive, from the tail if negative, or all of them if zero.
+pub fn rem(store: &mut Store, args: &[String]) -> Result<Response> {
+    let key = &args[1];
+    let count = parse_int(&args[2])?;
+    let element = args[3].as_bytes();
+
+    let limit = if count == 0 { usize::MAX } else { count.unsigned_abs() as usize };
+    let mut removed = 0;
+
+    if let Some(list) = get_list_mut(store, key)? {
+        if count < 0 {
+            let mut i = list.len();
+            while i > 0 && removed < limit {
+                i -= 1;
+                if list[i] == element {
+                    list.remove(i);
+                    removed += 1;
+                }
+            }
+        } else {
+            let mut i = 0;
+            while i < list.len() && removed < limit {
+                if list[i] == element {
+                    list.remove(i);
+                    removed += 1;
+                } else {
+                    i += 1;
+                }
+            }
+        }
+    }
+    if removed > 0 {
+        store.touch(key);
+        store.remove_if_empty(key);
+    }
+
+    Ok(Response::KeepAlive(RespWriter::to_signed_integer(removed as i64)))
+}
+
+/// LTRIM key start stop
+pub fn trim(store: &mut Store, args: &[String]) -> Result<Response> {
+    let key = &args[1];
+    let start = parse_int(&args[2])?;
+    let stop = parse_int(&args[3])?;
+
+    if let Some(list) = get_list_mut(store, key)? {
+        let len = list.len();
+        match normalize_range(start, stop, len) {
+            Some((start, stop)) => {
+                list.truncate(stop + 1);
+                list.drain(..start);
+            },
+            None => list.clear(),
+        }
+        if list.len() < len {
+            store.touch(key);
+            store.remove_if_empty(key);
+        }
+    }
+
+    Ok(Response::build_ok())
+}
+
+/// Parses the LEFT or RIGHT argument of LMOVE. Returns true for LEFT.
+fn parse_side(s: &str) -> Result<bool> {
+    match s.to_ascii_uppercase().as_ref() {
+        "LEFT" => Ok(true),
+        "RIGHT" => Ok(false),
+        _ => Err(CommandError::Syntax),
+    }
+}
+
+/// LMOVE source destination LEFT|RIGHT LEFT|RIGHT
+///
+/// Pops an element from one end of the source and pushes it to one end of
+/// the destination, replying with the element, or nil if the source is empty.
+pub fn lmove(store: &mut Store, args: &[String]) -> Result<Response> {
+    let element = try_move(store, args)?;
+    Ok(element.unwrap_or_else(|| Response::KeepAlive(RespWriter::null_bulk_string())))
+}
+
+/// The non-blocking part of LMOVE and BLMOVE.
+pub fn try_move(store: &mut Store, args: &[String]) -> Result<Option<Response>> {
+    let (source, destination) = (&args[1], &args[2]);
+    let from_left = parse_side(&args[3])?;
+    let to_left = parse_side(&args[4])?;
+
+    get_list(store, destination)?;
+    let element = match get_list_mut(store, source)? {
+        Some(list) if from_left => list.pop_front(),
+        Some(list) => list.pop_back(),
+        None => None,
+    };
+    let element = match element {
+        Some(element) => element,
+        None => return Ok(None),
+    };
+    store.touch(source);
+    store.remove_if_empty(source);
+
+    let reply = to_bulk_string(&element);
+    match store.get_or_insert_with(destination, || Data::List(VecDeque::new())) {
+        Data::List(list) if to_left => list.push_front(element),
+        Data::List(list) => list.push_back(element),
+        _ => return Err(CommandError::WrongType),
+    }
+    store.touch(destination);
+
+    Ok(Some(Response::KeepAlive(reply)))
+}
+
+/// The non-blocking part of BLPOP and BRPOP: pops from the first non-empty
+/// list, replying with its key and the element.
+pub fn try_pop(store: &mut Store, args: &[String]) -> Result<Option<Response>> {
+    for key in &args[1..args.len() - 1] {
+        let element = match get_list_mut(store, key)? {
+            Some(list) if args[0] == "BLPOP" => list.pop_front(),
+            Some(list) => list.pop_back(),
+            None => None,
+        };
+
+        if let Some(element) = element {
+            store.touch(key);
+            store.remove_if_empty(key);
+            let reply = RespWriter::to_raw_array(&[RespWriter::to_bulk_string(key), to_bulk_string(&element)]);
+            return Ok(Some(Response::KeepAlive(reply)));
+        }
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use command::test_args as args;
+    use command::test_integer as integer;
+    use command::test_bulk as bulk;
+    use command::test_nil as nil;
+    use command::test_array as array;
+
+    fn init_list() -> Store {
+        let mut store = Store::new();
+        push(&mut store, &args("RPUSH list a b c a b")).unwrap();
+        store
+    }
+
+    #[test]
+    fn push_command() {
+        let mut store = Store::new();
+
+        assert_eq!(push(&mut store, &args("RPUSH list b c")), integer(2));
+        assert_eq!(push(&mut store, &args("LPUSH list a")), integer(3));
+        assert_eq!(range(&store, &args("LRANGE list 0 -1")), array(&["a", "b", "c"]));
+
+        // elements pushed to the head are inserted one after the other
+        assert_eq!(push(&mut store, &args("LPUSH other x y")), integer(2));
+        assert_eq!(range(&store, &args("LRANGE other 0 -1")), array(&["y", "x"]));
+    }
+
+    #[test]
+    fn pop_command() {
+        let mut store = init_list();
+
+        assert_eq!(pop(&mut store, &args("LPOP list")), bulk("a"));
+        assert_eq!(pop(&mut store, &args("RPOP list")), bulk("b"));
+        assert_eq!(pop(&mut store, &args("LPOP missing")), nil());
+    }
+
+    #[test]
+    fn pop_removes_empty_list() {
+        let mut store = Store::new();
+        push(&mut store, &args("RPUSH list a")).unwrap();
+
+        assert_eq!(pop(&mut store, &args("RPOP list")), bulk("a"));
+        assert!(!store.contains_key("list"));
+        assert_eq!(pop(&mut store, &args("RPOP list")), nil());
+    }
+
+    #[test]
+    fn range_command() {
+        let store = init_list();
+
+        assert_eq!(range(&store, &args("LRANGE list 0 1")), array(&["a", "b"]));
+        assert_eq!(range(&store, &args("LRANGE list -2 -1")), array(&["a", "b"]));
+        assert_eq!(range(&store, &args("LRANGE list 3 100")), array(&["a", "b"]));
+        assert_eq!(range(&store, &args("LRANGE list 3 1")), array(&[]));
+        assert_eq!(range(&store, &args("LRANGE missing 0 -1")), array(&[]));
+        assert_eq!(range(&store, &args("LRANGE list a 1")), Err(CommandError::NotInteger));
+    }
+
+    #[test]
+    fn len_command() {
+        let store = init_list();
+
+        assert_eq!(len(&store, &args("LLEN list")), integer(5));
+        assert_eq!(len(&store, &args("LLEN missing")), integer(0));
+    }
+
+    #[test]
+    fn index_command() {
+        let store = init_list();
+
+        assert_eq!(index(&store, &args("LINDEX list 1")), bulk("b"));
+        assert_eq!(index(&store, &args("LINDEX list -1")), bulk("b"));
+        assert_eq!(index(&store, &args("LINDEX list -3")), bulk("c"));
+        assert_eq!(index(&store, &args("LINDEX list 5")), nil());
+        assert_eq!(index(&store, &args("LINDEX missing 0")), nil());
+    }
+
+    #[test]
+    fn set_command() {
+        let mut store = init_list();
+
+        assert_eq!(set(&mut store, &args("LSET list -1 z")), Ok(Response::build_ok()));
+        assert_eq!(index(&store, &args("LINDEX list 4")), bulk("z"));
+        assert_eq!(set(&mut store, &args("LSET list 5 z")), Err(CommandError::IndexOutOfRange));
+        assert_eq!(set(&mut store, &args("LSET missing 0 z")), Err(CommandError::KeyNotFound));
+    }
+
+    #[test]
+    fn rem_command() {
+        let mut store = init_list();
+        assert_eq!(rem(&mut store, &args("LREM list 1 a")), integer(1));
+        assert_eq!(range(&store, &args("LRANGE list 0 -1")), array(&["b", "c", "a", "b"]));
+
+        let mut store = init_list();
+        assert_eq!(rem(&mut store, &args("LREM list -1 b")), integer(1));
+        assert_eq!(range(&store, &args("LRANGE list 0 -1")), array(&["a", "b", "c", "a"]));
+
+        let mut store = init_list();
+        assert_eq!(rem(&mut store, &args("LREM list 0 a")), integer(2));
+        assert_eq!(range(&store, &args("LRANGE list 0 -1")), array(&["b", "c", "b"]));
+
+        assert_eq!(rem(&mut store, &args("LREM missing 0 a")), integer(0));
+    }
+
+    #[test]
+    fn trim_command() {
+        let mut store = init_list();
+        assert_eq!(trim(&mut store, &args("LTRIM list 1 -2")), Ok(Response::build_ok()));
+        assert_eq!(range(&store, &args("LRANGE list 0 -1")), array(&["b", "c", "a"]));
+
+        assert_eq!(trim(&mut store, &args("LTRIM list 2 1")), Ok(Response::build_ok()));
+        assert!(!store.contains_key("list"));
+    }
+
+    #[test]
+    fn lmove_command() {
+        let mut store = init_list();
+
+        assert_eq!(lmove(&mut store, &args("LMOVE list other LEFT RIGHT")), bulk("a"));
+        assert_eq!(lmove(&mut store, &args("LMOVE list other right left")), bulk("b"));
+        assert_eq!(range(&store, &args("LRANGE other 0 -1")), array(&["b", "a"]));
+        assert_eq!(lmove(&mut store, &args("LMOVE list list RIGHT LEFT")), bulk("a"));
+        assert_eq!(range(&store, &args("LRANGE list 0 -1")), array(&["a", "b", "c"]));
+
+        assert_eq!(lmove(&mut store, &args("LMOVE missing other LEFT LEFT")), nil());
+        assert_eq!(lmove(&mut store, &args("LMOVE list other UP LEFT")), Err(CommandError::Syntax));
+    }
+
+    #[test]
+    fn try_pop_from_first_non_empty_list() {
+        let mut store = init_list();
+
+        let reply = RespWriter::to_array(&["list", "b"]);
+        assert_eq!(try_pop(&mut store, &args("BRPOP missing list 0")), Ok(Some(Response::KeepAlive(reply))));
+        assert_eq!(try_pop(&mut store, &args("BLPOP missing 0")), Ok(None));
+    }
+
+    #[test]
+    fn wrong_type() {
+        let mut store = Store::new();
+        store.insert("hello".to_string(), Data::String(b"world".to_vec()));
+
+        assert_eq!(push(&mut store, &args("LPUSH hello a")), Err(CommandError::WrongType));
+        assert_eq!(pop(&mut store, &args("LPOP hello")), Err(CommandError::WrongType));
+        assert_eq!(len(&store, &args("LLEN hello")), Err(CommandError::WrongType));
+        assert_eq!(range(&store, &args("LRANGE hello 0 -1")), Err(CommandError::WrongType));
+
+        push(&mut store, &args("RPUSH list a")).unwrap();
+        assert_eq!(lmove(&mut store, &args("LMOVE list hello LEFT LEFT")), Err(CommandError::WrongType));
+        assert_eq!(len(&store, &args("LLEN list")), integer(1));
+    }
+}
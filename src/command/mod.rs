@@ -0,0 +1,610 @@
+//! The command table and the implementations of the commands that operate on
+//! the store.
+//!
+//! Each command declares its arity and whether it writes to the store, so
+//! the server knows which lock to take before calling its handler. Commands
+//! that work on the connection rather than the store get the session.
+
+mod acl;
+mod auth;
+mod bitmap;
+mod blocking;
+mod connection;
+mod database;
+mod hash;
+mod hyperloglog;
+mod keyspace;
+mod list;
+mod pubsub;
+mod server;
+mod set;
+mod stream;
+mod transaction;
+mod zset;
+
+pub use self::blocking::Blocked;
+
+use std::fmt;
+use std::result;
+
+use respwriter::RespWriter;
+use response::Response;
+use session::{Session, Shared};
+use store::Store;
+
+#[derive(Debug, PartialEq)]
+pub enum CommandError {
+    /// The key holds a value of a different type than the command expects.
+    WrongType,
+
+    /// The key doesn't exist.
+    KeyNotFound,
+
+    /// An argument was expected to be an integer.
+    NotInteger,
+
+    /// An argument was expected to be a floating point number.
+    NotFloat,
+
+    /// An index argument points outside of the value.
+    IndexOutOfRange,
+
+    /// An operation would overflow the integer value.
+    Overflow,
+
+    /// A bit offset is negative or beyond the maximum size of a string.
+    InvalidBitOffset,
+
+    /// A bit value is neither 0 nor 1.
+    InvalidBit,
+
+    /// The arguments don't follow the syntax of the command.
+    Syntax,
+
+    /// The command was called with an invalid number of arguments.
+    WrongNumberOfArguments(&'static str),
+
+    /// A stream ID argument isn't of the form `ms-seq`.
+    InvalidStreamId,
+
+    /// The ID given to XADD isn't greater than the last ID of the stream.
+    StreamIdTooSmall,
+
+    /// The stream or its consumer group doesn't exist.
+    NoGroup,
+
+    /// The consumer group being created already exists.
+    BusyGroup,
+
+    /// The command can't run while the connection is subscribed to channels.
+    SubscribeMode(&'static str),
+
+    /// MULTI was called inside a transaction.
+    NestedMulti,
+
+    /// EXEC or DISCARD was called outside of a transaction.
+    WithoutMulti(&'static str),
+
+    /// A command failed to queue, so the transaction was discarded.
+    ExecAbort,
+
+    /// WATCH was called inside a transaction.
+    WatchInMulti,
+
+    /// The timeout of a blocking command is negative or not a number.
+    InvalidTimeout,
+
+    /// A database index is out of range.
+    InvalidDbIndex,
+
+    /// The source and destination of a command are the same.
+    SameObject,
+
+    /// The client must authenticate before running commands.
+    NoAuth,
+
+    /// The user or password sent with AUTH is wrong, or the user is disabled.
+    WrongPass,
+
+    /// AUTH was called without a username but the default user has no
+    /// password.
+    NoPassword,
+
+    /// The user isn't allowed to run the command.
+    CommandNotAllowed(String, &'static str),
+
+    /// The user isn't allowed to access a key of the command.
+    KeyNotAllowed,
+
+    /// A rule of ACL SETUSER is invalid, for the given reason.
+    InvalidAclRule(String, &'static str),
+
+    /// ACL DELUSER was called with the default user.
+    DeleteDefaultUser,
+
+    /// ACL LOAD was called but the server wasn't started with an ACL file.
+    NoAclFile,
+
+    /// The ACL file couldn't be loaded.
+    AclLoad(String),
+
+    /// CONFIG SET or CONFIG REWRITE failed.
+    Config(String),
+
+    /// The command needs memory but the databases use more than maxmemory.
+    OutOfMemory,
+
+    /// CLIENT KILL found no client with the address.
+    NoSuchClient,
+
+    /// CLIENT SETNAME was given a name with spaces or special characters.
+    InvalidClientName,
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandError::WrongType =>
+                write!(f, "WRONGTYPE Operation against a key holding the wrong kind of value"),
+            CommandError::KeyNotFound => write!(f, "ERROR: Key not found"),
+            CommandError::NotInteger => write!(f, "ERROR: Value is not an integer or out of range"),
+            CommandError::NotFloat => write!(f, "ERROR: Value is not a valid float"),
+            CommandError::IndexOutOfRange => write!(f, "ERROR: Index out of range"),
+            CommandError::Overflow => write!(f, "ERROR: Increment or decrement would overflow"),
+            CommandError::InvalidBitOffset => write!(f, "ERROR: Bit offset is not an integer or out of range"),
+            CommandError::InvalidBit => write!(f, "ERROR: Bit is not an integer or out of range"),
+            CommandError::Syntax => write!(f, "ERROR: Syntax error"),
+            CommandError::WrongNumberOfArguments(name) =>
+                write!(f, "ERROR: Wrong number of arguments for '{}' command", name),
+            CommandError::InvalidStreamId =>
+                write!(f, "ERROR: Invalid stream ID specified as stream command argument"),
+            CommandError::StreamIdTooSmall =>
+                write!(f, "ERROR: The ID specified in XADD is equal or smaller than the target stream top item"),
+            CommandError::NoGroup => write!(f, "NOGROUP No such key or consumer group"),
+            CommandError::BusyGroup => write!(f, "BUSYGROUP Consumer Group name already exists"),
+            CommandError::SubscribeMode(name) => write!(
+                f,
+                "ERROR: Can't execute '{}': only (P)SUBSCRIBE / (P)UNSUBSCRIBE / PING / QUIT are allowed in this context",
+                name
+            ),
+            CommandError::NestedMulti => write!(f, "ERROR: MULTI calls can not be nested"),
+            CommandError::WithoutMulti(name) => write!(f, "ERROR: {} without MULTI", name),
+            CommandError::ExecAbort => write!(f, "EXECABORT Transaction discarded because of previous errors"),
+            CommandError::WatchInMulti => write!(f, "ERROR: WATCH inside MULTI is not allowed"),
+            CommandError::InvalidTimeout => write!(f, "ERROR: Timeout is negative or not a number"),
+            CommandError::InvalidDbIndex => write!(f, "ERROR: DB index is out of range"),
+            CommandError::SameObject => write!(f, "ERROR: Source and destination objects are the same"),
+            CommandError::NoAuth => write!(f, "NOAUTH Authentication required"),
+            CommandError::WrongPass => write!(f, "WRONGPASS Invalid username-password pair or user is disabled"),
+            CommandError::NoPassword =>
+                write!(f, "ERROR: AUTH <password> called without any password configured for the default user"),
+            CommandError::CommandNotAllowed(user, name) =>
+                write!(f, "NOPERM User {} has no permissions to run the '{}' command", user, name),
+            CommandError::KeyNotAllowed => write!(f, "NOPERM No permissions to access a key"),
+            CommandError::InvalidAclRule(rule, reason) =>
+                write!(f, "ERROR: Error in ACL SETUSER modifier '{}': {}", rule, reason),
+            CommandError::DeleteDefaultUser => write!(f, "ERROR: The 'default' user cannot be removed"),
+            CommandError::NoAclFile => write!(f, "ERROR: This instance is not configured to use an ACL file"),
+            CommandError::AclLoad(e) => write!(f, "ERROR: {}", e),
+            CommandError::Config(e) => write!(f, "ERROR: {}", e),
+            CommandError::OutOfMemory => write!(f, "OOM command not allowed when used memory > 'maxmemory'"),
+            CommandError::NoSuchClient => write!(f, "ERROR: No such client"),
+            CommandError::InvalidClientName =>
+                write!(f, "ERROR: Client names cannot contain spaces, newlines or special characters"),
+        }
+    }
+}
+
+pub type Result<T> = result::Result<T, CommandError>;
+
+type ReadFn = fn(&Store, &[String]) -> Result<Response>;
+type WriteFn = fn(&mut Store, &[String]) -> Result<Response>;
+type SessionFn = fn(&mut Session, &[String]) -> Result<Response>;
+type BlockingFn = fn(&mut Store, &[String]) -> Result<Option<Response>>;
+type DatabasesFn = fn(&mut [&mut Store], usize, &[String]) -> Result<Response>;
+type ServerFn = fn(&Shared, &[&Store], &[String]) -> Result<Response>;
+
+/// How a command accesses the store.
+pub enum Handler {
+    /// The command reads the selected database.
+    Read(ReadFn),
+
+    /// The command writes to the selected database.
+    Write(WriteFn),
+
+    /// The command writes to any database. It gets all of them and the index
+    /// of the selected one.
+    Databases(DatabasesFn),
+
+    /// The command takes any locks it needs itself. Inside EXEC it runs while
+    /// the databases are locked, so it must not lock them.
+    Session(SessionFn),
+
+    /// The command waits until its function returns a reply, which is retried
+    /// whenever another client writes to the keys it waits on.
+    Blocking(BlockingFn),
+
+    /// The command reports on the server. It gets the shared state and all
+    /// the databases, read-only.
+    Server(ServerFn),
+}
+
+pub struct Command {
+    pub name: &'static str,
+
+    /// The number of arguments including the command name. A negative arity
+    /// means at least that many arguments, e.g. -3 for `LPUSH key element...`.
+    pub arity: i32,
+
+    pub handler: Handler,
+}
+
+static COMMANDS: &[Command] = &[
+    Command { name: "SET", arity: -3, handler: Handler::Write(keyspace::set) },
+    Command { name: "GET", arity: 2, handler: Handler::Read(keyspace::get) },
+    Command { name: "SETNX", arity: 3, handler: Handler::Write(keyspace::setnx) },
+    Command { name: "GETSET", arity: 3, handler: Handler::Write(keyspace::getset) },
+    Command { name: "GETDEL", arity: 2, handler: Handler::Write(keyspace::getdel) },
+    Command { name: "CAS", arity: 4, handler: Handler::Write(keyspace::cas) },
+    Command { name: "DELETE", arity: 2, handler: Handler::Write(keyspace::delete) },
+    Command { name: "EXISTS", arity: 2, handler: Handler::Read(keyspace::exists) },
+    Command { name: "COUNT", arity: 1, handler: Handler::Read(keyspace::count) },
+    Command { name: "DESTROY", arity: 1, handler: Handler::Write(keyspace::destroy) },
+    Command { name: "FLUSHDB", arity: 1, handler: Handler::Write(keyspace::destroy) },
+    Command { name: "FLUSHALL", arity: 1, handler: Handler::Databases(database::flushall) },
+    Command { name: "SELECT", arity: 2, handler: Handler::Session(database::select) },
+    Command { name: "MOVE", arity: 3, handler: Handler::Databases(database::move_key) },
+    Command { name: "SWAPDB", arity: 3, handler: Handler::Databases(database::swapdb) },
+    Command { name: "PING", arity: -1, handler: Handler::Session(pubsub::ping) },
+    Command { name: "AUTH", arity: -2, handler: Handler::Session(auth::auth) },
+    Command { name: "ACL", arity: -2, handler: Handler::Session(acl::acl) },
+    Command { name: "CONFIG", arity: -2, handler: Handler::Session(server::config) },
+    Command { name: "INFO", arity: -1, handler: Handler::Server(server::info) },
+    Command { name: "SLOWLOG", arity: -2, handler: Handler::Session(server::slowlog) },
+    Command { name: "MONITOR", arity: 1, handler: Handler::Session(server::monitor) },
+    Command { name: "CLIENT", arity: -2, handler: Handler::Session(connection::client) },
+
+    Command { name: "SETBIT", arity: 4, handler: Handler::Write(bitmap::setbit) },
+    Command { name: "GETBIT", arity: 3, handler: Handler::Read(bitmap::getbit) },
+    Command { name: "BITCOUNT", arity: -2, handler: Handler::Read(bitmap::bitcount) },
+    Command { name: "BITPOS", arity: -3, handler: Handler::Read(bitmap::bitpos) },
+    Command { name: "BITOP", arity: -4, handler: Handler::Write(bitmap::bitop) },
+
+    Command { name: "LPUSH", arity: -3, handler: Handler::Write(list::push) },
+    Command { name: "RPUSH", arity: -3, handler: Handler::Write(list::push) },
+    Command { name: "LPOP", arity: 2, handler: Handler::Write(list::pop) },
+    Command { name: "RPOP", arity: 2, handler: Handler::Write(list::pop) },
+    Command { name: "LRANGE", arity: 4, handler: Handler::Read(list::range) },
+    Command { name: "LLEN", arity: 2, handler: Handler::Read(list::len) },
+    Command { name: "LINDEX", arity: 3, handler: Handler::Read(list::index) },
+    Command { name: "LSET", arity: 4, handler: Handler::Write(list::set) },
+    Command { name: "LREM", arity: 4, handler: Handler::Write(list::rem) },
+    Command { name: "LTRIM", arity: 4, handler: Handler::Write(list::trim) },
+    Command { name: "LMOVE", arity: 5, handler: Handler::Write(list::lmove) },
+    Command { name: "BLPOP", arity: -3, handler: Handler::Blocking(list::try_pop) },
+    Command { name: "BRPOP", arity: -3, handler: Handler::Blocking(list::try_pop) },
+    Command { name: "BLMOVE", arity: 6, handler: Handler::Blocking(list::try_move) },
+
+    Command { name: "HSET", arity: -4, handler: Handler::Write(hash::set) },
+    Command { name: "HGET", arity: 3, handler: Handler::Read(hash::get) },
+    Command { name: "HMGET", arity: -3, handler: Handler::Read(hash::mget) },
+    Command { name: "HDEL", arity: -3, handler: Handler::Write(hash::del) },
+    Command { name: "HEXISTS", arity: 3, handler: Handler::Read(hash::exists) },
+    Command { name: "HLEN", arity: 2, handler: Handler::Read(hash::len) },
+    Command { name: "HKEYS", arity: 2, handler: Handler::Read(hash::keys) },
+    Command { name: "HVALS", arity: 2, handler: Handler::Read(hash::vals) },
+    Command { name: "HGETALL", arity: 2, handler: Handler::Read(hash::getall) },
+    Command { name: "HINCRBY", arity: 4, handler: Handler::Write(hash::incrby) },
+
+    Command { name: "SADD", arity: -3, handler: Handler::Write(set::add) },
+    Command { name: "SREM", arity: -3, handler: Handler::Write(set::rem) },
+    Command { name: "SISMEMBER", arity: 3, handler: Handler::Read(set::ismember) },
+    Command { name: "SMEMBERS", arity: 2, handler: Handler::Read(set::members) },
+    Command { name: "SCARD", arity: 2, handler: Handler::Read(set::card) },
+    Command { name: "SPOP", arity: -2, handler: Handler::Write(set::pop) },
+    Command { name: "SRANDMEMBER", arity: -2, handler: Handler::Read(set::randmember) },
+    Command { name: "SINTER", arity: -2, handler: Handler::Read(set::combine) },
+    Command { name: "SUNION", arity: -2, handler: Handler::Read(set::combine) },
+    Command { name: "SDIFF", arity: -2, handler: Handler::Read(set::combine) },
+    Command { name: "SINTERSTORE", arity: -3, handler: Handler::Write(set::combine_store) },
+    Command { name: "SUNIONSTORE", arity: -3, handler: Handler::Write(set::combine_store) },
+    Command { name: "SDIFFSTORE", arity: -3, handler: Handler::Write(set::combine_store) },
+
+    Command { name: "ZADD", arity: -4, handler: Handler::Write(zset::add) },
+    Command { name: "ZREM", arity: -3, handler: Handler::Write(zset::rem) },
+    Command { name: "ZSCORE", arity: 3, handler: Handler::Read(zset::score) },
+    Command { name: "ZINCRBY", arity: 4, handler: Handler::Write(zset::incrby) },
+    Command { name: "ZRANK", arity: 3, handler: Handler::Read(zset::rank) },
+    Command { name: "ZRANGE", arity: -4, handler: Handler::Read(zset::range) },
+    Command { name: "ZREVRANGE", arity: -4, handler: Handler::Read(zset::range) },
+    Command { name: "ZRANGEBYSCORE", arity: -4, handler: Handler::Read(zset::range_by_score) },
+    Command { name: "ZCARD", arity: 2, handler: Handler::Read(zset::card) },
+
+    Command { name: "PFADD", arity: -2, handler: Handler::Write(hyperloglog::pfadd) },
+    Command { name: "PFCOUNT", arity: -2, handler: Handler::Read(hyperloglog::pfcount) },
+    Command { name: "PFMERGE", arity: -2, handler: Handler::Write(hyperloglog::pfmerge) },
+
+    Command { name: "XADD", arity: -5, handler: Handler::Write(stream::xadd) },
+    Command { name: "XRANGE", arity: -4, handler: Handler::Read(stream::xrange) },
+    Command { name: "XREVRANGE", arity: -4, handler: Handler::Read(stream::xrange) },
+    Command { name: "XLEN", arity: 2, handler: Handler::Read(stream::xlen) },
+    Command { name: "XTRIM", arity: -4, handler: Handler::Write(stream::xtrim) },
+    Command { name: "XGROUP", arity: -4, handler: Handler::Write(stream::xgroup) },
+    Command { name: "XREADGROUP", arity: -7, handler: Handler::Write(stream::xreadgroup) },
+    Command { name: "XACK", arity: -4, handler: Handler::Write(stream::xack) },
+    Command { name: "XPENDING", arity: -3, handler: Handler::Read(stream::xpending) },
+
+    Command { name: "SUBSCRIBE", arity: -2, handler: Handler::Session(pubsub::subscribe) },
+    Command { name: "UNSUBSCRIBE", arity: -1, handler: Handler::Session(pubsub::unsubscribe) },
+    Command { name: "PSUBSCRIBE", arity: -2, handler: Handler::Session(pubsub::psubscribe) },
+    Command { name: "PUNSUBSCRIBE", arity: -1, handler: Handler::Session(pubsub::punsubscribe) },
+    Command { name: "PUBLISH", arity: 3, handler: Handler::Session(pubsub::publish) },
+
+    Command { name: "MULTI", arity: 1, handler: Handler::Session(transaction::multi) },
+    Command { name: "EXEC", arity: 1, handler: Handler::Session(transaction::exec) },
+    Command { name: "DISCARD", arity: 1, handler: Handler::Session(transaction::discard) },
+    Command { name: "WATCH", arity: -2, handler: Handler::Session(transaction::watch) },
+    Command { name: "UNWATCH", arity: 1, handler: Handler::Session(transaction::unwatch) },
+];
+
+/// The write commands that only remove data, so they can run when memory is
+/// full.
+const FREEING_COMMANDS: &[&str] = &[
+    "DELETE", "GETDEL", "DESTROY", "FLUSHDB", "LPOP", "RPOP", "LREM", "LTRIM", "BLPOP", "BRPOP",
+    "HDEL", "SREM", "SPOP", "ZREM", "XTRIM", "XACK",
+];
+
+/// Finds the command with the given uppercase name.
+pub fn lookup(name: &str) -> Option<&'static Command> {
+    COMMANDS.iter().find(|c| c.name == name)
+}
+
+pub fn names() -> impl Iterator<Item = &'static str> {
+    COMMANDS.iter().map(|c| c.name)
+}
+
+impl Command {
+    pub fn check_arity(&self, argc: usize) -> bool {
+        if self.arity < 0 {
+            argc >= (-self.arity) as usize
+        } else {
+            argc == self.arity as usize
+        }
+    }
+
+    /// Returns the arguments that are keys, which ACL key patterns apply to.
+    pub fn keys<'a>(&self, args: &'a [String]) -> &'a [String] {
+        match self.name {
+            "SINTER" | "SUNION" | "SDIFF" | "SINTERSTORE" | "SUNIONSTORE" | "SDIFFSTORE" |
+            "PFCOUNT" | "PFMERGE" | "WATCH" => &args[1..],
+            "BITOP" => &args[2..],
+            "LMOVE" | "BLMOVE" => &args[1..3],
+            "BLPOP" | "BRPOP" => &args[1..args.len() - 1],
+            "XGROUP" => &args[2..3],
+            "XREADGROUP" => {
+                // The keys are the first half of the arguments after STREAMS.
+                let streams = args.iter().position(|arg| arg.eq_ignore_ascii_case("STREAMS"));
+                let start = streams.map_or(args.len(), |i| i + 1);
+                &args[start..start + (args.len() - start) / 2]
+            },
+            "COUNT" | "DESTROY" | "FLUSHDB" | "FLUSHALL" | "SWAPDB" => &[],
+            _ => match self.handler {
+                Handler::Session(_) | Handler::Server(_) => &[],
+                _ => &args[1..2],
+            },
+        }
+    }
+
+    /// Returns true if the command may use more memory, so it's rejected
+    /// when the databases are full.
+    pub fn uses_memory(&self) -> bool {
+        match self.handler {
+            Handler::Write(_) | Handler::Blocking(_) => !FREEING_COMMANDS.contains(&self.name),
+            _ => false,
+        }
+    }
+
+    /// Runs the command, taking the read or write lock on the databases as
+    /// needed.
+    pub fn execute(&self, session: &mut Session, args: &[String]) -> Response {
+        let result = match self.handler {
+            Handler::Read(f) => f(&session.store().read().unwrap(), args),
+            Handler::Write(f) => {
+                let mut store = session.shared.write(session.db);
+                let result = f(&mut store, args);
+                blocking::serve(&session.shared.blocked, session.db, &mut store);
+                result
+            },
+            Handler::Databases(f) => {
+                let mut guards = session.shared.write_all();
+                let mut stores: Vec<&mut Store> = guards.iter_mut().map(|guard| &mut **guard).collect();
+                let result = f(&mut stores, session.db, args);
+                blocking::serve_all(&session.shared.blocked, &mut stores);
+                result
+            },
+            Handler::Session(f) => f(session, args),
+            Handler::Blocking(f) => blocking::execute(session, f, args),
+            Handler::Server(f) => {
+                let guards = session.shared.read_all();
+                let stores: Vec<&Store> = guards.iter().map(|guard| &**guard).collect();
+                f(&session.shared, &stores, args)
+            },
+        };
+
+        result.unwrap_or_else(|e| Response::build_error(&e.to_string()))
+    }
+
+    /// Runs the command with all databases already locked. Blocking commands
+    /// don't wait, and reply as if they timed out.
+    pub fn execute_locked(&self, stores: &mut [&mut Store], session: &mut Session, args: &[String]) -> Response {
+        let db = session.db;
+        let result = match self.handler {
+            Handler::Read(f) => f(stores[db], args),
+            Handler::Write(f) => f(stores[db], args),
+            Handler::Databases(f) => f(stores, db, args),
+            Handler::Session(f) => f(session, args),
+            Handler::Blocking(f) => {
+                let reply = f(stores[db], args);
+                reply.map(|reply| reply.unwrap_or_else(|| blocking::timeout_reply(&args[0])))
+            },
+            Handler::Server(f) => {
+                let stores: Vec<&Store> = stores.iter().map(|store| &**store).collect();
+                f(&session.shared, &stores, args)
+            },
+        };
+
+        result.unwrap_or_else(|e| Response::build_error(&e.to_string()))
+    }
+}
+
+/// Serializes a stored value as a RESP Bulk String.
+fn to_bulk_string(value: &[u8]) -> String {
+    RespWriter::to_bulk_string(&String::from_utf8_lossy(value))
+}
+
+/// Serializes stored values as a RESP Array of Bulk Strings.
+fn to_array<'a, I>(values: I) -> String
+where
+    I: IntoIterator<Item = &'a Vec<u8>>,
+{
+    let elements: Vec<String> = values.into_iter().map(|v| to_bulk_string(v)).collect();
+    RespWriter::to_raw_array(&elements)
+}
+
+fn parse_int(s: &str) -> Result<i64> {
+    s.parse().map_err(|_| CommandError::NotInteger)
+}
+
+/// Converts inclusive `start` and `stop` indexes, which may be negative to
+/// count from the end, into a range over a sequence of `len` elements.
+///
+/// Returns `None` if the range is empty.
+fn normalize_range(start: i64, stop: i64, len: usize) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let start = if start < 0 { (len + start).max(0) } else { start };
+    let stop = if stop < 0 { len + stop } else { stop.min(len - 1) };
+
+    if start > stop || start >= len {
+        None
+    } else {
+        Some((start as usize, stop as usize))
+    }
+}
+
+/// Splits a command line into arguments for calling handlers in tests.
+#[cfg(test)]
+fn test_args(line: &str) -> Vec<String> {
+    line.split_whitespace().map(String::from).collect()
+}
+
+/// Splits a RESP Array of Bulk Strings into its sorted elements, for
+/// checking replies whose order is unspecified in tests.
+#[cfg(test)]
+fn test_sorted_elements(response: Result<Response>) -> Vec<String> {
+    let s = match response {
+        Ok(Response::KeepAlive(s)) => s,
+        _ => panic!("Unexpected response"),
+    };
+    let mut elements: Vec<String> = s.split("\r\n")
+        .skip(1)
+        .filter(|e| !e.is_empty() && !e.starts_with('$'))
+        .map(String::from)
+        .collect();
+    elements.sort();
+    elements
+}
+
+/// Builds the reply of a handler that answers with an Integer, in tests.
+#[cfg(test)]
+fn test_integer(i: i64) -> Result<Response> {
+    Ok(Response::KeepAlive(RespWriter::to_signed_integer(i)))
+}
+
+/// Builds the reply of a handler that answers with a Bulk String, in tests.
+#[cfg(test)]
+fn test_bulk(s: &str) -> Result<Response> {
+    Ok(Response::KeepAlive(RespWriter::to_bulk_string(s)))
+}
+
+/// Builds the reply of a handler that answers with a null Bulk String, in
+/// tests.
+#[cfg(test)]
+fn test_nil() -> Result<Response> {
+    Ok(Response::KeepAlive(RespWriter::null_bulk_string()))
+}
+
+/// Builds the reply of a handler that answers with an Array of Bulk Strings,
+/// in tests.
+#[cfg(test)]
+fn test_array(v: &[&str]) -> Result<Response> {
+    Ok(Response::KeepAlive(RespWriter::to_array(v)))
+}
+
+/// Creates a session without a connection for calling handlers in tests.
+#[cfg(test)]
+fn test_session(shared: &std::sync::Arc<Shared>) -> Session {
+    test_session_with_receiver(shared).0
+}
+
+/// Creates a session like `test_session`, also returning the receiving end
+/// of its channel to check what gets pushed to the client.
+#[cfg(test)]
+fn test_session_with_receiver(shared: &std::sync::Arc<Shared>) -> (Session, std::sync::mpsc::Receiver<String>) {
+    let (tx, rx) = std::sync::mpsc::sync_channel(16);
+    (Session::new(std::sync::Arc::clone(shared), None, tx), rx)
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn check_lookup() {
+        assert_eq!(lookup("LPUSH").unwrap().name, "LPUSH");
+        assert!(lookup("lpush").is_none());
+        assert!(lookup("UNKNOWN").is_none());
+    }
+
+    #[test]
+    fn check_arity() {
+        let getset = lookup("GETSET").unwrap();
+        assert!(getset.check_arity(3));
+        assert!(!getset.check_arity(2));
+        assert!(!getset.check_arity(4));
+
+        let lpush = lookup("LPUSH").unwrap();
+        assert!(!lpush.check_arity(2));
+        assert!(lpush.check_arity(3));
+        assert!(lpush.check_arity(10));
+    }
+
+    #[test]
+    fn check_keys() {
+        let keys = |line: &str| {
+            let args = test_args(line);
+            lookup(&args[0]).unwrap().keys(&args).to_vec()
+        };
+
+        assert_eq!(keys("GET a"), vec!["a"]);
+        assert_eq!(keys("SET a b"), vec!["a"]);
+        assert_eq!(keys("SUNION a b"), vec!["a", "b"]);
+        assert_eq!(keys("BITOP AND dest a b"), vec!["dest", "a", "b"]);
+        assert_eq!(keys("BLPOP a b 0"), vec!["a", "b"]);
+        assert_eq!(keys("LMOVE a b LEFT RIGHT"), vec!["a", "b"]);
+        assert_eq!(keys("XGROUP CREATE events workers $"), vec!["events"]);
+        assert_eq!(keys("XREADGROUP GROUP g c COUNT 1 STREAMS a b > >"), vec!["a", "b"]);
+        assert_eq!(keys("PUBLISH channel message"), Vec::<String>::new());
+        assert_eq!(keys("DESTROY"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn check_normalize_range() {
+        assert_eq!(normalize_range(0, -1, 3), Some((0, 2)));
+        assert_eq!(normalize_range(-2, -1, 3), Some((1, 2)));
+        assert_eq!(normalize_range(-100, 100, 3), Some((0, 2)));
+        assert_eq!(normalize_range(1, 0, 3), None);
+        assert_eq!(normalize_range(5, 10, 3), None);
+        assert_eq!(normalize_range(0, -1, 0), None);
+    }
+}
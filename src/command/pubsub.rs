@@ -0,0 +1,193 @@
+use respwriter::RespWriter;
+use response::Response;
+use session::Session;
+
+use super::Result;
+
+/// Serializes a subscription change as `[kind, name, count]`, where count is
+/// the number of subscriptions left.
+fn to_reply(kind: &str, name: Option<&str>, count: usize) -> String {
+    let name = match name {
+        Some(name) => RespWriter::to_bulk_string(name),
+        None => RespWriter::null_bulk_string(),
+    };
+    RespWriter::to_raw_array(&[RespWriter::to_bulk_string(kind), name, RespWriter::to_signed_integer(count as i64)])
+}
+
+/// SUBSCRIBE channel [channel ...]
+///
+/// Replies with a confirmation for each channel.
+pub fn subscribe(session: &mut Session, args: &[String]) -> Result<Response> {
+    let mut replies = String::new();
+
+    for channel in &args[1..] {
+        if session.channels.insert(channel.clone()) {
+            let mut pubsub = session.shared.pubsub.lock().unwrap();
+            pubsub.subscribe(channel, session.id, session.sender.clone());
+        }
+        replies.push_str(&to_reply("subscribe", Some(channel), session.subscriptions()));
+    }
+
+    Ok(Response::KeepAlive(replies))
+}
+
+/// UNSUBSCRIBE [channel ...]
+///
+/// Without channels, unsubscribes from all of them.
+pub fn unsubscribe(session: &mut Session, args: &[String]) -> Result<Response> {
+    let channels: Vec<String> = if args.len() > 1 {
+        args[1..].to_vec()
+    } else {
+        session.channels.iter().cloned().collect()
+    };
+
+    if channels.is_empty() {
+        return Ok(Response::KeepAlive(to_reply("unsubscribe", None, session.subscriptions())));
+    }
+
+    let mut replies = String::new();
+    for channel in &channels {
+        if session.channels.remove(channel) {
+            session.shared.pubsub.lock().unwrap().unsubscribe(channel, session.id);
+        }
+        replies.push_str(&to_reply("unsubscribe", Some(channel), session.subscriptions()));
+    }
+
+    Ok(Response::KeepAlive(replies))
+}
+
+/// PSUBSCRIBE pattern [pattern ...]
+///
+/// Subscribes to every channel matching the glob-style patterns.
+pub fn psubscribe(session: &mut Session, args: &[String]) -> Result<Response> {
+    let mut replies = String::new();
+
+    for pattern in &args[1..] {
+        if session.patterns.insert(pattern.clone()) {
+            let mut pubsub = session.shared.pubsub.lock().unwrap();
+            pubsub.psubscribe(pattern, session.id, session.sender.clone());
+        }
+        replies.push_str(&to_reply("psubscribe", Some(pattern), session.subscriptions()));
+    }
+
+    Ok(Response::KeepAlive(replies))
+}
+
+/// PUNSUBSCRIBE [pattern ...]
+///
+/// Without patterns, unsubscribes from all of them.
+pub fn punsubscribe(session: &mut Session, args: &[String]) -> Result<Response> {
+    let patterns: Vec<String> = if args.len() > 1 {
+        args[1..].to_vec()
+    } else {
+        session.patterns.iter().cloned().collect()
+    };
+
+    if patterns.is_empty() {
+        return Ok(Response::KeepAlive(to_reply("punsubscribe", None, session.subscriptions())));
+    }
+
+    let mut replies = String::new();
+    for pattern in &patterns {
+        if session.patterns.remove(pattern) {
+            session.shared.pubsub.lock().unwrap().punsubscribe(pattern, session.id);
+        }
+        replies.push_str(&to_reply("punsubscribe", Some(pattern), session.subscriptions()));
+    }
+
+    Ok(Response::KeepAlive(replies))
+}
+
+/// PUBLISH channel message
+///
+/// Replies with the number of clients that received the message.
+pub fn publish(session: &mut Session, args: &[String]) -> Result<Response> {
+    let (receivers, overflowed) = session.shared.pubsub.lock().unwrap().publish(&args[1], &args[2]);
+    for id in overflowed {
+        session.shared.clients.kill(id);
+    }
+    Ok(Response::KeepAlive(RespWriter::to_signed_integer(receivers as i64)))
+}
+
+/// PING [message]
+///
+/// In subscribe mode, replies with `["pong", message]` instead, as a plain
+/// reply could be mistaken for a pushed message.
+pub fn ping(session: &mut Session, args: &[String]) -> Result<Response> {
+    let message = args.get(1).map_or("", |m| m.as_ref());
+
+    let reply = if session.subscriptions() > 0 {
+        RespWriter::to_array(&["pong", message])
+    } else if args.len() > 1 {
+        RespWriter::to_bulk_string(message)
+    } else {
+        RespWriter::to_simple_string("PONG").unwrap()
+    };
+
+    Ok(Response::KeepAlive(reply))
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use command::test_args as args;
+    use command::test_integer as integer;
+    use command::test_session_with_receiver;
+    use session::Shared;
+    use std::sync::Arc;
+    use store::Store;
+
+    #[test]
+    fn subscribe_and_unsubscribe() {
+        let shared = Arc::new(Shared::new(vec![Store::new()]));
+        let (mut session, _rx) = test_session_with_receiver(&shared);
+
+        let expected = to_reply("subscribe", Some("a"), 1) + &to_reply("subscribe", Some("b"), 2);
+        assert_eq!(subscribe(&mut session, &args("SUBSCRIBE a b")), Ok(Response::KeepAlive(expected)));
+
+        let expected = to_reply("psubscribe", Some("c*"), 3);
+        assert_eq!(psubscribe(&mut session, &args("PSUBSCRIBE c*")), Ok(Response::KeepAlive(expected)));
+
+        let expected = to_reply("unsubscribe", Some("a"), 2) + &to_reply("unsubscribe", Some("b"), 1);
+        assert_eq!(unsubscribe(&mut session, &args("UNSUBSCRIBE")), Ok(Response::KeepAlive(expected)));
+
+        let expected = to_reply("unsubscribe", None, 1);
+        assert_eq!(unsubscribe(&mut session, &args("UNSUBSCRIBE")), Ok(Response::KeepAlive(expected)));
+
+        let expected = to_reply("punsubscribe", Some("c*"), 0);
+        assert_eq!(punsubscribe(&mut session, &args("PUNSUBSCRIBE c*")), Ok(Response::KeepAlive(expected)));
+    }
+
+    #[test]
+    fn publish_to_other_sessions() {
+        let shared = Arc::new(Shared::new(vec![Store::new()]));
+        let (mut publisher, _) = test_session_with_receiver(&shared);
+        let (mut subscriber, rx) = test_session_with_receiver(&shared);
+        let (mut psubscriber, prx) = test_session_with_receiver(&shared);
+
+        subscribe(&mut subscriber, &args("SUBSCRIBE cache")).unwrap();
+        psubscribe(&mut psubscriber, &args("PSUBSCRIBE cach?")).unwrap();
+
+        assert_eq!(publish(&mut publisher, &args("PUBLISH cache user:1")), integer(2));
+        assert_eq!(rx.try_recv(), Ok(RespWriter::to_array(&["message", "cache", "user:1"])));
+        assert_eq!(prx.try_recv(), Ok(RespWriter::to_array(&["pmessage", "cach?", "cache", "user:1"])));
+
+        // dropping a session unsubscribes it
+        drop(subscriber);
+        assert_eq!(publish(&mut publisher, &args("PUBLISH cache user:2")), integer(1));
+    }
+
+    #[test]
+    fn ping_command() {
+        let shared = Arc::new(Shared::new(vec![Store::new()]));
+        let (mut session, _rx) = test_session_with_receiver(&shared);
+
+        let pong = Ok(Response::KeepAlive(RespWriter::to_simple_string("PONG").unwrap()));
+        assert_eq!(ping(&mut session, &args("PING")), pong);
+        assert_eq!(ping(&mut session, &args("PING hello")), Ok(Response::KeepAlive(RespWriter::to_bulk_string("hello"))));
+
+        subscribe(&mut session, &args("SUBSCRIBE a")).unwrap();
+        assert_eq!(ping(&mut session, &args("PING")), Ok(Response::KeepAlive(RespWriter::to_array(&["pong", ""]))));
+    }
+}
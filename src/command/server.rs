@@ -0,0 +1,373 @@
+use std::fmt::Write;
+use std::process;
+use std::sync::atomic::Ordering;
+
+use acl::DEFAULT_USER;
+use glob;
+use respwriter::RespWriter;
+use response::Response;
+use session::{Session, Shared};
+use store::Store;
+
+use super::{CommandError, Result};
+
+/// CONFIG GET pattern
+/// CONFIG SET directive value
+/// CONFIG REWRITE
+pub fn config(session: &mut Session, args: &[String]) -> Result<Response> {
+    match (args[1].to_uppercase().as_ref(), args.len()) {
+        ("GET", 3) => config_get(session, &args[2]),
+        ("SET", 4) => config_set(session, &args[2], &args[3]),
+        ("REWRITE", 2) => config_rewrite(session),
+        _ => Err(CommandError::Syntax),
+    }
+}
+
+/// Replies with the directives matching the glob-style pattern and their
+/// values, as a flat array.
+fn config_get(session: &mut Session, pattern: &str) -> Result<Response> {
+    let pattern = pattern.to_lowercase();
+    let config = session.shared.config.read().unwrap();
+
+    let mut reply = Vec::new();
+    for (directive, value) in config.values() {
+        if glob::matches(pattern.as_bytes(), directive.as_bytes()) {
+            reply.push(RespWriter::to_bulk_string(directive));
+            reply.push(RespWriter::to_bulk_string(&value));
+        }
+    }
+    Ok(Response::KeepAlive(RespWriter::to_raw_array(&reply)))
+}
+
+/// Changes a directive for the running server. A new requirepass replaces
+/// the passwords of the default user, and an empty one removes them.
+fn config_set(session: &mut Session, directive: &str, value: &str) -> Result<Response> {
+    let directive = directive.to_lowercase();
+    let mut config = session.shared.config.write().unwrap();
+    config.set(&directive, value).map_err(|e| CommandError::Config(e.to_string()))?;
+
+    if directive == "requirepass" {
+        let rules = match &config.requirepass {
+            Some(password) => vec!["resetpass".to_string(), format!(">{}", password)],
+            None => vec!["nopass".to_string()],
+        };
+        let mut acl = session.shared.acl.write().unwrap();
+        acl.set_user(DEFAULT_USER, &rules).expect("requirepass rules are valid");
+    }
+
+    Ok(Response::build_ok())
+}
+
+fn config_rewrite(session: &mut Session) -> Result<Response> {
+    let config = session.shared.config.read().unwrap();
+    config.rewrite().map_err(|e| CommandError::Config(e.to_string()))?;
+    Ok(Response::build_ok())
+}
+
+/// MONITOR
+///
+/// Streams the commands run by the other connections to this one, until it
+/// closes.
+pub fn monitor(session: &mut Session, _args: &[String]) -> Result<Response> {
+    if !session.monitoring {
+        session.shared.monitors.add(session.id, session.sender.clone());
+        session.monitoring = true;
+    }
+    Ok(Response::build_ok())
+}
+
+/// SLOWLOG GET [count]
+/// SLOWLOG LEN
+/// SLOWLOG RESET
+pub fn slowlog(session: &mut Session, args: &[String]) -> Result<Response> {
+    match (args[1].to_uppercase().as_ref(), args.len()) {
+        ("GET", 2) => slowlog_get(session, 10),
+        ("GET", 3) => {
+            // -1 gets every entry.
+            let count = match args[2].parse::<i64>() {
+                Ok(-1) => usize::MAX,
+                Ok(count) if count >= 0 => count as usize,
+                _ => return Err(CommandError::NotInteger),
+            };
+            slowlog_get(session, count)
+        },
+        ("LEN", 2) => {
+            let len = session.shared.slowlog.lock().unwrap().len();
+            Ok(Response::KeepAlive(RespWriter::to_signed_integer(len as i64)))
+        },
+        ("RESET", 2) => {
+            session.shared.slowlog.lock().unwrap().reset();
+            Ok(Response::build_ok())
+        },
+        _ => Err(CommandError::Syntax),
+    }
+}
+
+/// Replies with the most recent entries, newest first, each as
+/// `[id, timestamp, microseconds, [arg ...], client address]`.
+fn slowlog_get(session: &mut Session, count: usize) -> Result<Response> {
+    let slowlog = session.shared.slowlog.lock().unwrap();
+    let entries: Vec<String> = slowlog.get(count)
+        .map(|entry| {
+            let args: Vec<&str> = entry.args.iter().map(String::as_ref).collect();
+            RespWriter::to_raw_array(&[
+                RespWriter::to_signed_integer(entry.id as i64),
+                RespWriter::to_signed_integer(entry.timestamp as i64),
+                RespWriter::to_signed_integer(entry.duration as i64),
+                RespWriter::to_array(&args),
+                RespWriter::to_bulk_string(&entry.addr),
+            ])
+        })
+        .collect();
+    Ok(Response::KeepAlive(RespWriter::to_raw_array(&entries)))
+}
+
+/// The sections of INFO, in order.
+const INFO_SECTIONS: &[&str] = &["server", "clients", "memory", "persistence", "stats", "commandstats", "keyspace"];
+
+/// INFO [section]
+///
+/// Replies with `field:value` lines under a `# Section` header for each
+/// section, or only the given one. An unknown section gives an empty reply.
+pub fn info(shared: &Shared, stores: &[&Store], args: &[String]) -> Result<Response> {
+    let sections: Vec<&str> = match args.get(1).map(|section| section.to_lowercase()) {
+        _ if args.len() > 2 => return Err(CommandError::Syntax),
+        None => INFO_SECTIONS.to_vec(),
+        Some(ref section) if section == "all" || section == "default" || section == "everything" => INFO_SECTIONS.to_vec(),
+        Some(section) => INFO_SECTIONS.iter().cloned().filter(|&name| name == section).collect(),
+    };
+
+    let mut info = String::new();
+    for section in sections {
+        if !info.is_empty() {
+            info.push_str("\r\n");
+        }
+        let (first, rest) = section.split_at(1);
+        let _ = write!(info, "# {}{}\r\n", first.to_uppercase(), rest);
+
+        let fields = match section {
+            "server" => server_info(shared),
+            "clients" => clients_info(shared),
+            "memory" => memory_info(shared),
+            "persistence" => persistence_info(),
+            "stats" => stats_info(shared),
+            "commandstats" => commandstats_info(shared),
+            _ => keyspace_info(stores),
+        };
+        for (field, value) in fields {
+            let _ = write!(info, "{}:{}\r\n", field, value);
+        }
+    }
+
+    Ok(Response::KeepAlive(RespWriter::to_bulk_string(&info)))
+}
+
+type Fields = Vec<(String, String)>;
+
+fn server_info(shared: &Shared) -> Fields {
+    let uptime = shared.stats.started.elapsed().as_secs();
+    vec![
+        ("hanbaiki_version".to_string(), env!("CARGO_PKG_VERSION").to_string()),
+        ("process_id".to_string(), process::id().to_string()),
+        ("tcp_port".to_string(), shared.config.read().unwrap().port.to_string()),
+        ("uptime_in_seconds".to_string(), uptime.to_string()),
+        ("uptime_in_days".to_string(), (uptime / 86_400).to_string()),
+    ]
+}
+
+fn clients_info(shared: &Shared) -> Fields {
+    vec![
+        ("connected_clients".to_string(), shared.stats.connected_clients.load(Ordering::Relaxed).to_string()),
+        ("blocked_clients".to_string(), shared.blocked.lock().unwrap().clients().to_string()),
+    ]
+}
+
+fn memory_info(shared: &Shared) -> Fields {
+    let used = shared.used_memory.load(Ordering::Relaxed);
+    let config = shared.config.read().unwrap();
+    vec![
+        ("used_memory".to_string(), used.to_string()),
+        ("used_memory_human".to_string(), to_human(used)),
+        ("maxmemory".to_string(), config.maxmemory.to_string()),
+        ("maxmemory_human".to_string(), to_human(config.maxmemory)),
+        ("maxmemory_policy".to_string(), config.maxmemory_policy.to_string()),
+    ]
+}
+
+/// Data isn't persisted yet, so the server never loads any.
+fn persistence_info() -> Fields {
+    vec![("loading".to_string(), "0".to_string())]
+}
+
+fn stats_info(shared: &Shared) -> Fields {
+    let stats = &shared.stats;
+    vec![
+        ("total_connections_received".to_string(), stats.total_connections.load(Ordering::Relaxed).to_string()),
+        ("total_commands_processed".to_string(), stats.total_commands.load(Ordering::Relaxed).to_string()),
+        ("evicted_keys".to_string(), stats.evicted_keys.load(Ordering::Relaxed).to_string()),
+    ]
+}
+
+/// The calls and run time of each command called at least once.
+fn commandstats_info(shared: &Shared) -> Fields {
+    shared.stats.commands()
+        .filter_map(|(name, stats)| {
+            let calls = stats.calls.load(Ordering::Relaxed);
+            let usec = stats.usec.load(Ordering::Relaxed);
+            if calls == 0 {
+                return None;
+            }
+            let value = format!("calls={},usec={},usec_per_call={:.2}", calls, usec, usec as f64 / calls as f64);
+            Some((format!("cmdstat_{}", name.to_lowercase()), value))
+        })
+        .collect()
+}
+
+/// The number of keys of each database that has any.
+fn keyspace_info(stores: &[&Store]) -> Fields {
+    stores.iter().enumerate()
+        .filter(|(_, store)| store.len() > 0)
+        .map(|(db, store)| (format!("db{}", db), format!("keys={}", store.len())))
+        .collect()
+}
+
+/// Formats a number of bytes with a unit, as in `1.50M`.
+fn to_human(bytes: usize) -> String {
+    let units = [("G", 1 << 30), ("M", 1 << 20), ("K", 1 << 10)];
+    match units.iter().find(|&&(_, size)| bytes >= size) {
+        Some(&(unit, size)) => format!("{:.2}{}", bytes as f64 / size as f64, unit),
+        None => format!("{}B", bytes),
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use command::test_args as args;
+    use command::test_session;
+    use session::Shared;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use store::Data;
+
+    #[test]
+    fn get_command() {
+        let mut session = test_session(&Arc::new(Shared::new(vec![Store::new()])));
+
+        let expected = RespWriter::to_array(&["port", "6363"]);
+        assert_eq!(config(&mut session, &args("CONFIG GET PORT")), Ok(Response::KeepAlive(expected)));
+
+        let expected = RespWriter::to_array(&["tls-port", "", "tls-cert-file", "", "tls-key-file", "", "tls-ca-cert-file", ""]);
+        assert_eq!(config(&mut session, &args("CONFIG GET tls-*")), Ok(Response::KeepAlive(expected)));
+
+        let expected = RespWriter::to_array(&[]);
+        assert_eq!(config(&mut session, &args("CONFIG GET bogus")), Ok(Response::KeepAlive(expected)));
+    }
+
+    #[test]
+    fn set_command() {
+        let mut session = test_session(&Arc::new(Shared::new(vec![Store::new()])));
+
+        assert_eq!(config(&mut session, &args("CONFIG SET timeout 30")), Ok(Response::build_ok()));
+        assert_eq!(session.shared.config.read().unwrap().timeout, 30);
+
+        let error = CommandError::Config("Can't set databases while the server is running".to_string());
+        assert_eq!(config(&mut session, &args("CONFIG SET databases 2")), Err(error));
+    }
+
+    #[test]
+    fn set_requirepass() {
+        let mut session = test_session(&Arc::new(Shared::new(vec![Store::new()])));
+
+        config(&mut session, &args("CONFIG SET requirepass secret")).unwrap();
+        {
+            let acl = session.shared.acl.read().unwrap();
+            let user = acl.user(DEFAULT_USER).unwrap();
+            assert!(!user.check_password("other"));
+            assert!(user.check_password("secret"));
+        }
+
+        // an empty password removes it
+        let mut clear = args("CONFIG SET requirepass x");
+        clear[3] = String::new();
+        config(&mut session, &clear).unwrap();
+        let acl = session.shared.acl.read().unwrap();
+        assert!(acl.user(DEFAULT_USER).unwrap().check_password("anything"));
+    }
+
+    fn run_info(shared: &Shared, command: &str) -> String {
+        let guards = shared.read_all();
+        let stores: Vec<&Store> = guards.iter().map(|guard| &**guard).collect();
+        match info(shared, &stores, &args(command)) {
+            Ok(Response::KeepAlive(reply)) => reply,
+            other => panic!("unexpected reply {:?}", other),
+        }
+    }
+
+    #[test]
+    fn info_command() {
+        let shared = Shared::new(vec![Store::new(), Store::new()]);
+        shared.write(1).insert("a".to_string(), Data::String(b"1".to_vec()));
+        shared.stats.record("GET", Duration::from_micros(30));
+        shared.stats.record("GET", Duration::from_micros(10));
+
+        let reply = run_info(&shared, "INFO");
+        for section in &["# Server\r\n", "# Clients\r\n", "# Memory\r\n", "# Persistence\r\n", "# Stats\r\n", "# Keyspace\r\n"] {
+            assert!(reply.contains(section));
+        }
+        assert!(reply.contains("total_commands_processed:2\r\n"));
+        let used = shared.used_memory.load(Ordering::Relaxed);
+        assert!(used > 0);
+        assert!(reply.contains(&format!("used_memory:{}\r\n", used)));
+        assert!(reply.contains("cmdstat_get:calls=2,usec=40,usec_per_call=20.00\r\n"));
+
+        let expected = RespWriter::to_bulk_string("# Keyspace\r\ndb1:keys=1\r\n");
+        assert_eq!(run_info(&shared, "INFO KEYSPACE"), expected);
+        assert_eq!(run_info(&shared, "INFO bogus"), RespWriter::to_bulk_string(""));
+    }
+
+    #[test]
+    fn slowlog_command() {
+        let mut session = test_session(&Arc::new(Shared::new(vec![Store::new()])));
+        {
+            let mut slowlog = session.shared.slowlog.lock().unwrap();
+            slowlog.push(1000, 20_000, vec!["GET".to_string(), "a".to_string()], "127.0.0.1:5000", 10);
+            slowlog.push(1001, 30_000, vec!["DESTROY".to_string()], "127.0.0.1:5001", 10);
+        }
+
+        let integer = |i| Ok(Response::KeepAlive(RespWriter::to_signed_integer(i)));
+        assert_eq!(slowlog(&mut session, &args("SLOWLOG LEN")), integer(2));
+
+        let expected = RespWriter::to_raw_array(&[RespWriter::to_raw_array(&[
+            RespWriter::to_signed_integer(1),
+            RespWriter::to_signed_integer(1001),
+            RespWriter::to_signed_integer(30_000),
+            RespWriter::to_array(&["DESTROY"]),
+            RespWriter::to_bulk_string("127.0.0.1:5001"),
+        ])]);
+        assert_eq!(slowlog(&mut session, &args("SLOWLOG GET 1")), Ok(Response::KeepAlive(expected)));
+
+        let reply = slowlog(&mut session, &args("SLOWLOG GET -1")).unwrap();
+        assert!(matches!(reply, Response::KeepAlive(ref reply) if reply.starts_with("*2\r\n")));
+        assert_eq!(slowlog(&mut session, &args("SLOWLOG GET -2")), Err(CommandError::NotInteger));
+
+        assert_eq!(slowlog(&mut session, &args("SLOWLOG RESET")), Ok(Response::build_ok()));
+        assert_eq!(slowlog(&mut session, &args("SLOWLOG LEN")), integer(0));
+    }
+
+    #[test]
+    fn human_sizes() {
+        assert_eq!(to_human(0), "0B");
+        assert_eq!(to_human(1023), "1023B");
+        assert_eq!(to_human(1536), "1.50K");
+        assert_eq!(to_human(3 << 30), "3.00G");
+    }
+
+    #[test]
+    fn rewrite_without_file() {
+        let mut session = test_session(&Arc::new(Shared::new(vec![Store::new()])));
+        let error = CommandError::Config("The server is running without a config file".to_string());
+        assert_eq!(config(&mut session, &args("CONFIG REWRITE")), Err(error));
+    }
+}
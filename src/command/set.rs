@@ -0,0 +1,342 @@
+use std::collections::HashSet;
+
+use rand::{self, Rng};
+use rand::seq::sample_iter;
+
+use respwriter::RespWriter;
+use response::Response;
+use store::{Data, Store};
+
+use super::{CommandError, Result, parse_int, to_array, to_bulk_string};
+
+type Set = HashSet<Vec<u8>>;
+
+/// The largest negative count SRANDMEMBER accepts, as the reply holds that
+/// many members however small the set.
+const MAX_REPEATED_MEMBERS: u64 = 1 << 20;
+
+fn get_set<'a>(store: &'a Store, key: &str) -> Result<Option<&'a Set>> {
+    match store.get(key) {
+        Some(Data::Set(set)) => Ok(Some(set)),
+        Some(_) => Err(CommandError::WrongType),
+        None => Ok(None),
+    }
+}
+
+fn get_set_mut<'a>(store: &'a mut Store, key: &str) -> Result<Option<&'a mut Set>> {
+    match store.get_mut(key) {
+        Some(Data::Set(set)) => Ok(Some(set)),
+        Some(_) => Err(CommandError::WrongType),
+        None => Ok(None),
+    }
+}
+
+/// Parses the optional count argument of SPOP and SRANDMEMBER.
+fn parse_count(args: &[String]) -> Result<Option<i64>> {
+    match args.len() {
+        2 => Ok(None),
+        3 => parse_int(&args[2]).map(Some),
+        _ => Err(CommandError::Syntax),
+    }
+}
+
+/// SADD key member [member ...]
+pub fn add(store: &mut Store, args: &[String]) -> Result<Response> {
+    let mut args = args.iter().skip(1);
+    let key = args.next().unwrap();
+
+    let set = match store.get_or_insert_with(key, || Data::Set(HashSet::new())) {
+        Data::Set(set) => set,
+        _ => return Err(CommandError::WrongType),
+    };
+    let added = args.filter(|member| set.insert(member.as_bytes().to_vec())).count();
+    if added > 0 {
+        store.touch(key);
+    }
+
+    Ok(Response::KeepAlive(RespWriter::to_signed_integer(added as i64)))
+}
+
+/// SREM key member [member ...]
+pub fn rem(store: &mut Store, args: &[String]) -> Result<Response> {
+    let key = &args[1];
+
+    let removed = match get_set_mut(store, key)? {
+        Some(set) => args[2..].iter().filter(|member| set.remove(member.as_bytes())).count(),
+        None => 0,
+    };
+    if removed > 0 {
+        store.touch(key);
+        store.remove_if_empty(key);
+    }
+
+    Ok(Response::KeepAlive(RespWriter::to_signed_integer(removed as i64)))
+}
+
+/// SISMEMBER key member
+pub fn ismember(store: &Store, args: &[String]) -> Result<Response> {
+    let member = get_set(store, &args[1])?.is_some_and(|set| set.contains(args[2].as_bytes()));
+    Ok(Response::KeepAlive(RespWriter::to_signed_integer(member as i64)))
+}
+
+/// SMEMBERS key
+pub fn members(store: &Store, args: &[String]) -> Result<Response> {
+    match get_set(store, &args[1])? {
+        Some(set) => Ok(Response::KeepAlive(to_array(set))),
+        None => Ok(Response::KeepAlive(to_array(&[]))),
+    }
+}
+
+/// SCARD key
+pub fn card(store: &Store, args: &[String]) -> Result<Response> {
+    let len = get_set(store, &args[1])?.map_or(0, |set| set.len());
+    Ok(Response::KeepAlive(RespWriter::to_signed_integer(len as i64)))
+}
+
+/// SPOP key [count]
+///
+/// Without a count, replies with a single member or nil. With a count,
+/// replies with an array of up to `count` distinct members.
+pub fn pop(store: &mut Store, args: &[String]) -> Result<Response> {
+    let key = &args[1];
+    let count = parse_count(args)?;
+    if count.is_some_and(|c| c < 0) {
+        return Err(CommandError::NotInteger);
+    }
+
+    let mut popped = vec![];
+    if let Some(set) = get_set_mut(store, key)? {
+        // The sample is allocated with the requested size.
+        let amount = (count.unwrap_or(1) as u64).min(set.len() as u64) as usize;
+        let chosen = sample_iter(&mut rand::thread_rng(), set.iter(), amount)
+            .unwrap_or_else(|all| all);
+        popped = chosen.into_iter().cloned().collect();
+        for member in &popped {
+            set.remove(member);
+        }
+    }
+    if !popped.is_empty() {
+        store.touch(key);
+        store.remove_if_empty(key);
+    }
+
+    match (count, popped.first()) {
+        (Some(_), _) => Ok(Response::KeepAlive(to_array(&popped))),
+        (None, Some(member)) => Ok(Response::KeepAlive(to_bulk_string(member))),
+        (None, None) => Ok(Response::KeepAlive(RespWriter::null_bulk_string())),
+    }
+}
+
+/// SRANDMEMBER key [count]
+///
+/// A positive count replies with up to `count` distinct members, while a
+/// negative count replies with exactly `-count` members that may repeat, up
+/// to `MAX_REPEATED_MEMBERS`.
+pub fn randmember(store: &Store, args: &[String]) -> Result<Response> {
+    let count = parse_count(args)?;
+    let mut rng = rand::thread_rng();
+
+    let members: Vec<&Vec<u8>> = match get_set(store, &args[1])? {
+        Some(set) => set.iter().collect(),
+        None => vec![],
+    };
+
+    if count.is_some_and(|c| c < 0 && c.unsigned_abs() > MAX_REPEATED_MEMBERS) {
+        return Err(CommandError::NotInteger);
+    }
+
+    match count {
+        None => match rng.choose(&members) {
+            Some(member) => Ok(Response::KeepAlive(to_bulk_string(member))),
+            None => Ok(Response::KeepAlive(RespWriter::null_bulk_string())),
+        },
+        Some(count) if count >= 0 => {
+            let amount = (count as u64).min(members.len() as u64) as usize;
+            let chosen = sample_iter(&mut rng, members, amount).unwrap_or_else(|all| all);
+            Ok(Response::KeepAlive(to_array(chosen)))
+        },
+        Some(_) if members.is_empty() => Ok(Response::KeepAlive(to_array(&[]))),
+        Some(count) => {
+            let chosen = (0..count.unsigned_abs()).map(|_| *rng.choose(&members).unwrap());
+            Ok(Response::KeepAlive(to_array(chosen)))
+        },
+    }
+}
+
+/// Computes the intersection, union or difference of the sets at `keys`,
+/// treating missing keys as empty sets.
+fn compute(store: &Store, command: &str, keys: &[String]) -> Result<Set> {
+    let sets = keys.iter()
+        .map(|key| get_set(store, key))
+        .collect::<Result<Vec<Option<&Set>>>>()?;
+
+    let empty = HashSet::new();
+    let mut sets = sets.into_iter().map(|set| set.unwrap_or(&empty));
+    let mut result = sets.next().unwrap().clone();
+
+    for set in sets {
+        match command {
+            "SINTER" => result.retain(|member| set.contains(member)),
+            "SUNION" => result.extend(set.iter().cloned()),
+            _ => result.retain(|member| !set.contains(member)),
+        }
+    }
+
+    Ok(result)
+}
+
+/// SINTER key [key ...]
+/// SUNION key [key ...]
+/// SDIFF key [key ...]
+pub fn combine(store: &Store, args: &[String]) -> Result<Response> {
+    let result = compute(store, &args[0], &args[1..])?;
+    Ok(Response::KeepAlive(to_array(&result)))
+}
+
+/// SINTERSTORE destination key [key ...]
+/// SUNIONSTORE destination key [key ...]
+/// SDIFFSTORE destination key [key ...]
+///
+/// Overwrites the destination with the result, replying with its size.
+pub fn combine_store(store: &mut Store, args: &[String]) -> Result<Response> {
+    let command = args[0].trim_end_matches("STORE");
+    let result = compute(store, command, &args[2..])?;
+    let len = result.len();
+
+    store.remove(&args[1]);
+    if !result.is_empty() {
+        store.insert(args[1].clone(), Data::Set(result));
+    }
+
+    Ok(Response::KeepAlive(RespWriter::to_signed_integer(len as i64)))
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use command::test_args as args;
+    use command::test_integer as integer;
+    use command::test_sorted_elements as sorted_elements;
+
+    fn init_sets() -> Store {
+        let mut store = Store::new();
+        add(&mut store, &args("SADD a 1 2 3 4")).unwrap();
+        add(&mut store, &args("SADD b 3 4 5")).unwrap();
+        add(&mut store, &args("SADD c 4 6")).unwrap();
+        store
+    }
+
+    fn members_of(store: &Store, key: &str) -> Vec<String> {
+        let mut members: Vec<String> = match get_set(store, key).unwrap() {
+            Some(set) => set.iter().map(|m| String::from_utf8(m.clone()).unwrap()).collect(),
+            None => vec![],
+        };
+        members.sort();
+        members
+    }
+
+    #[test]
+    fn add_and_rem_commands() {
+        let mut store = init_sets();
+
+        assert_eq!(add(&mut store, &args("SADD a 4 5 5")), integer(1));
+        assert_eq!(card(&store, &args("SCARD a")), integer(5));
+        assert_eq!(rem(&mut store, &args("SREM a 1 9")), integer(1));
+        assert_eq!(members_of(&store, "a"), vec!["2", "3", "4", "5"]);
+
+        assert_eq!(rem(&mut store, &args("SREM c 4 6")), integer(2));
+        assert!(!store.contains_key("c"));
+    }
+
+    #[test]
+    fn ismember_and_members_commands() {
+        let store = init_sets();
+
+        assert_eq!(ismember(&store, &args("SISMEMBER a 1")), integer(1));
+        assert_eq!(ismember(&store, &args("SISMEMBER a 5")), integer(0));
+        assert_eq!(ismember(&store, &args("SISMEMBER missing 1")), integer(0));
+        assert_eq!(sorted_elements(members(&store, &args("SMEMBERS b"))), vec!["3", "4", "5"]);
+        assert_eq!(card(&store, &args("SCARD missing")), integer(0));
+    }
+
+    #[test]
+    fn pop_command() {
+        let mut store = init_sets();
+
+        let popped = sorted_elements(pop(&mut store, &args("SPOP a 3")));
+        assert_eq!(popped.len(), 3);
+        assert_eq!(card(&store, &args("SCARD a")), integer(1));
+
+        let remaining = members_of(&store, "a");
+        assert!(popped.iter().all(|m| !remaining.contains(m)));
+
+        let expected = RespWriter::to_bulk_string(&remaining[0]);
+        assert_eq!(pop(&mut store, &args("SPOP a")), Ok(Response::KeepAlive(expected)));
+        assert!(!store.contains_key("a"));
+
+        assert_eq!(pop(&mut store, &args("SPOP a")), Ok(Response::KeepAlive(RespWriter::null_bulk_string())));
+        assert_eq!(pop(&mut store, &args("SPOP b -1")), Err(CommandError::NotInteger));
+
+        // huge counts pop the whole set
+        assert_eq!(sorted_elements(pop(&mut store, &args("SPOP b 1099511627776"))), vec!["3", "4", "5"]);
+        assert_eq!(sorted_elements(pop(&mut store, &args("SPOP c 9223372036854775807"))), vec!["4", "6"]);
+    }
+
+    #[test]
+    fn randmember_command() {
+        let store = init_sets();
+
+        let chosen = sorted_elements(randmember(&store, &args("SRANDMEMBER b 10")));
+        assert_eq!(chosen, vec!["3", "4", "5"]);
+
+        let chosen = sorted_elements(randmember(&store, &args("SRANDMEMBER c -5")));
+        assert_eq!(chosen.len(), 5);
+        assert!(chosen.iter().all(|m| m == "4" || m == "6"));
+
+        assert_eq!(card(&store, &args("SCARD b")), integer(3));
+
+        let chosen = sorted_elements(randmember(&store, &args("SRANDMEMBER b 9223372036854775807")));
+        assert_eq!(chosen, vec!["3", "4", "5"]);
+        assert_eq!(randmember(&store, &args("SRANDMEMBER b -9223372036854775808")), Err(CommandError::NotInteger));
+        assert_eq!(randmember(&store, &args("SRANDMEMBER b -1048577")), Err(CommandError::NotInteger));
+        assert_eq!(
+            randmember(&store, &args("SRANDMEMBER missing")),
+            Ok(Response::KeepAlive(RespWriter::null_bulk_string()))
+        );
+    }
+
+    #[test]
+    fn combine_command() {
+        let store = init_sets();
+
+        assert_eq!(sorted_elements(combine(&store, &args("SINTER a b c"))), vec!["4"]);
+        assert_eq!(sorted_elements(combine(&store, &args("SUNION b c"))), vec!["3", "4", "5", "6"]);
+        assert_eq!(sorted_elements(combine(&store, &args("SDIFF a b c"))), vec!["1", "2"]);
+        assert_eq!(sorted_elements(combine(&store, &args("SINTER a missing"))), Vec::<String>::new());
+    }
+
+    #[test]
+    fn combine_store_command() {
+        let mut store = init_sets();
+
+        assert_eq!(combine_store(&mut store, &args("SUNIONSTORE dest a c")), integer(5));
+        assert_eq!(members_of(&store, "dest"), vec!["1", "2", "3", "4", "6"]);
+
+        // the destination may be one of the source keys
+        assert_eq!(combine_store(&mut store, &args("SDIFFSTORE a a b")), integer(2));
+        assert_eq!(members_of(&store, "a"), vec!["1", "2"]);
+
+        assert_eq!(combine_store(&mut store, &args("SINTERSTORE dest a b")), integer(0));
+        assert!(!store.contains_key("dest"));
+    }
+
+    #[test]
+    fn wrong_type() {
+        let mut store = init_sets();
+        store.insert("hello".to_string(), Data::String(b"world".to_vec()));
+
+        assert_eq!(add(&mut store, &args("SADD hello a")), Err(CommandError::WrongType));
+        assert_eq!(combine(&store, &args("SUNION a hello")), Err(CommandError::WrongType));
+    }
+}
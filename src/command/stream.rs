@@ -0,0 +1,617 @@
+use std::collections::Bound;
+
+use respwriter::RespWriter;
+use response::Response;
+use store::{Data, Store};
+use stream::{Fields, Stream, StreamId};
+use time::now_ms;
+
+use super::{CommandError, Result, parse_int, to_bulk_string};
+
+fn get_stream<'a>(store: &'a Store, key: &str) -> Result<Option<&'a Stream>> {
+    match store.get(key) {
+        Some(Data::Stream(stream)) => Ok(Some(stream)),
+        Some(_) => Err(CommandError::WrongType),
+        None => Ok(None),
+    }
+}
+
+fn get_stream_mut<'a>(store: &'a mut Store, key: &str) -> Result<Option<&'a mut Stream>> {
+    match store.get_mut(key) {
+        Some(Data::Stream(stream)) => Ok(Some(stream)),
+        Some(_) => Err(CommandError::WrongType),
+        None => Ok(None),
+    }
+}
+
+fn parse_id(s: &str) -> Result<StreamId> {
+    s.parse().map_err(|_| CommandError::InvalidStreamId)
+}
+
+/// Parses the start of a range: `-` for the first entry, an ID, or `(` and
+/// an ID to exclude it. An ID without a sequence number starts at `ms-0`.
+fn parse_start(s: &str) -> Result<Bound<StreamId>> {
+    match s {
+        "-" => Ok(Bound::Unbounded),
+        _ if s.starts_with('(') => parse_id(&s[1..]).map(Bound::Excluded),
+        _ => parse_id(s).map(Bound::Included),
+    }
+}
+
+/// Parses the end of a range: `+` for the last entry, an ID, or `(` and an
+/// ID to exclude it. An ID without a sequence number includes all of `ms`.
+fn parse_end(s: &str) -> Result<Bound<StreamId>> {
+    match s {
+        "+" => Ok(Bound::Unbounded),
+        _ if s.starts_with('(') => parse_id(&s[1..]).map(Bound::Excluded),
+        _ if !s.contains('-') => parse_id(s).map(|id| Bound::Included(StreamId::new(id.ms, u64::MAX))),
+        _ => parse_id(s).map(Bound::Included),
+    }
+}
+
+fn parse_count(s: &str) -> Result<usize> {
+    match parse_int(s)? {
+        count if count < 0 => Ok(0),
+        count => Ok(count as usize),
+    }
+}
+
+/// Serializes an entry as an array of its ID and its flattened fields.
+fn to_entry(id: &StreamId, fields: Option<&Fields>) -> String {
+    let fields = match fields {
+        Some(fields) => {
+            let elements: Vec<String> = fields.iter()
+                .flat_map(|(field, value)| vec![to_bulk_string(field), to_bulk_string(value)])
+                .collect();
+            RespWriter::to_raw_array(&elements)
+        },
+        None => RespWriter::null_array(),
+    };
+
+    RespWriter::to_raw_array(&[RespWriter::to_bulk_string(&id.to_string()), fields])
+}
+
+/// XADD key [MAXLEN [=|~] count] *|id field value [field value ...]
+///
+/// An ID of `*` is generated from the current time, and `ms-*` generates
+/// the sequence number only. Replies with the ID of the new entry.
+pub fn xadd(store: &mut Store, args: &[String]) -> Result<Response> {
+    let mut i = 2;
+    let mut maxlen = None;
+    if args[i].eq_ignore_ascii_case("MAXLEN") {
+        i += 1;
+        if args.get(i).is_some_and(|a| a == "=" || a == "~") {
+            i += 1;
+        }
+        let count = args.get(i).ok_or(CommandError::Syntax)?;
+        maxlen = Some(parse_count(count)?);
+        i += 1;
+    }
+
+    let pairs = args.get(i + 1..).unwrap_or(&[]);
+    if pairs.is_empty() || !pairs.len().is_multiple_of(2) {
+        return Err(CommandError::WrongNumberOfArguments("XADD"));
+    }
+
+    // ms-* generates only the sequence number
+    let (ms, id) = match args[i].as_ref() {
+        "*" => (None, None),
+        id => match id.strip_suffix("-*") {
+            Some(ms) => (Some(ms.parse().map_err(|_| CommandError::InvalidStreamId)?), None),
+            None => (None, Some(parse_id(id)?)),
+        },
+    };
+
+    let fields: Fields = pairs.chunks(2)
+        .map(|pair| (pair[0].clone().into_bytes(), pair[1].clone().into_bytes()))
+        .collect();
+
+    let created = !store.contains_key(&args[1]);
+    let stream = match store.get_or_insert_with(&args[1], || Data::Stream(Stream::new())) {
+        Data::Stream(stream) => stream,
+        _ => return Err(CommandError::WrongType),
+    };
+
+    let id = match (ms, id) {
+        (_, Some(id)) => Some(id),
+        (Some(ms), None) => stream.next_id_in(ms),
+        (None, None) => stream.next_id(now_ms()),
+    };
+
+    let id = match id {
+        Some(id) if stream.insert(id, fields) => id,
+        _ => {
+            if created {
+                store.remove(&args[1]);
+            }
+            return Err(CommandError::StreamIdTooSmall);
+        },
+    };
+
+    if let Some(maxlen) = maxlen {
+        stream.trim_maxlen(maxlen);
+    }
+    store.touch(&args[1]);
+
+    Ok(Response::KeepAlive(RespWriter::to_bulk_string(&id.to_string())))
+}
+
+/// XRANGE key start end [COUNT count]
+/// XREVRANGE key end start [COUNT count]
+pub fn xrange(store: &Store, args: &[String]) -> Result<Response> {
+    let reverse = args[0] == "XREVRANGE";
+    let (start, end) = if reverse { (&args[3], &args[2]) } else { (&args[2], &args[3]) };
+    let start = parse_start(start)?;
+    let end = parse_end(end)?;
+
+    let count = match args.len() {
+        4 => usize::MAX,
+        6 if args[4].eq_ignore_ascii_case("COUNT") => parse_count(&args[5])?,
+        _ => return Err(CommandError::Syntax),
+    };
+
+    let entries: Vec<String> = match get_stream(store, &args[1])? {
+        Some(stream) if reverse => stream.range(start, end).rev()
+            .take(count)
+            .map(|(id, fields)| to_entry(id, Some(fields)))
+            .collect(),
+        Some(stream) => stream.range(start, end)
+            .take(count)
+            .map(|(id, fields)| to_entry(id, Some(fields)))
+            .collect(),
+        None => vec![],
+    };
+
+    Ok(Response::KeepAlive(RespWriter::to_raw_array(&entries)))
+}
+
+/// XLEN key
+pub fn xlen(store: &Store, args: &[String]) -> Result<Response> {
+    let len = get_stream(store, &args[1])?.map_or(0, |stream| stream.len());
+    Ok(Response::KeepAlive(RespWriter::to_signed_integer(len as i64)))
+}
+
+/// XTRIM key MAXLEN|MINID [=|~] threshold
+///
+/// Replies with the number of entries removed. Trimming is always exact.
+pub fn xtrim(store: &mut Store, args: &[String]) -> Result<Response> {
+    let strategy = args[2].to_ascii_uppercase();
+    let threshold = match args.len() {
+        4 => &args[3],
+        5 if args[3] == "=" || args[3] == "~" => &args[4],
+        _ => return Err(CommandError::Syntax),
+    };
+
+    let removed = match strategy.as_ref() {
+        "MAXLEN" => {
+            let maxlen = parse_count(threshold)?;
+            get_stream_mut(store, &args[1])?.map_or(0, |stream| stream.trim_maxlen(maxlen))
+        },
+        "MINID" => {
+            let min_id = parse_id(threshold)?;
+            get_stream_mut(store, &args[1])?.map_or(0, |stream| stream.trim_minid(min_id))
+        },
+        _ => return Err(CommandError::Syntax),
+    };
+    if removed > 0 {
+        store.touch(&args[1]);
+    }
+
+    Ok(Response::KeepAlive(RespWriter::to_signed_integer(removed as i64)))
+}
+
+/// XGROUP CREATE key group id|$ [MKSTREAM]
+/// XGROUP DESTROY key group
+///
+/// `$` creates a group that only delivers entries added after it.
+pub fn xgroup(store: &mut Store, args: &[String]) -> Result<Response> {
+    let subcommand = args[1].to_ascii_uppercase();
+
+    match subcommand.as_ref() {
+        "CREATE" if args.len() == 5 || args.len() == 6 => {
+            let mkstream = match args.get(5) {
+                Some(option) if option.eq_ignore_ascii_case("MKSTREAM") => true,
+                Some(_) => return Err(CommandError::Syntax),
+                None => false,
+            };
+
+            if mkstream && !store.contains_key(&args[2]) {
+                store.insert(args[2].clone(), Data::Stream(Stream::new()));
+            }
+
+            let stream = get_stream_mut(store, &args[2])?.ok_or(CommandError::KeyNotFound)?;
+            let last_delivered = match args[4].as_ref() {
+                "$" => stream.last_id(),
+                id => parse_id(id)?,
+            };
+
+            if stream.create_group(&args[3], last_delivered) {
+                store.touch(&args[2]);
+                Ok(Response::build_ok())
+            } else {
+                Err(CommandError::BusyGroup)
+            }
+        },
+        "DESTROY" if args.len() == 4 => {
+            let destroyed = match get_stream_mut(store, &args[2])? {
+                Some(stream) => stream.destroy_group(&args[3]),
+                None => false,
+            };
+            if destroyed {
+                store.touch(&args[2]);
+            }
+            Ok(Response::KeepAlive(RespWriter::to_signed_integer(destroyed as i64)))
+        },
+        "CREATE" | "DESTROY" => Err(CommandError::WrongNumberOfArguments("XGROUP")),
+        _ => Err(CommandError::Syntax),
+    }
+}
+
+/// XREADGROUP GROUP group consumer [COUNT count] STREAMS key [key ...] id [id ...]
+///
+/// An ID of `>` delivers entries that were never delivered to the group,
+/// adding them to the consumer's pending entries. Any other ID replies with
+/// the consumer's pending entries after that ID.
+pub fn xreadgroup(store: &mut Store, args: &[String]) -> Result<Response> {
+    if !args[1].eq_ignore_ascii_case("GROUP") {
+        return Err(CommandError::Syntax);
+    }
+    let group = &args[2];
+    let consumer = &args[3];
+
+    let mut count = usize::MAX;
+    let mut i = 4;
+    loop {
+        match args.get(i).map(|a| a.to_ascii_uppercase()) {
+            Some(ref a) if a == "COUNT" => {
+                let c = args.get(i + 1).ok_or(CommandError::Syntax)?;
+                count = match parse_count(c)? {
+                    0 => usize::MAX,
+                    c => c,
+                };
+                i += 2;
+            },
+            Some(ref a) if a == "STREAMS" => break,
+            _ => return Err(CommandError::Syntax),
+        }
+    }
+
+    let streams = &args[i + 1..];
+    if streams.is_empty() || !streams.len().is_multiple_of(2) {
+        return Err(CommandError::Syntax);
+    }
+    let (keys, ids) = streams.split_at(streams.len() / 2);
+
+    let now = now_ms();
+    let mut replies = vec![];
+    let mut delivered_new = false;
+    let mut read_history = false;
+
+    for (key, id) in keys.iter().zip(ids) {
+        let stream = get_stream_mut(store, key)?.ok_or(CommandError::NoGroup)?;
+
+        let entries: Vec<String> = if id == ">" {
+            let entries = stream.read_new(group, consumer, count, now).ok_or(CommandError::NoGroup)?;
+            if entries.is_empty() {
+                continue;
+            }
+            delivered_new = true;
+            let entries = entries.iter().map(|(id, fields)| to_entry(id, Some(fields))).collect();
+            store.touch(key);
+            entries
+        } else {
+            let after = parse_id(id)?;
+            let entries = stream.read_pending(group, consumer, after, count).ok_or(CommandError::NoGroup)?;
+            read_history = true;
+            entries.iter().map(|(id, fields)| to_entry(id, *fields)).collect()
+        };
+
+        replies.push(RespWriter::to_raw_array(&[
+            RespWriter::to_bulk_string(key),
+            RespWriter::to_raw_array(&entries),
+        ]));
+    }
+
+    if !delivered_new && !read_history {
+        return Ok(Response::KeepAlive(RespWriter::null_array()));
+    }
+
+    Ok(Response::KeepAlive(RespWriter::to_raw_array(&replies)))
+}
+
+/// XACK key group id [id ...]
+pub fn xack(store: &mut Store, args: &[String]) -> Result<Response> {
+    let ids = args[3..].iter().map(|id| parse_id(id)).collect::<Result<Vec<StreamId>>>()?;
+
+    let acked = match get_stream_mut(store, &args[1])? {
+        Some(stream) => stream.ack(&args[2], &ids).unwrap_or(0),
+        None => 0,
+    };
+    if acked > 0 {
+        store.touch(&args[1]);
+    }
+
+    Ok(Response::KeepAlive(RespWriter::to_signed_integer(acked as i64)))
+}
+
+/// XPENDING key group [start end count [consumer]]
+///
+/// Without a range, replies with a summary of the group's pending entries:
+/// their number, the lowest and highest IDs, and the count per consumer.
+/// With a range, replies with the ID, consumer, milliseconds since the last
+/// delivery and delivery count of each pending entry.
+pub fn xpending(store: &Store, args: &[String]) -> Result<Response> {
+    let stream = get_stream(store, &args[1])?.ok_or(CommandError::NoGroup)?;
+    let group = stream.group(&args[2]).ok_or(CommandError::NoGroup)?;
+
+    if args.len() == 3 {
+        let pending = &group.pending;
+        let reply = match (pending.keys().next(), pending.keys().next_back()) {
+            (Some(first), Some(last)) => {
+                let consumers: Vec<String> = group.consumer_counts().iter()
+                    .map(|(consumer, count)| RespWriter::to_array(&[consumer, &count.to_string()]))
+                    .collect();
+                RespWriter::to_raw_array(&[
+                    RespWriter::to_signed_integer(pending.len() as i64),
+                    RespWriter::to_bulk_string(&first.to_string()),
+                    RespWriter::to_bulk_string(&last.to_string()),
+                    RespWriter::to_raw_array(&consumers),
+                ])
+            },
+            _ => RespWriter::to_raw_array(&[
+                RespWriter::to_signed_integer(0),
+                RespWriter::null_bulk_string(),
+                RespWriter::null_bulk_string(),
+                RespWriter::null_array(),
+            ]),
+        };
+        return Ok(Response::KeepAlive(reply));
+    }
+
+    if args.len() != 6 && args.len() != 7 {
+        return Err(CommandError::Syntax);
+    }
+
+    let start = parse_start(&args[3])?;
+    let end = parse_end(&args[4])?;
+    let count = parse_count(&args[5])?;
+    let consumer = args.get(6);
+
+    let now = now_ms();
+    let entries: Vec<String> = group.pending.iter()
+        .filter(|(id, _)| in_range(**id, start, end))
+        .filter(|(_, pending)| consumer.is_none_or(|c| *c == pending.consumer))
+        .take(count)
+        .map(|(id, pending)| RespWriter::to_raw_array(&[
+            RespWriter::to_bulk_string(&id.to_string()),
+            RespWriter::to_bulk_string(&pending.consumer),
+            RespWriter::to_signed_integer(now.saturating_sub(pending.delivery_time) as i64),
+            RespWriter::to_signed_integer(pending.delivery_count as i64),
+        ]))
+        .collect();
+
+    Ok(Response::KeepAlive(RespWriter::to_raw_array(&entries)))
+}
+
+fn in_range(id: StreamId, start: Bound<StreamId>, end: Bound<StreamId>) -> bool {
+    let after_start = match start {
+        Bound::Included(s) => id >= s,
+        Bound::Excluded(s) => id > s,
+        Bound::Unbounded => true,
+    };
+    let before_end = match end {
+        Bound::Included(e) => id <= e,
+        Bound::Excluded(e) => id < e,
+        Bound::Unbounded => true,
+    };
+    after_start && before_end
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use command::test_args as args;
+    use command::test_integer as integer;
+    use command::test_bulk as bulk;
+
+    fn entry(id: &str, value: &str) -> String {
+        RespWriter::to_raw_array(&[
+            RespWriter::to_bulk_string(id),
+            RespWriter::to_array(&["event", value]),
+        ])
+    }
+
+    fn entries(v: &[(&str, &str)]) -> Result<Response> {
+        let v: Vec<String> = v.iter().map(|(id, value)| entry(id, value)).collect();
+        Ok(Response::KeepAlive(RespWriter::to_raw_array(&v)))
+    }
+
+    fn init_stream() -> Store {
+        let mut store = Store::new();
+        for seq in 1..=4 {
+            let command = format!("XADD events 1000-{} event e{}", seq, seq);
+            xadd(&mut store, &args(&command)).unwrap();
+        }
+        store
+    }
+
+    #[test]
+    fn xadd_command() {
+        let mut store = init_stream();
+
+        assert_eq!(xadd(&mut store, &args("XADD events 1000-* event e5")), bulk("1000-5"));
+        assert_eq!(xadd(&mut store, &args("XADD events 2000 event e6")), bulk("2000-0"));
+        assert_eq!(xadd(&mut store, &args("XADD events 2000-0 event x")), Err(CommandError::StreamIdTooSmall));
+        assert_eq!(xadd(&mut store, &args("XADD events abc event x")), Err(CommandError::InvalidStreamId));
+        assert_eq!(
+            xadd(&mut store, &args("XADD events * event")),
+            Err(CommandError::WrongNumberOfArguments("XADD"))
+        );
+
+        let id = match xadd(&mut store, &args("XADD events * event e7")) {
+            Ok(Response::KeepAlive(reply)) => reply,
+            _ => panic!("Unexpected response"),
+        };
+        assert!(id.ends_with("-0\r\n"));
+        assert_eq!(xlen(&store, &args("XLEN events")), integer(7));
+    }
+
+    #[test]
+    fn xadd_without_sequence_left() {
+        let mut store = Store::new();
+        let max = u64::MAX;
+
+        let command = format!("XADD events {}-{} event x", max, max);
+        assert_eq!(xadd(&mut store, &args(&command)), bulk(&format!("{}-{}", max, max)));
+        let command = format!("XADD events {}-* event x", max);
+        assert_eq!(xadd(&mut store, &args(&command)), Err(CommandError::StreamIdTooSmall));
+        assert_eq!(xadd(&mut store, &args("XADD events * event x")), Err(CommandError::StreamIdTooSmall));
+    }
+
+    #[test]
+    fn xadd_invalid_id_does_not_create_stream() {
+        let mut store = Store::new();
+
+        assert_eq!(xadd(&mut store, &args("XADD events 0-0 event x")), Err(CommandError::StreamIdTooSmall));
+        assert!(!store.contains_key("events"));
+    }
+
+    #[test]
+    fn xadd_with_maxlen() {
+        let mut store = init_stream();
+
+        assert_eq!(xadd(&mut store, &args("XADD events MAXLEN ~ 2 1000-5 event e5")), bulk("1000-5"));
+        assert_eq!(xrange(&store, &args("XRANGE events - +")), entries(&[("1000-4", "e4"), ("1000-5", "e5")]));
+    }
+
+    #[test]
+    fn xrange_command() {
+        let store = init_stream();
+
+        assert_eq!(
+            xrange(&store, &args("XRANGE events 1000-2 1000-3")),
+            entries(&[("1000-2", "e2"), ("1000-3", "e3")])
+        );
+        assert_eq!(
+            xrange(&store, &args("XRANGE events (1000-2 + COUNT 1")),
+            entries(&[("1000-3", "e3")])
+        );
+        assert_eq!(xrange(&store, &args("XRANGE events 1000 1000")).map(|_| ()), Ok(()));
+        assert_eq!(
+            xrange(&store, &args("XREVRANGE events + - COUNT 2")),
+            entries(&[("1000-4", "e4"), ("1000-3", "e3")])
+        );
+        assert_eq!(xrange(&store, &args("XRANGE events 1000-3 1000-2")), entries(&[]));
+        assert_eq!(xrange(&store, &args("XRANGE missing - +")), entries(&[]));
+        assert_eq!(xrange(&store, &args("XRANGE events - + LIMIT 1")), Err(CommandError::Syntax));
+    }
+
+    #[test]
+    fn xtrim_command() {
+        let mut store = init_stream();
+
+        assert_eq!(xtrim(&mut store, &args("XTRIM events MAXLEN 3")), integer(1));
+        assert_eq!(xtrim(&mut store, &args("XTRIM events MINID = 1000-4")), integer(2));
+        assert_eq!(xrange(&store, &args("XRANGE events - +")), entries(&[("1000-4", "e4")]));
+        assert_eq!(xtrim(&mut store, &args("XTRIM missing MAXLEN 0")), integer(0));
+        assert_eq!(xtrim(&mut store, &args("XTRIM events SIZE 0")), Err(CommandError::Syntax));
+    }
+
+    #[test]
+    fn xgroup_command() {
+        let mut store = init_stream();
+
+        assert_eq!(xgroup(&mut store, &args("XGROUP CREATE events workers $")), Ok(Response::build_ok()));
+        assert_eq!(xgroup(&mut store, &args("XGROUP CREATE events workers 0")), Err(CommandError::BusyGroup));
+        assert_eq!(xgroup(&mut store, &args("XGROUP CREATE missing workers 0")), Err(CommandError::KeyNotFound));
+        assert_eq!(
+            xgroup(&mut store, &args("XGROUP CREATE created workers 0 MKSTREAM")),
+            Ok(Response::build_ok())
+        );
+        assert_eq!(xlen(&store, &args("XLEN created")), integer(0));
+
+        assert_eq!(xgroup(&mut store, &args("XGROUP DESTROY events workers")), integer(1));
+        assert_eq!(xgroup(&mut store, &args("XGROUP DESTROY events workers")), integer(0));
+    }
+
+    #[test]
+    fn consumers_share_stream() {
+        let mut store = init_stream();
+        xgroup(&mut store, &args("XGROUP CREATE events workers 0")).unwrap();
+
+        let read = xreadgroup(&mut store, &args("XREADGROUP GROUP workers alice COUNT 3 STREAMS events >"));
+        let expected = RespWriter::to_raw_array(&[RespWriter::to_raw_array(&[
+            RespWriter::to_bulk_string("events"),
+            RespWriter::to_raw_array(&[entry("1000-1", "e1"), entry("1000-2", "e2"), entry("1000-3", "e3")]),
+        ])]);
+        assert_eq!(read, Ok(Response::KeepAlive(expected)));
+
+        let read = xreadgroup(&mut store, &args("XREADGROUP GROUP workers bob STREAMS events >"));
+        let expected = RespWriter::to_raw_array(&[RespWriter::to_raw_array(&[
+            RespWriter::to_bulk_string("events"),
+            RespWriter::to_raw_array(&[entry("1000-4", "e4")]),
+        ])]);
+        assert_eq!(read, Ok(Response::KeepAlive(expected)));
+
+        // nothing left to deliver
+        let read = xreadgroup(&mut store, &args("XREADGROUP GROUP workers bob STREAMS events >"));
+        assert_eq!(read, Ok(Response::KeepAlive(RespWriter::null_array())));
+
+        // alice's pending entries
+        let read = xreadgroup(&mut store, &args("XREADGROUP GROUP workers alice COUNT 1 STREAMS events 1000-1"));
+        let expected = RespWriter::to_raw_array(&[RespWriter::to_raw_array(&[
+            RespWriter::to_bulk_string("events"),
+            RespWriter::to_raw_array(&[entry("1000-2", "e2")]),
+        ])]);
+        assert_eq!(read, Ok(Response::KeepAlive(expected)));
+
+        let read = xreadgroup(&mut store, &args("XREADGROUP GROUP missing bob STREAMS events >"));
+        assert_eq!(read, Err(CommandError::NoGroup));
+    }
+
+    #[test]
+    fn xack_and_xpending_commands() {
+        let mut store = init_stream();
+        xgroup(&mut store, &args("XGROUP CREATE events workers 0")).unwrap();
+        xreadgroup(&mut store, &args("XREADGROUP GROUP workers alice COUNT 2 STREAMS events >")).unwrap();
+        xreadgroup(&mut store, &args("XREADGROUP GROUP workers bob COUNT 1 STREAMS events >")).unwrap();
+
+        let expected = RespWriter::to_raw_array(&[
+            RespWriter::to_signed_integer(3),
+            RespWriter::to_bulk_string("1000-1"),
+            RespWriter::to_bulk_string("1000-3"),
+            RespWriter::to_raw_array(&[RespWriter::to_array(&["alice", "2"]), RespWriter::to_array(&["bob", "1"])]),
+        ]);
+        assert_eq!(xpending(&store, &args("XPENDING events workers")), Ok(Response::KeepAlive(expected)));
+
+        assert_eq!(xack(&mut store, &args("XACK events workers 1000-1 1000-3 1000-4")), integer(2));
+        assert_eq!(xack(&mut store, &args("XACK events workers 1000-1")), integer(0));
+
+        let reply = match xpending(&store, &args("XPENDING events workers - + 10 alice")) {
+            Ok(Response::KeepAlive(reply)) => reply,
+            _ => panic!("Unexpected response"),
+        };
+        assert!(reply.starts_with("*1\r\n*4\r\n$6\r\n1000-2\r\n$5\r\nalice\r\n:"));
+        assert!(reply.ends_with(":1\r\n"));
+
+        xack(&mut store, &args("XACK events workers 1000-2")).unwrap();
+        let expected = RespWriter::to_raw_array(&[
+            RespWriter::to_signed_integer(0),
+            RespWriter::null_bulk_string(),
+            RespWriter::null_bulk_string(),
+            RespWriter::null_array(),
+        ]);
+        assert_eq!(xpending(&store, &args("XPENDING events workers")), Ok(Response::KeepAlive(expected)));
+        assert_eq!(xpending(&store, &args("XPENDING events missing")), Err(CommandError::NoGroup));
+    }
+
+    #[test]
+    fn wrong_type() {
+        let mut store = Store::new();
+        store.insert("hello".to_string(), Data::String(b"world".to_vec()));
+
+        assert_eq!(xadd(&mut store, &args("XADD hello * a b")), Err(CommandError::WrongType));
+        assert_eq!(xlen(&store, &args("XLEN hello")), Err(CommandError::WrongType));
+    }
+}
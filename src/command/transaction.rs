@@ -0,0 +1,224 @@
+use std::sync::Arc;
+
+use respwriter::RespWriter;
+use response::Response;
+use session::{Session, Transaction};
+use store::Store;
+
+use super::{CommandError, Result, blocking};
+
+/// MULTI
+///
+/// Starts queueing commands until EXEC or DISCARD.
+pub fn multi(session: &mut Session, _args: &[String]) -> Result<Response> {
+    if session.transaction.is_some() {
+        return Err(CommandError::NestedMulti);
+    }
+
+    session.transaction = Some(Transaction::default());
+    Ok(Response::build_ok())
+}
+
+/// EXEC
+///
+/// Runs the queued commands with every database locked, so no other client
+/// sees them between the commands, and replies with an array of their replies.
+/// A command failing at runtime doesn't stop the others.
+///
+/// Replies with a null array without running anything if a watched key was
+/// modified since WATCH.
+pub fn exec(session: &mut Session, _args: &[String]) -> Result<Response> {
+    let transaction = session.transaction.take().ok_or(CommandError::WithoutMulti("EXEC"))?;
+
+    // A queued command may select another database.
+    let shared = Arc::clone(&session.shared);
+    let mut guards = shared.write_all();
+    let mut stores: Vec<&mut Store> = guards.iter_mut().map(|guard| &mut **guard).collect();
+
+    let changed = session.watched.iter().any(|(db, key, version)| stores[*db].version(key) != *version);
+    for (db, key, _) in session.watched.drain(..) {
+        stores[db].unwatch(&key);
+    }
+
+    if transaction.failed {
+        return Err(CommandError::ExecAbort);
+    }
+    if changed {
+        return Ok(Response::KeepAlive(RespWriter::null_array()));
+    }
+
+    let replies: Vec<String> = transaction.commands.into_iter()
+        .map(|(cmd, args)| match cmd.execute_locked(&mut stores, session, &args) {
+            Response::KeepAlive(reply) | Response::Close(reply) => reply,
+        })
+        .collect();
+    blocking::serve_all(&shared.blocked, &mut stores);
+
+    Ok(Response::KeepAlive(RespWriter::to_raw_array(&replies)))
+}
+
+/// DISCARD
+///
+/// Also stops watching all keys.
+pub fn discard(session: &mut Session, _args: &[String]) -> Result<Response> {
+    session.transaction.take().ok_or(CommandError::WithoutMulti("DISCARD"))?;
+    session.unwatch();
+    Ok(Response::build_ok())
+}
+
+/// WATCH key [key ...]
+///
+/// Makes the next EXEC fail if any of the keys is modified before it.
+pub fn watch(session: &mut Session, args: &[String]) -> Result<Response> {
+    if session.transaction.is_some() {
+        return Err(CommandError::WatchInMulti);
+    }
+
+    let db = session.db;
+    let shared = Arc::clone(&session.shared);
+    let mut store = shared.databases[db].write().unwrap();
+
+    for key in &args[1..] {
+        if !session.watched.iter().any(|(watched_db, watched, _)| *watched_db == db && watched == key) {
+            let version = store.watch(key);
+            session.watched.push((db, key.clone(), version));
+        }
+    }
+
+    Ok(Response::build_ok())
+}
+
+/// UNWATCH
+pub fn unwatch(session: &mut Session, _args: &[String]) -> Result<Response> {
+    // Inside EXEC, the keys were already unwatched before running the queued
+    // commands, so this never locks a database there.
+    session.unwatch();
+
+    Ok(Response::build_ok())
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use command::{lookup, test_args as args, test_session};
+    use session::Shared;
+    use store::Data;
+
+    fn queue(session: &mut Session, line: &str) {
+        let args = args(line);
+        let cmd = lookup(&args[0]).unwrap();
+        session.transaction.as_mut().unwrap().commands.push((cmd, args));
+    }
+
+    #[test]
+    fn exec_runs_queued_commands() {
+        let mut session = test_session(&Arc::new(Shared::new(vec![Store::new(), Store::new()])));
+
+        assert_eq!(multi(&mut session, &args("MULTI")), Ok(Response::build_ok()));
+        assert_eq!(multi(&mut session, &args("MULTI")), Err(CommandError::NestedMulti));
+        queue(&mut session, "SET hello world");
+        queue(&mut session, "LPUSH hello a");
+        queue(&mut session, "GET hello");
+
+        let expected = RespWriter::to_raw_array(&[
+            RespWriter::to_simple_string("OK").unwrap(),
+            RespWriter::to_error(&CommandError::WrongType.to_string()).unwrap(),
+            RespWriter::to_bulk_string("world"),
+        ]);
+        assert_eq!(exec(&mut session, &args("EXEC")), Ok(Response::KeepAlive(expected)));
+        assert!(session.transaction.is_none());
+
+        let store = session.store().read().unwrap();
+        assert_eq!(store.get("hello"), Some(&Data::String(b"world".to_vec())));
+    }
+
+    #[test]
+    fn exec_failed_transaction() {
+        let mut session = test_session(&Arc::new(Shared::new(vec![Store::new(), Store::new()])));
+
+        multi(&mut session, &args("MULTI")).unwrap();
+        queue(&mut session, "SET hello world");
+        session.fail_transaction();
+
+        assert_eq!(exec(&mut session, &args("EXEC")), Err(CommandError::ExecAbort));
+        assert!(session.transaction.is_none());
+        assert_eq!(session.store().read().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn exec_with_watched_keys() {
+        let mut session = test_session(&Arc::new(Shared::new(vec![Store::new(), Store::new()])));
+        let mut other = test_session(&session.shared);
+
+        // unmodified
+        assert_eq!(watch(&mut session, &args("WATCH hello")), Ok(Response::build_ok()));
+        multi(&mut session, &args("MULTI")).unwrap();
+        queue(&mut session, "SET hello world");
+        let expected = RespWriter::to_raw_array(&[RespWriter::to_simple_string("OK").unwrap()]);
+        assert_eq!(exec(&mut session, &args("EXEC")), Ok(Response::KeepAlive(expected)));
+
+        // modified by another client between WATCH and EXEC
+        watch(&mut session, &args("WATCH hello")).unwrap();
+        multi(&mut session, &args("MULTI")).unwrap();
+        assert_eq!(watch(&mut session, &args("WATCH hello")), Err(CommandError::WatchInMulti));
+        queue(&mut session, "SET hello mine");
+        lookup("DESTROY").unwrap().execute(&mut other, &args("DESTROY"));
+        assert_eq!(exec(&mut session, &args("EXEC")), Ok(Response::KeepAlive(RespWriter::null_array())));
+        assert!(session.watched.is_empty());
+        assert_eq!(session.store().read().unwrap().len(), 0);
+
+        // unwatched
+        watch(&mut session, &args("WATCH hello")).unwrap();
+        assert_eq!(unwatch(&mut session, &args("UNWATCH")), Ok(Response::build_ok()));
+        lookup("SET").unwrap().execute(&mut other, &args("SET hello there"));
+        multi(&mut session, &args("MULTI")).unwrap();
+        queue(&mut session, "GET hello");
+        let expected = RespWriter::to_raw_array(&[RespWriter::to_bulk_string("there")]);
+        assert_eq!(exec(&mut session, &args("EXEC")), Ok(Response::KeepAlive(expected)));
+    }
+
+    #[test]
+    fn exec_after_commands_changing_nothing() {
+        let mut session = test_session(&Arc::new(Shared::new(vec![Store::new(), Store::new()])));
+        let mut other = test_session(&session.shared);
+        lookup("RPUSH").unwrap().execute(&mut other, &args("RPUSH list a"));
+        lookup("HSET").unwrap().execute(&mut other, &args("HSET hash n 9223372036854775807"));
+
+        watch(&mut session, &args("WATCH list hash missing")).unwrap();
+        multi(&mut session, &args("MULTI")).unwrap();
+        queue(&mut session, "LLEN list");
+        for line in &["LSET list 5 b", "LREM list 0 b", "HINCRBY hash n 1", "HDEL hash other", "ZADD missing XX 1 a"] {
+            lookup(&args(line)[0]).unwrap().execute(&mut other, &args(line));
+        }
+        let expected = RespWriter::to_raw_array(&[RespWriter::to_signed_integer(1)]);
+        assert_eq!(exec(&mut session, &args("EXEC")), Ok(Response::KeepAlive(expected)));
+    }
+
+    #[test]
+    fn exec_with_select() {
+        let mut session = test_session(&Arc::new(Shared::new(vec![Store::new(), Store::new()])));
+
+        multi(&mut session, &args("MULTI")).unwrap();
+        queue(&mut session, "SELECT 1");
+        queue(&mut session, "SET hello world");
+        exec(&mut session, &args("EXEC")).unwrap();
+
+        assert_eq!(session.db, 1);
+        assert_eq!(session.shared.databases[0].read().unwrap().len(), 0);
+        assert_eq!(session.shared.databases[1].read().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn discard_command() {
+        let mut session = test_session(&Arc::new(Shared::new(vec![Store::new(), Store::new()])));
+
+        multi(&mut session, &args("MULTI")).unwrap();
+        queue(&mut session, "SET hello world");
+
+        assert_eq!(discard(&mut session, &args("DISCARD")), Ok(Response::build_ok()));
+        assert_eq!(discard(&mut session, &args("DISCARD")), Err(CommandError::WithoutMulti("DISCARD")));
+        assert_eq!(exec(&mut session, &args("EXEC")), Err(CommandError::WithoutMulti("EXEC")));
+        assert_eq!(session.store().read().unwrap().len(), 0);
+    }
+}
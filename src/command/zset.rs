@@ -0,0 +1,397 @@
+use respwriter::RespWriter;
+use response::Response;
+use sorted_set::{ScoreBound, SortedSet};
+use store::{Data, Store};
+
+use super::{CommandError, Result, normalize_range, parse_int, to_bulk_string};
+
+fn get_zset<'a>(store: &'a Store, key: &str) -> Result<Option<&'a SortedSet>> {
+    match store.get(key) {
+        Some(Data::SortedSet(zset)) => Ok(Some(zset)),
+        Some(_) => Err(CommandError::WrongType),
+        None => Ok(None),
+    }
+}
+
+fn get_zset_mut<'a>(store: &'a mut Store, key: &str) -> Result<Option<&'a mut SortedSet>> {
+    match store.get_mut(key) {
+        Some(Data::SortedSet(zset)) => Ok(Some(zset)),
+        Some(_) => Err(CommandError::WrongType),
+        None => Ok(None),
+    }
+}
+
+fn zset_entry<'a>(store: &'a mut Store, key: &str) -> Result<&'a mut SortedSet> {
+    match store.get_or_insert_with(key, || Data::SortedSet(SortedSet::new())) {
+        Data::SortedSet(zset) => Ok(zset),
+        _ => Err(CommandError::WrongType),
+    }
+}
+
+fn parse_score(s: &str) -> Result<f64> {
+    match s.parse::<f64>() {
+        Ok(score) if !score.is_nan() => Ok(score),
+        _ => Err(CommandError::NotFloat),
+    }
+}
+
+/// Parses a score range boundary, which is exclusive if prefixed with `(`.
+fn parse_bound(s: &str) -> Result<ScoreBound> {
+    if let Some(rest) = s.strip_prefix('(') {
+        parse_score(rest).map(ScoreBound::Exclusive)
+    } else {
+        parse_score(s).map(ScoreBound::Inclusive)
+    }
+}
+
+fn score_to_bulk_string(score: f64) -> String {
+    RespWriter::to_bulk_string(&score.to_string())
+}
+
+/// Serializes members as an array, each followed by its score if requested.
+fn to_member_array<'a, I>(members: I, with_scores: bool) -> String
+where
+    I: Iterator<Item = (&'a [u8], f64)>,
+{
+    let mut elements = vec![];
+    for (member, score) in members {
+        elements.push(to_bulk_string(member));
+        if with_scores {
+            elements.push(score_to_bulk_string(score));
+        }
+    }
+    RespWriter::to_raw_array(&elements)
+}
+
+/// ZADD key [NX|XX] [CH] score member [score member ...]
+///
+/// NX only adds new members and XX only updates existing ones. Replies with
+/// the number of members added, or with CH, the number added or updated.
+pub fn add(store: &mut Store, args: &[String]) -> Result<Response> {
+    let (mut nx, mut xx, mut ch) = (false, false, false);
+
+    let mut i = 2;
+    while i < args.len() {
+        match args[i].to_ascii_uppercase().as_ref() {
+            "NX" => nx = true,
+            "XX" => xx = true,
+            "CH" => ch = true,
+            _ => break,
+        }
+        i += 1;
+    }
+
+    let pairs = &args[i..];
+    if (nx && xx) || pairs.is_empty() || !pairs.len().is_multiple_of(2) {
+        return Err(CommandError::Syntax);
+    }
+
+    // Validate every score before changing the sorted set.
+    let scores = pairs.iter().step_by(2).map(|s| parse_score(s)).collect::<Result<Vec<f64>>>()?;
+    let members = pairs.iter().skip(1).step_by(2);
+
+    let zset = zset_entry(store, &args[1])?;
+    let mut count = 0;
+    let mut changed = false;
+    for (score, member) in scores.into_iter().zip(members) {
+        let current = zset.score(member.as_bytes());
+        if (nx && current.is_some()) || (xx && current.is_none()) || current == Some(score) {
+            continue;
+        }
+
+        if current.is_none() || ch {
+            count += 1;
+        }
+        zset.insert(member.clone().into_bytes(), score);
+        changed = true;
+    }
+    if changed {
+        store.touch(&args[1]);
+    }
+    store.remove_if_empty(&args[1]);
+
+    Ok(Response::KeepAlive(RespWriter::to_signed_integer(count)))
+}
+
+/// ZREM key member [member ...]
+pub fn rem(store: &mut Store, args: &[String]) -> Result<Response> {
+    let key = &args[1];
+
+    let removed = match get_zset_mut(store, key)? {
+        Some(zset) => args[2..].iter().filter(|member| zset.remove(member.as_bytes())).count(),
+        None => 0,
+    };
+    if removed > 0 {
+        store.touch(key);
+        store.remove_if_empty(key);
+    }
+
+    Ok(Response::KeepAlive(RespWriter::to_signed_integer(removed as i64)))
+}
+
+/// ZSCORE key member
+pub fn score(store: &Store, args: &[String]) -> Result<Response> {
+    let score = get_zset(store, &args[1])?.and_then(|zset| zset.score(args[2].as_bytes()));
+
+    match score {
+        Some(score) => Ok(Response::KeepAlive(score_to_bulk_string(score))),
+        None => Ok(Response::KeepAlive(RespWriter::null_bulk_string())),
+    }
+}
+
+/// ZINCRBY key increment member
+pub fn incrby(store: &mut Store, args: &[String]) -> Result<Response> {
+    let increment = parse_score(&args[2])?;
+    let member = args[3].as_bytes();
+
+    let zset = zset_entry(store, &args[1])?;
+    let score = zset.score(member).unwrap_or(0.0) + increment;
+    if score.is_nan() {
+        store.remove_if_empty(&args[1]);
+        return Err(CommandError::NotFloat);
+    }
+    zset.insert(member.to_vec(), score);
+    store.touch(&args[1]);
+
+    Ok(Response::KeepAlive(score_to_bulk_string(score)))
+}
+
+/// ZRANK key member
+pub fn rank(store: &Store, args: &[String]) -> Result<Response> {
+    let rank = get_zset(store, &args[1])?.and_then(|zset| zset.rank(args[2].as_bytes()));
+
+    match rank {
+        Some(rank) => Ok(Response::KeepAlive(RespWriter::to_signed_integer(rank as i64))),
+        None => Ok(Response::KeepAlive(RespWriter::null_bulk_string())),
+    }
+}
+
+/// ZCARD key
+pub fn card(store: &Store, args: &[String]) -> Result<Response> {
+    let len = get_zset(store, &args[1])?.map_or(0, |zset| zset.len());
+    Ok(Response::KeepAlive(RespWriter::to_signed_integer(len as i64)))
+}
+
+/// ZRANGE key start stop [WITHSCORES]
+/// ZREVRANGE key start stop [WITHSCORES]
+///
+/// The indexes of ZREVRANGE count from the member with the highest score.
+pub fn range(store: &Store, args: &[String]) -> Result<Response> {
+    let start = parse_int(&args[2])?;
+    let stop = parse_int(&args[3])?;
+
+    let with_scores = match args.get(4) {
+        Some(option) if args.len() == 5 && option.eq_ignore_ascii_case("WITHSCORES") => true,
+        Some(_) => return Err(CommandError::Syntax),
+        None => false,
+    };
+
+    let zset = match get_zset(store, &args[1])? {
+        Some(zset) => zset,
+        None => return Ok(Response::KeepAlive(RespWriter::to_raw_array(&[]))),
+    };
+
+    let (start, stop) = match normalize_range(start, stop, zset.len()) {
+        Some(range) => range,
+        None => return Ok(Response::KeepAlive(RespWriter::to_raw_array(&[]))),
+    };
+
+    let count = stop - start + 1;
+    let reply = if args[0] == "ZREVRANGE" {
+        to_member_array(zset.iter().rev().skip(start).take(count), with_scores)
+    } else {
+        to_member_array(zset.iter().skip(start).take(count), with_scores)
+    };
+
+    Ok(Response::KeepAlive(reply))
+}
+
+/// ZRANGEBYSCORE key min max [WITHSCORES] [LIMIT offset count]
+///
+/// The boundaries are inclusive unless prefixed with `(`, and may be `-inf`
+/// or `+inf`. A negative LIMIT count returns all remaining members.
+pub fn range_by_score(store: &Store, args: &[String]) -> Result<Response> {
+    let min = parse_bound(&args[2])?;
+    let max = parse_bound(&args[3])?;
+
+    let mut with_scores = false;
+    let mut offset = 0;
+    let mut count = usize::MAX;
+
+    let mut i = 4;
+    while i < args.len() {
+        match args[i].to_ascii_uppercase().as_ref() {
+            "WITHSCORES" => with_scores = true,
+            "LIMIT" if i + 2 < args.len() => {
+                let o = parse_int(&args[i + 1])?;
+                let c = parse_int(&args[i + 2])?;
+                if o < 0 {
+                    return Ok(Response::KeepAlive(RespWriter::to_raw_array(&[])));
+                }
+                offset = o as usize;
+                count = if c < 0 { usize::MAX } else { c as usize };
+                i += 2;
+            },
+            _ => return Err(CommandError::Syntax),
+        }
+        i += 1;
+    }
+
+    let reply = match get_zset(store, &args[1])? {
+        Some(zset) => {
+            let members = zset.range_by_score(min, max).skip(offset).take(count);
+            to_member_array(members, with_scores)
+        },
+        None => RespWriter::to_raw_array(&[]),
+    };
+
+    Ok(Response::KeepAlive(reply))
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use command::test_args as args;
+    use command::test_integer as integer;
+    use command::test_bulk as bulk;
+    use command::test_nil as nil;
+    use command::test_array as array;
+
+    fn init_leaderboard() -> Store {
+        let mut store = Store::new();
+        add(&mut store, &args("ZADD board 100 carol 50 alice 100 bob 75 dave")).unwrap();
+        store
+    }
+
+    #[test]
+    fn add_command() {
+        let mut store = init_leaderboard();
+
+        assert_eq!(add(&mut store, &args("ZADD board 10 alice 20 erin")), integer(1));
+        assert_eq!(score(&store, &args("ZSCORE board alice")), bulk("10"));
+
+        assert_eq!(add(&mut store, &args("ZADD board NX 0 alice 30 frank")), integer(1));
+        assert_eq!(score(&store, &args("ZSCORE board alice")), bulk("10"));
+
+        assert_eq!(add(&mut store, &args("ZADD board XX 1 alice 1 gina")), integer(0));
+        assert_eq!(score(&store, &args("ZSCORE board alice")), bulk("1"));
+        assert_eq!(score(&store, &args("ZSCORE board gina")), nil());
+
+        assert_eq!(add(&mut store, &args("ZADD board CH 2 alice 20 erin 20 frank")), integer(2));
+        assert_eq!(card(&store, &args("ZCARD board")), integer(6));
+    }
+
+    #[test]
+    fn add_invalid_arguments() {
+        let mut store = init_leaderboard();
+
+        assert_eq!(add(&mut store, &args("ZADD board 1 a 2")), Err(CommandError::Syntax));
+        assert_eq!(add(&mut store, &args("ZADD board NX XX 1 a")), Err(CommandError::Syntax));
+        assert_eq!(add(&mut store, &args("ZADD board 1 a x b")), Err(CommandError::NotFloat));
+        assert_eq!(add(&mut store, &args("ZADD board nan a")), Err(CommandError::NotFloat));
+
+        // nothing is added if any score is invalid
+        assert_eq!(score(&store, &args("ZSCORE board a")), nil());
+        assert_eq!(add(&mut store, &args("ZADD other 1 a x b")), Err(CommandError::NotFloat));
+        assert!(!store.contains_key("other"));
+    }
+
+    #[test]
+    fn rem_command() {
+        let mut store = init_leaderboard();
+
+        assert_eq!(rem(&mut store, &args("ZREM board alice zed")), integer(1));
+        assert_eq!(rank(&store, &args("ZRANK board alice")), nil());
+        assert_eq!(rem(&mut store, &args("ZREM board bob carol dave")), integer(3));
+        assert!(!store.contains_key("board"));
+    }
+
+    #[test]
+    fn incrby_command() {
+        let mut store = init_leaderboard();
+
+        assert_eq!(incrby(&mut store, &args("ZINCRBY board 2.5 alice")), bulk("52.5"));
+        assert_eq!(incrby(&mut store, &args("ZINCRBY board 5 erin")), bulk("5"));
+        assert_eq!(rank(&store, &args("ZRANK board erin")), integer(0));
+
+        incrby(&mut store, &args("ZINCRBY board inf alice")).unwrap();
+        assert_eq!(incrby(&mut store, &args("ZINCRBY board -inf alice")), Err(CommandError::NotFloat));
+        assert_eq!(score(&store, &args("ZSCORE board alice")), bulk("inf"));
+    }
+
+    #[test]
+    fn rank_with_score_ties() {
+        let store = init_leaderboard();
+
+        // bob and carol are tied, so they're ordered by member
+        assert_eq!(rank(&store, &args("ZRANK board alice")), integer(0));
+        assert_eq!(rank(&store, &args("ZRANK board dave")), integer(1));
+        assert_eq!(rank(&store, &args("ZRANK board bob")), integer(2));
+        assert_eq!(rank(&store, &args("ZRANK board carol")), integer(3));
+        assert_eq!(rank(&store, &args("ZRANK missing bob")), nil());
+    }
+
+    #[test]
+    fn range_command() {
+        let store = init_leaderboard();
+
+        assert_eq!(range(&store, &args("ZRANGE board 0 -1")), array(&["alice", "dave", "bob", "carol"]));
+        assert_eq!(range(&store, &args("ZREVRANGE board 0 1")), array(&["carol", "bob"]));
+        assert_eq!(
+            range(&store, &args("ZRANGE board 1 2 WITHSCORES")),
+            array(&["dave", "75", "bob", "100"])
+        );
+        assert_eq!(
+            range(&store, &args("ZREVRANGE board -1 -1 withscores")),
+            array(&["alice", "50"])
+        );
+        assert_eq!(range(&store, &args("ZRANGE board 5 10")), array(&[]));
+        assert_eq!(range(&store, &args("ZRANGE missing 0 -1")), array(&[]));
+        assert_eq!(range(&store, &args("ZRANGE board 0 -1 SCORES")), Err(CommandError::Syntax));
+    }
+
+    #[test]
+    fn range_by_score_command() {
+        let store = init_leaderboard();
+
+        assert_eq!(
+            range_by_score(&store, &args("ZRANGEBYSCORE board 75 100")),
+            array(&["dave", "bob", "carol"])
+        );
+        assert_eq!(
+            range_by_score(&store, &args("ZRANGEBYSCORE board (75 +inf WITHSCORES")),
+            array(&["bob", "100", "carol", "100"])
+        );
+        assert_eq!(
+            range_by_score(&store, &args("ZRANGEBYSCORE board -inf (100")),
+            array(&["alice", "dave"])
+        );
+        assert_eq!(
+            range_by_score(&store, &args("ZRANGEBYSCORE board -inf +inf LIMIT 1 2")),
+            array(&["dave", "bob"])
+        );
+        assert_eq!(
+            range_by_score(&store, &args("ZRANGEBYSCORE board -inf +inf LIMIT 3 -1")),
+            array(&["carol"])
+        );
+        assert_eq!(range_by_score(&store, &args("ZRANGEBYSCORE board 200 100")), array(&[]));
+        assert_eq!(
+            range_by_score(&store, &args("ZRANGEBYSCORE board a 100")),
+            Err(CommandError::NotFloat)
+        );
+        assert_eq!(
+            range_by_score(&store, &args("ZRANGEBYSCORE board 0 100 LIMIT 1")),
+            Err(CommandError::Syntax)
+        );
+    }
+
+    #[test]
+    fn wrong_type() {
+        let mut store = Store::new();
+        store.insert("hello".to_string(), Data::String(b"world".to_vec()));
+
+        assert_eq!(add(&mut store, &args("ZADD hello 1 a")), Err(CommandError::WrongType));
+        assert_eq!(range(&store, &args("ZRANGE hello 0 -1")), Err(CommandError::WrongType));
+    }
+}
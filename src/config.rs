@@ -1,35 +1,580 @@
+//! The server configuration, from command-line flags and a config file.
+//!
+//! A config file has one `directive value` per line, named like the flags
+//! without the dashes, e.g. `port 6364` or `requirepass "two words"`. Empty
+//! lines and lines starting with `#` are ignored. Flags override the file.
+//!
+//! While the server runs, CONFIG GET reads the settings, CONFIG SET changes
+//! the ones that can change live and CONFIG REWRITE writes them back to the
+//! file.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
 use std::net::IpAddr;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use clap::ArgMatches;
+
+use eviction::Policy;
+
+/// An invalid option, which stops the program from starting.
+#[derive(Debug, PartialEq)]
+pub struct ConfigError(String);
+
+impl ConfigError {
+    pub fn new(message: String) -> Self {
+        ConfigError(message)
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Error for ConfigError {}
+
+/// The options given with flags and in the config file passed with
+/// `--config`, if any.
+pub struct Options<'a> {
+    matches: &'a ArgMatches<'a>,
+
+    /// The directives of the config file, with where they were set.
+    file: HashMap<String, (String, String)>,
+
+    /// The directives and the names of the matching arguments.
+    directives: &'static [(&'static str, &'static str)],
+}
+
+impl<'a> Options<'a> {
+    pub fn new(matches: &'a ArgMatches<'a>, directives: &'static [(&'static str, &'static str)]) -> Result<Self, ConfigError> {
+        let file = match matches.value_of("CONFIG") {
+            Some(path) => parse_file(Path::new(path), directives)?,
+            None => HashMap::new(),
+        };
+        Ok(Options { matches, file, directives })
+    }
+
+    /// Returns the value of a directive, and where it was set for errors.
+    fn get(&self, directive: &str) -> Option<(&str, String)> {
+        let arg = self.directives.iter().find(|&&(name, _)| name == directive).map(|&(_, arg)| arg)?;
+        match self.matches.value_of(arg) {
+            Some(value) => Some((value, format!("--{}", directive))),
+            None => self.file.get(directive).map(|(value, source)| (value.as_ref(), source.clone())),
+        }
+    }
+
+    pub fn is_set(&self, directive: &str) -> bool {
+        self.get(directive).is_some()
+    }
+
+    pub fn string(&self, directive: &str) -> Option<String> {
+        self.get(directive).map(|(value, _)| value.to_string())
+    }
+
+    pub fn path(&self, directive: &str) -> Option<PathBuf> {
+        self.get(directive).map(|(value, _)| PathBuf::from(value))
+    }
+
+    pub fn parse<T: FromStr>(&self, directive: &str) -> Result<Option<T>, ConfigError> {
+        self.parse_with(directive, |value| value.parse().ok())
+    }
+
+    /// Parses a value, where `parse` returns None if it's invalid.
+    pub fn parse_with<T, F>(&self, directive: &str, parse: F) -> Result<Option<T>, ConfigError>
+    where
+        F: FnOnce(&str) -> Option<T>,
+    {
+        match self.get(directive) {
+            Some((value, source)) => match parse(value) {
+                Some(parsed) => Ok(Some(parsed)),
+                None => Err(ConfigError(format!("Invalid value '{}' for {}", value, source))),
+            },
+            None => Ok(None),
+        }
+    }
 
-use clap::{ArgMatches, ErrorKind};
+    /// Returns whether a switch is on: given as a flag without value, or as
+    /// `yes` or `no` in the file.
+    pub fn flag(&self, directive: &str) -> Result<bool, ConfigError> {
+        let arg = self.directives.iter().find(|&&(name, _)| name == directive).map(|&(_, arg)| arg);
+        if arg.is_some_and(|arg| self.matches.is_present(arg)) {
+            return Ok(true);
+        }
 
-#[derive(Debug)]
+        match self.file.get(directive) {
+            Some((value, _)) if value == "yes" => Ok(true),
+            Some((value, _)) if value == "no" => Ok(false),
+            Some((value, source)) => Err(ConfigError(format!("Invalid value '{}' for {}, expected yes or no", value, source))),
+            None => Ok(false),
+        }
+    }
+
+    /// Fails if the directive is set without the other one.
+    pub fn require(&self, directive: &str, required: &str) -> Result<(), ConfigError> {
+        if self.is_set(directive) && !self.is_set(required) {
+            return Err(ConfigError(format!("{} requires {} to be set", directive, required)));
+        }
+        Ok(())
+    }
+}
+
+/// Parses a config file into the value of each directive and where it was
+/// set. A directive set more than once takes the last value.
+fn parse_file(path: &Path, directives: &[(&str, &str)]) -> Result<HashMap<String, (String, String)>, ConfigError> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| ConfigError(format!("Couldn't read config file {}: {}", path.display(), e)))?;
+
+    let mut file = HashMap::new();
+    for (i, line) in contents.lines().enumerate() {
+        let source = format!("{}:{}", path.display(), i + 1);
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (name, value) = match line.find(char::is_whitespace) {
+            Some(i) => (&line[..i], line[i..].trim()),
+            None => return Err(ConfigError(format!("{}: Missing value for '{}'", source, line))),
+        };
+        if !directives.iter().any(|&(directive, _)| directive == name) {
+            return Err(ConfigError(format!("{}: Unknown directive '{}'", source, name)));
+        }
+
+        let value = if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+            &value[1..value.len() - 1]
+        } else {
+            value
+        };
+        file.insert(name.to_string(), (value.to_string(), source));
+    }
+    Ok(file)
+}
+
+/// The directives of the server and the names of the matching arguments.
+const DIRECTIVES: &[(&str, &str)] = &[
+    ("port", "PORT"),
+    ("bind", "IP"),
+    ("pidfile", "PIDFILE"),
+    ("databases", "DATABASES"),
+    ("requirepass", "REQUIREPASS"),
+    ("aclfile", "ACLFILE"),
+    ("tls-port", "TLS_PORT"),
+    ("tls-cert-file", "TLS_CERT_FILE"),
+    ("tls-key-file", "TLS_KEY_FILE"),
+    ("tls-ca-cert-file", "TLS_CA_CERT_FILE"),
+    ("unixsocket", "UNIXSOCKET"),
+    ("unixsocketperm", "UNIXSOCKETPERM"),
+    ("timeout", "TIMEOUT"),
+    ("maxmemory", "MAXMEMORY"),
+    ("maxmemory-policy", "MAXMEMORY_POLICY"),
+    ("maxmemory-samples", "MAXMEMORY_SAMPLES"),
+    ("metrics-port", "METRICS_PORT"),
+    ("slowlog-log-slower-than", "SLOWLOG_LOG_SLOWER_THAN"),
+    ("slowlog-max-len", "SLOWLOG_MAX_LEN"),
+];
+
+#[derive(Clone, Debug)]
 pub struct Config {
+    /// The config file passed with `--config`, which CONFIG REWRITE updates.
+    pub file: Option<PathBuf>,
+
     pub ip: IpAddr,
     pub port: u16,
     pub pidfile: Option<PathBuf>,
+
+    /// The number of databases, selected with SELECT.
+    pub databases: usize,
+
+    /// The password clients must send with AUTH before running commands.
+    pub requirepass: Option<String>,
+
+    /// The file to load users from, with one `user <name> <rule> ...` line
+    /// per user.
+    pub aclfile: Option<PathBuf>,
+
+    pub tls: Option<TlsConfig>,
+
+    /// The path of a Unix socket to also listen on.
+    pub unixsocket: Option<PathBuf>,
+
+    /// The permissions of the Unix socket file, e.g. 0o770.
+    pub unixsocketperm: Option<u32>,
+
+    /// The number of seconds after which idle clients are disconnected, or 0
+    /// to never disconnect them.
+    pub timeout: u64,
+
+    /// The number of bytes the keys and values may use, or 0 for no limit.
+    pub maxmemory: usize,
+
+    /// What to do when a write needs more memory than `maxmemory`.
+    pub maxmemory_policy: Policy,
+
+    /// The number of keys sampled in each database to pick one to evict.
+    pub maxmemory_samples: usize,
+
+    /// The port of the HTTP listener serving Prometheus metrics, if any.
+    pub metrics_port: Option<u16>,
+
+    /// Commands taking at least this many microseconds are added to the slow
+    /// log. 0 logs every command and a negative value none.
+    pub slowlog_log_slower_than: i64,
+
+    /// The number of entries the slow log keeps.
+    pub slowlog_max_len: usize,
+}
+
+/// The settings of the TLS listener, which runs alongside the plain one.
+#[derive(Clone, Debug)]
+pub struct TlsConfig {
+    pub port: u16,
+    pub cert_file: PathBuf,
+    pub key_file: PathBuf,
+
+    /// If set, clients must present a certificate signed by this CA.
+    pub ca_cert_file: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            file: None,
+            ip: "127.0.0.1".parse().unwrap(),
+            port: 6363,
+            pidfile: None,
+            databases: 16,
+            requirepass: None,
+            aclfile: None,
+            tls: None,
+            unixsocket: None,
+            unixsocketperm: None,
+            timeout: 0,
+            maxmemory: 0,
+            maxmemory_policy: Policy::NoEviction,
+            maxmemory_samples: 5,
+            metrics_port: None,
+            slowlog_log_slower_than: 10_000,
+            slowlog_max_len: 128,
+        }
+    }
 }
 
 impl Config {
-    pub fn new(matches: ArgMatches) -> Self {
-        let port = value_t!(matches, "PORT", u16).unwrap_or_else(|e| {
-            if e.kind == ErrorKind::ValueValidation {
-                println!("Specified port value is invalid, using default 6363.");
+    pub fn new(matches: ArgMatches) -> Result<Self, ConfigError> {
+        let options = Options::new(&matches, DIRECTIVES)?;
+        let defaults = Config::default();
+
+        let file = matches.value_of("CONFIG").map(PathBuf::from);
+        let port = options.parse("port")?.unwrap_or(defaults.port);
+        let ip = options.parse("bind")?.unwrap_or(defaults.ip);
+        let pidfile = options.path("pidfile");
+        let databases = options.parse_with("databases", |n| n.parse().ok().filter(|&n| n > 0))?.unwrap_or(defaults.databases);
+        let requirepass = options.string("requirepass");
+        let aclfile = options.path("aclfile");
+
+        for directive in &["tls-cert-file", "tls-key-file", "tls-ca-cert-file"] {
+            options.require(directive, "tls-port")?;
+        }
+        let tls = match options.parse("tls-port")? {
+            Some(port) => {
+                options.require("tls-port", "tls-cert-file")?;
+                options.require("tls-port", "tls-key-file")?;
+                Some(TlsConfig {
+                    port,
+                    cert_file: options.path("tls-cert-file").unwrap(),
+                    key_file: options.path("tls-key-file").unwrap(),
+                    ca_cert_file: options.path("tls-ca-cert-file"),
+                })
+            },
+            None => None,
+        };
+
+        options.require("unixsocketperm", "unixsocket")?;
+        let unixsocket = options.path("unixsocket");
+        let unixsocketperm = options.parse_with("unixsocketperm", |perm| {
+            u32::from_str_radix(perm, 8).ok().filter(|&mode| mode <= 0o777)
+        })?;
+        let timeout = options.parse("timeout")?.unwrap_or(defaults.timeout);
+        let maxmemory = options.parse_with("maxmemory", parse_memory)?.unwrap_or(defaults.maxmemory);
+        let maxmemory_policy = options.parse("maxmemory-policy")?.unwrap_or(defaults.maxmemory_policy);
+        let maxmemory_samples = options.parse_with("maxmemory-samples", parse_samples)?.unwrap_or(defaults.maxmemory_samples);
+        let metrics_port = options.parse("metrics-port")?;
+        let slowlog_log_slower_than = options.parse("slowlog-log-slower-than")?.unwrap_or(defaults.slowlog_log_slower_than);
+        let slowlog_max_len = options.parse("slowlog-max-len")?.unwrap_or(defaults.slowlog_max_len);
+
+        Ok(Config {
+            file, ip, port, pidfile, databases, requirepass, aclfile, tls, unixsocket, unixsocketperm, timeout,
+            maxmemory, maxmemory_policy, maxmemory_samples, metrics_port, slowlog_log_slower_than, slowlog_max_len,
+        })
+    }
+
+    /// Returns the value of every directive, in the order of the directives,
+    /// with an empty string for the ones that aren't set.
+    pub fn values(&self) -> Vec<(&'static str, String)> {
+        let path = |path: &Option<PathBuf>| path.as_ref().map_or(String::new(), |path| path.display().to_string());
+        let tls = self.tls.as_ref();
+
+        vec![
+            ("port", self.port.to_string()),
+            ("bind", self.ip.to_string()),
+            ("pidfile", path(&self.pidfile)),
+            ("databases", self.databases.to_string()),
+            ("requirepass", self.requirepass.clone().unwrap_or_default()),
+            ("aclfile", path(&self.aclfile)),
+            ("tls-port", tls.map_or(String::new(), |tls| tls.port.to_string())),
+            ("tls-cert-file", tls.map_or(String::new(), |tls| tls.cert_file.display().to_string())),
+            ("tls-key-file", tls.map_or(String::new(), |tls| tls.key_file.display().to_string())),
+            ("tls-ca-cert-file", path(&tls.and_then(|tls| tls.ca_cert_file.clone()))),
+            ("unixsocket", path(&self.unixsocket)),
+            ("unixsocketperm", self.unixsocketperm.map_or(String::new(), |mode| format!("{:o}", mode))),
+            ("timeout", self.timeout.to_string()),
+            ("maxmemory", self.maxmemory.to_string()),
+            ("maxmemory-policy", self.maxmemory_policy.to_string()),
+            ("maxmemory-samples", self.maxmemory_samples.to_string()),
+            ("metrics-port", self.metrics_port.map_or(String::new(), |port| port.to_string())),
+            ("slowlog-log-slower-than", self.slowlog_log_slower_than.to_string()),
+            ("slowlog-max-len", self.slowlog_max_len.to_string()),
+        ]
+    }
+
+    /// Changes a directive that can change while the server runs. The
+    /// others need a restart.
+    pub fn set(&mut self, directive: &str, value: &str) -> Result<(), ConfigError> {
+        let invalid = || ConfigError(format!("Invalid value '{}' for {}", value, directive));
+        match directive {
+            "requirepass" if value.is_empty() => self.requirepass = None,
+            "requirepass" => self.requirepass = Some(value.to_string()),
+            "timeout" => self.timeout = value.parse().map_err(|_| invalid())?,
+            "maxmemory" => self.maxmemory = parse_memory(value).ok_or_else(invalid)?,
+            "maxmemory-policy" => self.maxmemory_policy = value.parse().map_err(|_| invalid())?,
+            "maxmemory-samples" => self.maxmemory_samples = parse_samples(value).ok_or_else(invalid)?,
+            "slowlog-log-slower-than" => self.slowlog_log_slower_than = value.parse().map_err(|_| invalid())?,
+            "slowlog-max-len" => self.slowlog_max_len = value.parse().map_err(|_| invalid())?,
+            _ if DIRECTIVES.iter().any(|&(name, _)| name == directive) => {
+                return Err(ConfigError(format!("Can't set {} while the server is running", directive)));
+            },
+            _ => return Err(ConfigError(format!("Unknown directive '{}'", directive))),
+        }
+        Ok(())
+    }
+
+    /// Writes the current settings to the config file. Directives already in
+    /// the file are updated in place, keeping comments and order, and the
+    /// others are appended if they differ from the default.
+    pub fn rewrite(&self) -> Result<(), ConfigError> {
+        let path = self.file.as_ref()
+            .ok_or_else(|| ConfigError("The server is running without a config file".to_string()))?;
+        let contents = fs::read_to_string(path).unwrap_or_default();
+
+        let values = self.values();
+        let defaults = Config::default().values();
+        let mut written = Vec::new();
+        let mut lines = Vec::new();
+
+        for line in contents.lines() {
+            let name = line.split_whitespace().next().unwrap_or("");
+            match values.iter().find(|&&(directive, _)| directive == name) {
+                // A directive set more than once is only written once.
+                Some(&(directive, ref value)) => {
+                    if !written.contains(&directive) && !value.is_empty() {
+                        lines.push(format_directive(directive, value));
+                    }
+                    written.push(directive);
+                },
+                None => lines.push(line.to_string()),
             }
-            6363
-        });
+        }
 
-        let ip = value_t!(matches, "IP", IpAddr).unwrap_or_else(|e| {
-            if e.kind == ErrorKind::ValueValidation {
-                println!("Specified IP address is invalid, using default 127.0.0.1.");
+        for (&(directive, ref value), (_, default)) in values.iter().zip(&defaults) {
+            if !written.contains(&directive) && value != default {
+                lines.push(format_directive(directive, value));
             }
-            "127.0.0.1".parse().unwrap()
-        });
+        }
+
+        // Replaces the file at once, so it's never left half written.
+        let temp = path.with_extension("rewrite.tmp");
+        let mut contents = lines.join("\n");
+        contents.push('\n');
+        fs::write(&temp, contents)
+            .and_then(|_| fs::rename(&temp, path))
+            .map_err(|e| ConfigError(format!("Couldn't write config file {}: {}", path.display(), e)))
+    }
+}
+
+/// Parses a number of bytes with an optional unit, as in `100mb`. k, m and g
+/// are powers of 1000, and kb, mb and gb powers of 1024.
+fn parse_memory(value: &str) -> Option<usize> {
+    const UNITS: &[(&str, usize)] = &[
+        ("kb", 1 << 10), ("mb", 1 << 20), ("gb", 1 << 30),
+        ("k", 1_000), ("m", 1_000_000), ("g", 1_000_000_000), ("b", 1),
+    ];
+
+    let value = value.to_lowercase();
+    let (number, unit) = match UNITS.iter().find(|&&(suffix, _)| value.ends_with(suffix)) {
+        Some(&(suffix, unit)) => (&value[..value.len() - suffix.len()], unit),
+        None => (&value[..], 1),
+    };
+    number.parse::<usize>().ok()?.checked_mul(unit)
+}
+
+fn parse_samples(value: &str) -> Option<usize> {
+    value.parse().ok().filter(|&n| n > 0)
+}
+
+/// Formats a line of a config file, quoting values with spaces.
+fn format_directive(directive: &str, value: &str) -> String {
+    if value.contains(char::is_whitespace) {
+        format!("{} \"{}\"", directive, value)
+    } else {
+        format!("{} {}", directive, value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use clap::{App, Arg};
+    use std::env;
+    use std::process;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static NEXT_FILE: AtomicUsize = AtomicUsize::new(0);
+
+    fn temp_file(contents: &str) -> PathBuf {
+        let n = NEXT_FILE.fetch_add(1, Ordering::Relaxed);
+        let path = env::temp_dir().join(format!("hanbaiki-{}-{}.conf", process::id(), n));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    /// Parses the arguments with a config file holding `contents`.
+    fn config(args: &[&str], contents: &str) -> Result<Config, ConfigError> {
+        let path = temp_file(contents);
+
+        let mut app = App::new("test").arg(Arg::with_name("CONFIG").long("config").takes_value(true));
+        for &(directive, arg) in DIRECTIVES {
+            app = app.arg(Arg::with_name(arg).long(directive).takes_value(true));
+        }
+        let mut argv = vec!["test".to_string(), "--config".to_string(), path.to_str().unwrap().to_string()];
+        argv.extend(args.iter().map(|arg| arg.to_string()));
+
+        let config = Config::new(app.get_matches_from(argv));
+        fs::remove_file(&path).unwrap();
+        config
+    }
+
+    #[test]
+    fn defaults() {
+        let config = config(&[], "# nothing\n\n").unwrap();
+        assert_eq!(config.port, 6363);
+        assert_eq!(config.ip, "127.0.0.1".parse::<IpAddr>().unwrap());
+        assert_eq!(config.databases, 16);
+        assert!(config.tls.is_none());
+    }
+
+    #[test]
+    fn file_and_flags() {
+        let contents = "port 7000\ndatabases 4\nrequirepass \"two words\"\nunixsocket /tmp/h.sock\nunixsocketperm 770\n";
+        let config = config(&["--port", "7001"], contents).unwrap();
+        assert_eq!(config.port, 7001);
+        assert_eq!(config.databases, 4);
+        assert_eq!(config.requirepass, Some("two words".to_string()));
+        assert_eq!(config.unixsocketperm, Some(0o770));
+    }
+
+    #[test]
+    fn invalid_values() {
+        let error = config(&["--port", "abc"], "").unwrap_err();
+        assert_eq!(error.to_string(), "Invalid value 'abc' for --port");
+
+        let error = config(&["--databases", "0"], "").unwrap_err();
+        assert_eq!(error.to_string(), "Invalid value '0' for --databases");
+
+        let error = config(&["--pidfile", "x"], "\nunixsocket /tmp/h.sock\nunixsocketperm 999\n").unwrap_err();
+        assert!(error.to_string().starts_with("Invalid value '999' for "));
+        assert!(error.to_string().ends_with(".conf:3"));
+
+        let error = config(&["--tls-port", "7002"], "tls-cert-file cert.pem").unwrap_err();
+        assert_eq!(error.to_string(), "tls-port requires tls-key-file to be set");
+    }
+
+    #[test]
+    fn invalid_file() {
+        let error = config(&["--bind", "0.0.0.0"], "bogus 1\n").unwrap_err();
+        assert!(error.to_string().ends_with(".conf:1: Unknown directive 'bogus'"));
+
+        let error = config(&["--bind", "::1"], "port\n").unwrap_err();
+        assert!(error.to_string().ends_with(".conf:1: Missing value for 'port'"));
+    }
+
+    #[test]
+    fn set_live_directives() {
+        let mut config = Config::default();
+        assert_eq!(config.set("timeout", "300"), Ok(()));
+        assert_eq!(config.timeout, 300);
+        assert_eq!(config.set("requirepass", "secret"), Ok(()));
+        assert_eq!(config.requirepass, Some("secret".to_string()));
+        assert_eq!(config.set("requirepass", ""), Ok(()));
+        assert_eq!(config.requirepass, None);
+
+        let error = config.set("timeout", "-1").unwrap_err();
+        assert_eq!(error.to_string(), "Invalid value '-1' for timeout");
+        let error = config.set("port", "7000").unwrap_err();
+        assert_eq!(error.to_string(), "Can't set port while the server is running");
+        let error = config.set("bogus", "1").unwrap_err();
+        assert_eq!(error.to_string(), "Unknown directive 'bogus'");
+    }
+
+    #[test]
+    fn memory_units() {
+        assert_eq!(parse_memory("100"), Some(100));
+        assert_eq!(parse_memory("1k"), Some(1000));
+        assert_eq!(parse_memory("2KB"), Some(2048));
+        assert_eq!(parse_memory("3mb"), Some(3 << 20));
+        assert_eq!(parse_memory("1gb"), Some(1 << 30));
+        assert_eq!(parse_memory("mb"), None);
+        assert_eq!(parse_memory("-1"), None);
+    }
+
+    #[test]
+    fn set_maxmemory() {
+        let mut config = Config::default();
+        assert_eq!(config.set("maxmemory", "10mb"), Ok(()));
+        assert_eq!(config.maxmemory, 10 << 20);
+        assert_eq!(config.set("maxmemory-policy", "allkeys-lfu"), Ok(()));
+        assert_eq!(config.maxmemory_policy, Policy::AllKeysLfu);
+
+        let error = config.set("maxmemory-policy", "sometimes").unwrap_err();
+        assert_eq!(error.to_string(), "Invalid value 'sometimes' for maxmemory-policy");
+        assert_eq!(config.set("maxmemory-policy", "volatile-lru"), Ok(()));
+        assert_eq!(config.maxmemory_policy, Policy::VolatileLru);
+        let error = config.set("maxmemory-samples", "0").unwrap_err();
+        assert_eq!(error.to_string(), "Invalid value '0' for maxmemory-samples");
+    }
+
+    #[test]
+    fn rewrite_file() {
+        let path = temp_file("# the port\nport 7000\n\ntimeout 10\nrequirepass old\ntimeout 20\n");
+        let config = Config {
+            file: Some(path.clone()),
+            port: 7000,
+            timeout: 60,
+            unixsocket: Some(PathBuf::from("/tmp/a b.sock")),
+            ..Config::default()
+        };
 
-        let pidfile = matches
-            .value_of("PIDFILE")
-            .and_then(|v| Some(PathBuf::from(v)));
+        config.rewrite().unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(contents, "# the port\nport 7000\n\ntimeout 60\nunixsocket \"/tmp/a b.sock\"\n");
 
-        Config { ip, port, pidfile }
+        let error = Config::default().rewrite().unwrap_err();
+        assert_eq!(error.to_string(), "The server is running without a config file");
     }
 }
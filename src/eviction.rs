@@ -0,0 +1,227 @@
+//! Evicting keys when the databases use more memory than `maxmemory`.
+//!
+//! Like Redis, keys aren't ranked exactly: each round samples a few random
+//! keys of every database and evicts the best candidate among them. More
+//! samples get closer to true LRU or LFU, at the cost of speed.
+//!
+//! The used memory is the running total kept by the databases, so checking
+//! it takes no lock. The databases are locked only to evict.
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::Ordering;
+
+use rand::{self, Rng};
+
+use session::Shared;
+use store::KeySample;
+
+/// Which keys to evict when memory is full.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Policy {
+    /// Rejects writes instead of evicting.
+    NoEviction,
+
+    /// Evicts the least recently used keys.
+    AllKeysLru,
+
+    /// Evicts the least frequently used keys.
+    AllKeysLfu,
+
+    /// Evicts random keys.
+    AllKeysRandom,
+
+    /// Evicts the least recently used keys among those with an expiry. Keys
+    /// can't expire yet, so it rejects writes like `NoEviction`, as Redis
+    /// does when no key has an expiry.
+    VolatileLru,
+
+    /// Evicts the keys with an expiry that expire the soonest. Keys can't
+    /// expire yet, so it rejects writes like `NoEviction`.
+    VolatileTtl,
+}
+
+const POLICIES: &[(&str, Policy)] = &[
+    ("noeviction", Policy::NoEviction),
+    ("allkeys-lru", Policy::AllKeysLru),
+    ("allkeys-lfu", Policy::AllKeysLfu),
+    ("allkeys-random", Policy::AllKeysRandom),
+    ("volatile-lru", Policy::VolatileLru),
+    ("volatile-ttl", Policy::VolatileTtl),
+];
+
+impl FromStr for Policy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        POLICIES.iter().find(|&&(name, _)| name.eq_ignore_ascii_case(s)).map(|&(_, policy)| policy).ok_or(())
+    }
+}
+
+impl fmt::Display for Policy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = POLICIES.iter().find(|&&(_, policy)| policy == *self).map(|&(name, _)| name).unwrap();
+        write!(f, "{}", name)
+    }
+}
+
+/// Evicts keys until the databases use at most `maxmemory` bytes, if set.
+/// Returns false if they still use more, because the policy doesn't evict
+/// or there's nothing left to evict.
+pub fn evict(shared: &Shared) -> bool {
+    let (maxmemory, policy, samples) = {
+        let config = shared.config.read().unwrap();
+        (config.maxmemory, config.maxmemory_policy, config.maxmemory_samples)
+    };
+    if maxmemory == 0 {
+        return true;
+    }
+
+    let used = || shared.used_memory.load(Ordering::Relaxed);
+    if used() <= maxmemory {
+        return true;
+    }
+    // Keys can't expire yet, so the volatile policies have nothing to evict.
+    if let Policy::NoEviction | Policy::VolatileLru | Policy::VolatileTtl = policy {
+        return false;
+    }
+
+    // Higher scores are better candidates.
+    let score = |sample: &KeySample| match policy {
+        Policy::AllKeysLru => (sample.idle, 0),
+        Policy::AllKeysLfu => (u64::from(u8::MAX - sample.frequency), sample.idle),
+        _ => (rand::thread_rng().gen(), 0),
+    };
+
+    let mut stores = shared.write_all();
+    while used() > maxmemory {
+        let mut best = None;
+        for (db, store) in stores.iter().enumerate() {
+            for sample in store.sample(samples) {
+                let score = score(&sample);
+                if best.as_ref().is_none_or(|&(best, _, _)| score > best) {
+                    best = Some((score, db, sample.key));
+                }
+            }
+        }
+
+        let (db, key) = match best {
+            Some((_, db, key)) => (db, key),
+            None => return false,
+        };
+        stores[db].remove(&key);
+        shared.stats.evicted_keys.fetch_add(1, Ordering::Relaxed);
+    }
+    true
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use std::thread;
+    use std::time::Duration;
+    use store::{Data, Store};
+
+    fn string(s: &str) -> Data {
+        Data::String(s.as_bytes().to_vec())
+    }
+
+    /// Returns shared state with keys `a`, `b` and `c` in the first database
+    /// and a limit leaving room for two of them.
+    fn full(policy: Policy) -> Shared {
+        let shared = Shared::new(vec![Store::new(), Store::new()]);
+        {
+            let mut store = shared.write(0);
+            for key in &["a", "b", "c"] {
+                store.insert(key.to_string(), string("value"));
+                // so that access times differ
+                thread::sleep(Duration::from_millis(2));
+            }
+        }
+        {
+            let mut config = shared.config.write().unwrap();
+            config.maxmemory = shared.used_memory.load(Ordering::Relaxed) * 2 / 3;
+            config.maxmemory_policy = policy;
+            // enough samples to never miss a key
+            config.maxmemory_samples = 100;
+        }
+        shared
+    }
+
+    fn keys(shared: &Shared) -> Vec<&str> {
+        let store = shared.databases[0].read().unwrap();
+        ["a", "b", "c"].iter().cloned().filter(|key| store.contains_key(key)).collect()
+    }
+
+    #[test]
+    fn parse_policy() {
+        assert_eq!("allkeys-lru".parse(), Ok(Policy::AllKeysLru));
+        assert_eq!("ALLKEYS-RANDOM".parse(), Ok(Policy::AllKeysRandom));
+        assert_eq!("bogus".parse::<Policy>(), Err(()));
+        assert_eq!("VOLATILE-TTL".parse(), Ok(Policy::VolatileTtl));
+        assert_eq!(Policy::AllKeysLfu.to_string(), "allkeys-lfu");
+    }
+
+    #[test]
+    fn without_limit() {
+        let shared = Shared::new(vec![Store::new()]);
+        shared.write(0).insert("a".to_string(), string("1"));
+        assert!(evict(&shared));
+    }
+
+    #[test]
+    fn under_limit_without_locking() {
+        let shared = full(Policy::AllKeysLru);
+        shared.config.write().unwrap().maxmemory = usize::MAX;
+        let _reader = shared.databases[0].read().unwrap();
+        assert!(evict(&shared));
+    }
+
+    #[test]
+    fn noeviction() {
+        let shared = full(Policy::NoEviction);
+        assert!(!evict(&shared));
+        assert_eq!(keys(&shared), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn volatile_without_expiring_keys() {
+        for &policy in &[Policy::VolatileLru, Policy::VolatileTtl] {
+            let shared = full(policy);
+            assert!(!evict(&shared));
+            assert_eq!(keys(&shared), ["a", "b", "c"]);
+        }
+    }
+
+    #[test]
+    fn allkeys_lru() {
+        let shared = full(Policy::AllKeysLru);
+        shared.databases[0].read().unwrap().get("a");
+        assert!(evict(&shared));
+        assert_eq!(keys(&shared), ["a", "c"]);
+    }
+
+    #[test]
+    fn allkeys_lfu() {
+        let shared = full(Policy::AllKeysLfu);
+        for _ in 0..10 {
+            shared.databases[0].read().unwrap().get("a");
+            shared.databases[0].read().unwrap().get("c");
+        }
+        assert!(evict(&shared));
+        assert_eq!(keys(&shared), ["a", "c"]);
+    }
+
+    #[test]
+    fn allkeys_random() {
+        let shared = full(Policy::AllKeysRandom);
+        assert!(evict(&shared));
+        assert_eq!(keys(&shared).len(), 2);
+
+        // evicts everything if needed
+        shared.config.write().unwrap().maxmemory = 1;
+        assert!(evict(&shared));
+        assert!(keys(&shared).is_empty());
+    }
+}
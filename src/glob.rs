@@ -0,0 +1,109 @@
+//! Glob-style pattern matching as used by Redis.
+//!
+//! `*` matches any sequence of bytes, `?` matches a single byte, `[abc]`,
+//! `[^abc]` and `[a-z]` match a set of bytes, and `\` escapes the next byte.
+
+/// Returns true if `string` matches `pattern` in its entirety.
+pub fn matches(pattern: &[u8], string: &[u8]) -> bool {
+    let (mut p, mut s) = (0, 0);
+
+    // Where to resume after the last `*` if the rest fails to match.
+    let mut backtrack = None;
+
+    while s < string.len() {
+        if p < pattern.len() {
+            if pattern[p] == b'*' {
+                backtrack = Some((p, s));
+                p += 1;
+                continue;
+            }
+
+            if let Some(len) = match_one(&pattern[p..], string[s]) {
+                p += len;
+                s += 1;
+                continue;
+            }
+        }
+
+        match backtrack {
+            Some((star, from)) => {
+                p = star + 1;
+                s = from + 1;
+                backtrack = Some((star, from + 1));
+            },
+            None => return false,
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+/// Matches the first token of `pattern`, which isn't `*`, against `c`.
+/// Returns the length of the token if it matches.
+fn match_one(pattern: &[u8], c: u8) -> Option<usize> {
+    match pattern[0] {
+        b'?' => Some(1),
+        b'\\' if pattern.len() > 1 => if pattern[1] == c { Some(2) } else { None },
+        b'[' => {
+            let negate = pattern.get(1) == Some(&b'^');
+            let mut i = if negate { 2 } else { 1 };
+            let mut found = false;
+
+            while i < pattern.len() && pattern[i] != b']' {
+                if pattern[i] == b'\\' && i + 1 < pattern.len() {
+                    found |= pattern[i + 1] == c;
+                    i += 2;
+                } else if i + 2 < pattern.len() && pattern[i + 1] == b'-' && pattern[i + 2] != b']' {
+                    let (lo, hi) = (pattern[i].min(pattern[i + 2]), pattern[i].max(pattern[i + 2]));
+                    found |= lo <= c && c <= hi;
+                    i += 3;
+                } else {
+                    found |= pattern[i] == c;
+                    i += 1;
+                }
+            }
+
+            // an unterminated class extends to the end of the pattern
+            let len = (i + 1).min(pattern.len());
+            if found != negate { Some(len) } else { None }
+        },
+        literal => if literal == c { Some(1) } else { None },
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn literals_and_wildcards() {
+        assert!(matches(b"news", b"news"));
+        assert!(!matches(b"news", b"newsletter"));
+        assert!(matches(b"news.*", b"news.sports"));
+        assert!(matches(b"news.*", b"news."));
+        assert!(!matches(b"news.*", b"news"));
+        assert!(matches(b"*", b""));
+        assert!(matches(b"h?llo", b"hello"));
+        assert!(!matches(b"h?llo", b"hllo"));
+        assert!(matches(b"*a*b*", b"xxaxxbxx"));
+        assert!(!matches(b"*a*b", b"xxbxxa"));
+    }
+
+    #[test]
+    fn classes() {
+        assert!(matches(b"h[ae]llo", b"hello"));
+        assert!(!matches(b"h[ae]llo", b"hillo"));
+        assert!(matches(b"h[^e]llo", b"hallo"));
+        assert!(!matches(b"h[^e]llo", b"hello"));
+        assert!(matches(b"h[a-b]llo", b"hbllo"));
+        assert!(!matches(b"h[a-b]llo", b"hcllo"));
+    }
+
+    #[test]
+    fn escapes() {
+        assert!(matches(b"a\\*b", b"a*b"));
+        assert!(!matches(b"a\\*b", b"axb"));
+        assert!(matches(b"[\\]]", b"]"));
+    }
+}
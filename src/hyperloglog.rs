@@ -0,0 +1,320 @@
+//! A HyperLogLog for estimating the number of unique elements in a set.
+//!
+//! It uses 2^14 registers, each holding the longest run of trailing zeros
+//! (plus one) seen in the hashes that map to it, for a standard error of
+//! 0.81%. The cardinality is computed with the estimator from Otmar Ertl's
+//! "New cardinality estimation algorithms for HyperLogLog sketches", which
+//! doesn't need bias correction for small or large cardinalities.
+
+use std::collections::BTreeMap;
+use std::mem;
+
+/// The number of bits of the hash used to select a register.
+const P: u32 = 14;
+
+/// The number of registers.
+const REGISTERS: usize = 1 << P;
+
+/// The number of bits of the hash left for counting trailing zeros.
+const Q: u32 = 64 - P;
+
+/// The number of non-zero registers above which the sparse encoding is
+/// converted to the dense one.
+const SPARSE_MAX: usize = 3000;
+
+/// The size of a node of the sparse map: up to 11 keys and values, with a
+/// parent pointer, its position in the parent and the length.
+const SPARSE_NODE_SIZE: usize = mem::size_of::<usize>() + 4 + 11 * (mem::size_of::<u16>() + mem::size_of::<u8>());
+
+/// The average number of registers in a node of the sparse map, which is
+/// split when full.
+const SPARSE_NODE_FILL: usize = 8;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Registers {
+    /// Only the registers with a non-zero value, suitable for small sets.
+    Sparse(BTreeMap<u16, u8>),
+
+    /// Every register, one byte each.
+    Dense(Vec<u8>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HyperLogLog {
+    registers: Registers,
+}
+
+impl Default for HyperLogLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HyperLogLog {
+    pub fn new() -> Self {
+        HyperLogLog { registers: Registers::Sparse(BTreeMap::new()) }
+    }
+
+    /// Returns the memory used by the registers, in bytes. For the sparse
+    /// encoding, it's estimated from the number of nodes of the map, and
+    /// leaves out the pointers to children which internal nodes also have.
+    pub fn memory_usage(&self) -> usize {
+        match &self.registers {
+            Registers::Sparse(map) => map.len().div_ceil(SPARSE_NODE_FILL) * SPARSE_NODE_SIZE,
+            Registers::Dense(v) => v.capacity(),
+        }
+    }
+
+    fn get(&self, index: usize) -> u8 {
+        match &self.registers {
+            Registers::Sparse(map) => map.get(&(index as u16)).cloned().unwrap_or(0),
+            Registers::Dense(v) => v[index],
+        }
+    }
+
+    /// Raises the register at `index` to `value`. Returns true if it changed.
+    fn update(&mut self, index: usize, value: u8) -> bool {
+        if self.get(index) >= value {
+            return false;
+        }
+
+        match &mut self.registers {
+            Registers::Sparse(map) => {
+                map.insert(index as u16, value);
+            },
+            Registers::Dense(v) => v[index] = value,
+        }
+
+        if let Registers::Sparse(map) = &self.registers {
+            if map.len() > SPARSE_MAX {
+                let mut dense = vec![0; REGISTERS];
+                for (&i, &value) in map {
+                    dense[i as usize] = value;
+                }
+                self.registers = Registers::Dense(dense);
+            }
+        }
+
+        true
+    }
+
+    /// Adds an element. Returns true if the estimate may have changed.
+    pub fn add(&mut self, element: &[u8]) -> bool {
+        let hash = murmur_hash64a(element, 0xadc8_3b19);
+        let index = (hash & (REGISTERS as u64 - 1)) as usize;
+
+        // Set a sentinel bit so the count is at most Q + 1.
+        let rest = (hash >> P) | (1 << Q);
+        let count = rest.trailing_zeros() as u8 + 1;
+
+        self.update(index, count)
+    }
+
+    /// Merges another HyperLogLog into this one, so that it estimates the
+    /// cardinality of the union of both sets.
+    pub fn merge(&mut self, other: &HyperLogLog) {
+        match &other.registers {
+            Registers::Sparse(map) => {
+                for (&i, &value) in map {
+                    self.update(i as usize, value);
+                }
+            },
+            Registers::Dense(v) => {
+                for (i, &value) in v.iter().enumerate() {
+                    self.update(i, value);
+                }
+            },
+        }
+    }
+
+    /// Estimates the number of unique elements added.
+    pub fn count(&self) -> u64 {
+        // histogram of the register values
+        let mut c = [0u32; Q as usize + 2];
+        match &self.registers {
+            Registers::Sparse(map) => {
+                c[0] = (REGISTERS - map.len()) as u32;
+                for &value in map.values() {
+                    c[value as usize] += 1;
+                }
+            },
+            Registers::Dense(v) => {
+                for &value in v {
+                    c[value as usize] += 1;
+                }
+            },
+        }
+
+        let m = REGISTERS as f64;
+        let mut z = m * tau((m - c[Q as usize + 1] as f64) / m);
+        for k in (1..=Q as usize).rev() {
+            z += c[k] as f64;
+            z *= 0.5;
+        }
+        z += m * sigma(c[0] as f64 / m);
+
+        let alpha = 0.5 / 2f64.ln();
+        (alpha * m * m / z).round() as u64
+    }
+}
+
+fn sigma(mut x: f64) -> f64 {
+    if x == 1.0 {
+        return f64::INFINITY;
+    }
+
+    let mut y = 1.0;
+    let mut z = x;
+    loop {
+        x *= x;
+        let previous = z;
+        z += x * y;
+        y += y;
+        if previous == z {
+            return z;
+        }
+    }
+}
+
+fn tau(mut x: f64) -> f64 {
+    if x == 0.0 || x == 1.0 {
+        return 0.0;
+    }
+
+    let mut y = 1.0;
+    let mut z = 1.0 - x;
+    loop {
+        x = x.sqrt();
+        let previous = z;
+        y *= 0.5;
+        z -= (1.0 - x).powi(2) * y;
+        if previous == z {
+            return z / 3.0;
+        }
+    }
+}
+
+/// MurmurHash64A by Austin Appleby, which has a good distribution over all
+/// of its 64 bits.
+fn murmur_hash64a(key: &[u8], seed: u64) -> u64 {
+    const M: u64 = 0xc6a4_a793_5bd1_e995;
+    const R: u32 = 47;
+
+    let mut h = seed ^ (key.len() as u64).wrapping_mul(M);
+
+    let mut chunks = key.chunks_exact(8);
+    for chunk in &mut chunks {
+        let mut k = chunk.iter().rev().fold(0u64, |acc, &b| (acc << 8) | b as u64);
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+
+        h ^= k;
+        h = h.wrapping_mul(M);
+    }
+
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        for (i, &b) in tail.iter().enumerate() {
+            h ^= (b as u64) << (8 * i);
+        }
+        h = h.wrapping_mul(M);
+    }
+
+    h ^= h >> R;
+    h = h.wrapping_mul(M);
+    h ^= h >> R;
+    h
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    fn is_sparse(hll: &HyperLogLog) -> bool {
+        matches!(hll.registers, Registers::Sparse(_))
+    }
+
+    fn assert_estimate(hll: &HyperLogLog, exact: u64) {
+        let estimate = hll.count() as f64;
+        let error = (estimate - exact as f64).abs() / exact as f64;
+        assert!(error < 0.02, "estimate {} for {} elements", estimate, exact);
+    }
+
+    #[test]
+    fn empty_count() {
+        assert_eq!(HyperLogLog::new().count(), 0);
+    }
+
+    #[test]
+    fn small_counts_are_exact() {
+        let mut hll = HyperLogLog::new();
+        for i in 0..10 {
+            assert!(hll.add(format!("element:{}", i).as_bytes()));
+        }
+        assert!(!hll.add(b"element:0"));
+        assert_eq!(hll.count(), 10);
+    }
+
+    #[test]
+    fn accuracy_against_exact_counts() {
+        let mut hll = HyperLogLog::new();
+        let mut added = 0;
+
+        for &exact in &[100, 1_000, 10_000, 100_000, 500_000] {
+            while added < exact {
+                hll.add(format!("user:{}", added).as_bytes());
+                added += 1;
+            }
+            assert_estimate(&hll, exact);
+        }
+    }
+
+    #[test]
+    fn duplicates_do_not_change_count() {
+        let mut hll = HyperLogLog::new();
+        for _ in 0..3 {
+            for i in 0..5_000 {
+                hll.add(format!("user:{}", i).as_bytes());
+            }
+        }
+        assert_estimate(&hll, 5_000);
+    }
+
+    #[test]
+    fn sparse_to_dense() {
+        let mut hll = HyperLogLog::new();
+        for i in 0..1_000 {
+            hll.add(format!("user:{}", i).as_bytes());
+        }
+        assert!(is_sparse(&hll));
+        let count = hll.count();
+
+        for i in 1_000..20_000 {
+            hll.add(format!("user:{}", i).as_bytes());
+        }
+        assert!(!is_sparse(&hll));
+
+        let mut small = HyperLogLog::new();
+        for i in 0..1_000 {
+            small.add(format!("user:{}", i).as_bytes());
+        }
+        assert_eq!(small.count(), count);
+    }
+
+    #[test]
+    fn merge_estimates_union() {
+        let mut a = HyperLogLog::new();
+        let mut b = HyperLogLog::new();
+        for i in 0..30_000 {
+            a.add(format!("user:{}", i).as_bytes());
+        }
+        for i in 20_000..50_000 {
+            b.add(format!("user:{}", i).as_bytes());
+        }
+
+        a.merge(&b);
+        assert_estimate(&a, 50_000);
+    }
+}
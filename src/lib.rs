@@ -4,19 +4,46 @@
 //! Serialization Protocol). In practice, you can use any client that supports the Redis protocol
 //! to communicate with a Hanbaiki server.
 
-#[macro_use]
 extern crate clap;
+extern crate rand;
+extern crate rustls;
+extern crate rustls_pemfile;
+extern crate sha2;
 
+#[cfg(unix)]
+extern crate libc;
+
+#[cfg(test)]
+extern crate rcgen;
+
+mod acl;
+mod clients;
+mod command;
 mod config;
+mod eviction;
+mod glob;
+mod hyperloglog;
+mod metrics;
+mod monitor;
+mod pubsub;
 pub mod resp_error;
 mod respreader;
 mod respwriter;
 mod server;
+mod sorted_set;
 pub mod client;
 mod response;
+mod session;
+mod slowlog;
+mod socket;
+mod stats;
+mod store;
+mod stream;
+mod time;
+mod tls;
 mod value;
 
-pub use config::Config;
+pub use config::{Config, ConfigError, TlsConfig};
 pub use respreader::RespReader;
 pub use respwriter::RespWriter;
 pub use server::Server;
@@ -6,14 +6,21 @@ extern crate clap;
 use hanbaiki::Server;
 use hanbaiki::Config;
 
+use std::process;
+
 use clap::{App, Arg};
 
 fn main() {
     let matches = App::new("Hanbaiki")
         .version(crate_version!())
         .about("A simple key-value store.")
+        .arg(Arg::with_name("CONFIG")
+            .help("Read options from the specified file. Flags override them")
+            .takes_value(true)
+            .long("config")
+            .short("c"))
         .arg(Arg::with_name("PORT")
-            .help("Specify a custom port. Default: 6363")
+            .help("Specify a custom port, or 0 to not listen on TCP. Default: 6363")
             .takes_value(true)
             .long("port")
             .short("p"))
@@ -26,9 +33,77 @@ fn main() {
             .help("Generate a pidfile at the specified path. Example: /var/run/hanbaiki.pid")
             .takes_value(true)
             .long("pidfile"))
+        .arg(Arg::with_name("DATABASES")
+            .help("Set the number of databases. Default: 16")
+            .takes_value(true)
+            .long("databases"))
+        .arg(Arg::with_name("REQUIREPASS")
+            .help("Require clients to authenticate with AUTH using this password")
+            .takes_value(true)
+            .long("requirepass"))
+        .arg(Arg::with_name("ACLFILE")
+            .help("Load users and their permissions from the specified file")
+            .takes_value(true)
+            .long("aclfile"))
+        .arg(Arg::with_name("TLS_PORT")
+            .help("Also accept TLS connections on the specified port")
+            .takes_value(true)
+            .long("tls-port"))
+        .arg(Arg::with_name("TLS_CERT_FILE")
+            .help("The PEM certificate of the server, followed by any intermediates")
+            .takes_value(true)
+            .long("tls-cert-file"))
+        .arg(Arg::with_name("TLS_KEY_FILE")
+            .help("The PEM private key of the server certificate")
+            .takes_value(true)
+            .long("tls-key-file"))
+        .arg(Arg::with_name("TLS_CA_CERT_FILE")
+            .help("Require TLS clients to present a certificate signed by this PEM CA certificate")
+            .takes_value(true)
+            .long("tls-ca-cert-file"))
+        .arg(Arg::with_name("UNIXSOCKET")
+            .help("Also listen on a Unix socket at the specified path")
+            .takes_value(true)
+            .long("unixsocket"))
+        .arg(Arg::with_name("UNIXSOCKETPERM")
+            .help("Set the permissions of the Unix socket in octal. Example: 770")
+            .takes_value(true)
+            .long("unixsocketperm"))
+        .arg(Arg::with_name("TIMEOUT")
+            .help("Disconnect clients idle for the specified number of seconds, or never with 0. Default: 0")
+            .takes_value(true)
+            .long("timeout"))
+        .arg(Arg::with_name("MAXMEMORY")
+            .help("Limit the memory used by keys and values, in bytes or with a unit. Example: 100mb. Default: no limit")
+            .takes_value(true)
+            .long("maxmemory"))
+        .arg(Arg::with_name("MAXMEMORY_POLICY")
+            .help("What to do when memory is full: noeviction, allkeys-lru, allkeys-lfu, allkeys-random, volatile-lru or volatile-ttl. Keys can't expire, so the volatile policies reject writes like noeviction. Default: noeviction")
+            .takes_value(true)
+            .long("maxmemory-policy"))
+        .arg(Arg::with_name("MAXMEMORY_SAMPLES")
+            .help("The number of keys sampled in each database to pick one to evict. Default: 5")
+            .takes_value(true)
+            .long("maxmemory-samples"))
+        .arg(Arg::with_name("METRICS_PORT")
+            .help("Serve Prometheus metrics at /metrics and a health check at /healthz over HTTP on the specified port")
+            .takes_value(true)
+            .long("metrics-port"))
+        .arg(Arg::with_name("SLOWLOG_LOG_SLOWER_THAN")
+            .help("Log commands taking at least the specified number of microseconds, all with 0 or none if negative. Default: 10000")
+            .takes_value(true)
+            .allow_hyphen_values(true)
+            .long("slowlog-log-slower-than"))
+        .arg(Arg::with_name("SLOWLOG_MAX_LEN")
+            .help("The number of entries the slow log keeps. Default: 128")
+            .takes_value(true)
+            .long("slowlog-max-len"))
         .get_matches();
 
-    let config = Config::new(matches);
+    let config = Config::new(matches).unwrap_or_else(|e| {
+        eprintln!("Invalid configuration: {}", e);
+        process::exit(1);
+    });
 
     Server::run(config);
 
@@ -0,0 +1,232 @@
+//! An HTTP listener for monitoring: `/metrics` in the Prometheus text format
+//! and `/healthz` for load balancer checks.
+//!
+//! Each connection is served on its own thread, so a slow client can't delay
+//! the others. A request must be read and answered within `TIMEOUT`, so the
+//! threads of stuck clients don't pile up.
+
+use std::fmt::Write as FmtWrite;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use session::Shared;
+use stats::LATENCY_BUCKETS;
+
+/// The longest request accepted, headers included.
+const MAX_REQUEST: usize = 8192;
+
+/// The time allowed for a whole request, from accepting the connection to
+/// writing the response.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+pub fn serve(listener: TcpListener, shared: Arc<Shared>) {
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let shared = Arc::clone(&shared);
+                thread::spawn(move || {
+                    if let Err(e) = handle_request(stream, &shared) {
+                        println!("metrics request failed: {:?}", e);
+                    }
+                });
+            },
+            Err(e) => println!("connection failed: {:?}", e),
+        }
+    }
+}
+
+/// Returns the time left until `deadline`, or a timeout error if it passed.
+fn remaining(deadline: Instant) -> io::Result<Duration> {
+    match deadline.checked_duration_since(Instant::now()) {
+        Some(left) if left > Duration::from_millis(0) => Ok(left),
+        _ => Err(io::Error::new(io::ErrorKind::TimedOut, "request took too long")),
+    }
+}
+
+fn handle_request(mut stream: TcpStream, shared: &Shared) -> io::Result<()> {
+    let deadline = Instant::now() + TIMEOUT;
+
+    // Only the request line matters; the headers are read and ignored.
+    let mut request = Vec::new();
+    let mut buf = [0; 1024];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+        stream.set_read_timeout(Some(remaining(deadline)?))?;
+        let n = stream.read(&mut buf)?;
+        if n == 0 || request.len() + n > MAX_REQUEST {
+            return respond(&mut stream, deadline, "400 Bad Request", "text/plain", "Bad request\n");
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+
+    let request = String::from_utf8_lossy(&request);
+    let mut words = request.lines().next().unwrap_or("").split_whitespace();
+    let (method, target) = (words.next().unwrap_or(""), words.next().unwrap_or(""));
+    let path = target.split('?').next().unwrap_or("");
+
+    match (method, path) {
+        ("GET", "/metrics") => respond(&mut stream, deadline, "200 OK", "text/plain; version=0.0.4; charset=utf-8", &render(shared)),
+        ("GET", "/healthz") => respond(&mut stream, deadline, "200 OK", "text/plain", "OK\n"),
+        (_, "/metrics") | (_, "/healthz") => respond(&mut stream, deadline, "405 Method Not Allowed", "text/plain", "Method not allowed\n"),
+        _ => respond(&mut stream, deadline, "404 Not Found", "text/plain", "Not found\n"),
+    }
+}
+
+fn respond(stream: &mut TcpStream, deadline: Instant, status: &str, content_type: &str, body: &str) -> io::Result<()> {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status, content_type, body.len(), body
+    );
+
+    let mut unsent = response.as_bytes();
+    while !unsent.is_empty() {
+        stream.set_write_timeout(Some(remaining(deadline)?))?;
+        match stream.write(unsent)? {
+            0 => return Err(io::Error::new(io::ErrorKind::WriteZero, "connection closed")),
+            n => unsent = &unsent[n..],
+        }
+    }
+    Ok(())
+}
+
+/// Appends the help and type lines of a metric.
+fn describe(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = write!(out, "# HELP {} {}\n# TYPE {} {}\n", name, help, name, kind);
+}
+
+/// Renders every metric in the Prometheus text format.
+pub fn render(shared: &Shared) -> String {
+    let stats = &shared.stats;
+    let mut out = String::new();
+
+    describe(&mut out, "hanbaiki_uptime_seconds", "gauge", "Seconds since the server started.");
+    let _ = writeln!(out, "hanbaiki_uptime_seconds {}", stats.started.elapsed().as_secs());
+
+    describe(&mut out, "hanbaiki_connected_clients", "gauge", "The number of connected clients.");
+    let _ = writeln!(out, "hanbaiki_connected_clients {}", stats.connected_clients.load(Ordering::Relaxed));
+
+    describe(&mut out, "hanbaiki_connections_received_total", "counter", "The number of connections accepted.");
+    let _ = writeln!(out, "hanbaiki_connections_received_total {}", stats.total_connections.load(Ordering::Relaxed));
+
+    describe(&mut out, "hanbaiki_commands_processed_total", "counter", "The number of commands run.");
+    let _ = writeln!(out, "hanbaiki_commands_processed_total {}", stats.total_commands.load(Ordering::Relaxed));
+
+    describe(&mut out, "hanbaiki_evicted_keys_total", "counter", "The number of keys evicted because of maxmemory.");
+    let _ = writeln!(out, "hanbaiki_evicted_keys_total {}", stats.evicted_keys.load(Ordering::Relaxed));
+
+    let called: Vec<_> = stats.commands().filter(|(_, stats)| stats.calls.load(Ordering::Relaxed) > 0).collect();
+
+    describe(&mut out, "hanbaiki_commands_total", "counter", "The number of calls of each command.");
+    for &(name, stats) in &called {
+        let _ = writeln!(out, "hanbaiki_commands_total{{command=\"{}\"}} {}", name.to_lowercase(), stats.calls.load(Ordering::Relaxed));
+    }
+
+    let metric = "hanbaiki_command_duration_seconds";
+    describe(&mut out, metric, "histogram", "The time spent running each command.");
+    for &(name, stats) in &called {
+        let name = name.to_lowercase();
+        let mut count = 0;
+        for (bound, bucket) in LATENCY_BUCKETS.iter().zip(&stats.latencies) {
+            count += bucket.load(Ordering::Relaxed);
+            let _ = writeln!(out, "{}_bucket{{command=\"{}\",le=\"{}\"}} {}", metric, name, *bound as f64 / 1e6, count);
+        }
+        let calls = stats.calls.load(Ordering::Relaxed);
+        let _ = writeln!(out, "{}_bucket{{command=\"{}\",le=\"+Inf\"}} {}", metric, name, calls);
+        let _ = writeln!(out, "{}_sum{{command=\"{}\"}} {}", metric, name, stats.usec.load(Ordering::Relaxed) as f64 / 1e6);
+        let _ = writeln!(out, "{}_count{{command=\"{}\"}} {}", metric, name, calls);
+    }
+
+    // Each database is only locked for reading, one at a time.
+    let keys: Vec<usize> = shared.databases.iter().map(|db| db.read().unwrap().len()).collect();
+    let used = shared.used_memory.load(Ordering::Relaxed);
+
+    describe(&mut out, "hanbaiki_keys", "gauge", "The number of keys in each database.");
+    for (db, keys) in keys.iter().enumerate() {
+        let _ = writeln!(out, "hanbaiki_keys{{db=\"{}\"}} {}", db, keys);
+    }
+
+    describe(&mut out, "hanbaiki_used_memory_bytes", "gauge", "The estimated memory used by keys and values.");
+    let _ = writeln!(out, "hanbaiki_used_memory_bytes {}", used);
+
+    describe(&mut out, "hanbaiki_maxmemory_bytes", "gauge", "The memory limit, or 0 for none.");
+    let _ = writeln!(out, "hanbaiki_maxmemory_bytes {}", shared.config.read().unwrap().maxmemory);
+
+    out
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use store::{Data, Store};
+
+    fn request(port: u16, request: &str) -> String {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn render_metrics() {
+        let shared = Shared::new(vec![Store::new(), Store::new()]);
+        shared.write(1).insert("a".to_string(), Data::String(b"1".to_vec()));
+        shared.stats.record("GET", Duration::from_micros(30));
+        shared.stats.record("GET", Duration::from_secs(2));
+
+        let metrics = render(&shared);
+        assert!(metrics.contains("# TYPE hanbaiki_connected_clients gauge\nhanbaiki_connected_clients 0\n"));
+        assert!(metrics.contains("hanbaiki_commands_total{command=\"get\"} 2\n"));
+        assert!(!metrics.contains("command=\"set\""));
+        assert!(metrics.contains("hanbaiki_command_duration_seconds_bucket{command=\"get\",le=\"0.00001\"} 0\n"));
+        assert!(metrics.contains("hanbaiki_command_duration_seconds_bucket{command=\"get\",le=\"0.00005\"} 1\n"));
+        assert!(metrics.contains("hanbaiki_command_duration_seconds_bucket{command=\"get\",le=\"1\"} 1\n"));
+        assert!(metrics.contains("hanbaiki_command_duration_seconds_bucket{command=\"get\",le=\"+Inf\"} 2\n"));
+        assert!(metrics.contains("hanbaiki_command_duration_seconds_sum{command=\"get\"} 2.00003\n"));
+        assert!(metrics.contains("hanbaiki_keys{db=\"0\"} 0\nhanbaiki_keys{db=\"1\"} 1\n"));
+        let used = shared.used_memory.load(Ordering::Relaxed);
+        assert!(used > 0);
+        assert!(metrics.contains(&format!("hanbaiki_used_memory_bytes {}\n", used)));
+    }
+
+    #[test]
+    fn http_endpoints() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let shared = Arc::new(Shared::new(vec![Store::new()]));
+        thread::spawn(move || serve(listener, shared));
+
+        let response = request(port, "GET /healthz HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("\r\n\r\nOK\n"));
+
+        let response = request(port, "GET /metrics?x=1 HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4"));
+        assert!(response.contains("hanbaiki_used_memory_bytes 0\n"));
+
+        let response = request(port, "POST /metrics HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+        let response = request(port, "GET / HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+
+    #[test]
+    fn slow_client_does_not_block_others() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let shared = Arc::new(Shared::new(vec![Store::new()]));
+        thread::spawn(move || serve(listener, shared));
+
+        let mut slow = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        slow.write_all(b"GET /heal").unwrap();
+
+        let start = Instant::now();
+        let response = request(port, "GET /healthz HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(start.elapsed() < TIMEOUT);
+    }
+}
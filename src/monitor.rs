@@ -0,0 +1,149 @@
+//! The connections in MONITOR mode, which receive every command run by the
+//! other connections.
+
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{SyncSender, TrySendError};
+
+use respwriter::RespWriter;
+use time;
+
+#[derive(Default)]
+pub struct Monitors {
+    /// The number of monitors, checked without locking so that commands cost
+    /// nothing more while no one is monitoring.
+    count: AtomicUsize,
+
+    /// The ID of each monitoring session and the sender of its connection.
+    senders: Mutex<Vec<(u64, SyncSender<String>)>>,
+}
+
+impl Monitors {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn add(&self, id: u64, sender: SyncSender<String>) {
+        let mut senders = self.senders.lock().unwrap();
+        senders.push((id, sender));
+        self.count.store(senders.len(), Ordering::Relaxed);
+    }
+
+    pub fn remove(&self, id: u64) {
+        let mut senders = self.senders.lock().unwrap();
+        senders.retain(|&(monitor, _)| monitor != id);
+        self.count.store(senders.len(), Ordering::Relaxed);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count.load(Ordering::Relaxed) == 0
+    }
+
+    /// Sends a command run by session `id` to every other monitor, as
+    /// `+1339518083.107412 [0 127.0.0.1:60866] "GET" "key"`. Returns the IDs
+    /// of the monitors whose output buffer was full, which are dropped to be
+    /// disconnected.
+    pub fn feed(&self, id: u64, db: usize, addr: &str, args: &[String]) -> Vec<u64> {
+        let mut overflowed = Vec::new();
+        if self.is_empty() {
+            return overflowed;
+        }
+
+        let now = time::now_ms();
+        let args: Vec<String> = args.iter().map(|arg| quote(arg)).collect();
+        let line = format!("{}.{:03}000 [{} {}] {}", now / 1000, now % 1000, db, addr, args.join(" "));
+        let line = match RespWriter::to_simple_string(&line) {
+            Ok(line) => line,
+            Err(_) => return overflowed,
+        };
+
+        let mut senders = self.senders.lock().unwrap();
+        // Monitors whose connection is gone are dropped too.
+        senders.retain(|&(monitor, ref sender)| {
+            if monitor == id {
+                return true;
+            }
+            match sender.try_send(line.clone()) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) => {
+                    overflowed.push(monitor);
+                    false
+                },
+                Err(TrySendError::Disconnected(_)) => false,
+            }
+        });
+        self.count.store(senders.len(), Ordering::Relaxed);
+        overflowed
+    }
+}
+
+/// Quotes an argument, escaping quotes, backslashes and unprintable
+/// characters.
+fn quote(arg: &str) -> String {
+    let mut quoted = String::with_capacity(arg.len() + 2);
+    quoted.push('"');
+    for c in arg.chars() {
+        match c {
+            '"' | '\\' => {
+                quoted.push('\\');
+                quoted.push(c);
+            },
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\x{:02x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use std::sync::mpsc::sync_channel;
+
+    #[test]
+    fn quote_arguments() {
+        assert_eq!(quote("hello"), "\"hello\"");
+        assert_eq!(quote("a \"b\"\\"), "\"a \\\"b\\\"\\\\\"");
+        assert_eq!(quote("line\r\n\u{1}"), "\"line\\r\\n\\x01\"");
+    }
+
+    #[test]
+    fn feed_other_monitors() {
+        let monitors = Monitors::new();
+        assert!(monitors.is_empty());
+
+        let (tx, rx) = sync_channel(16);
+        monitors.add(1, tx);
+        let (tx, gone) = sync_channel(16);
+        monitors.add(2, tx);
+        drop(gone);
+
+        let overflowed = monitors.feed(3, 0, "127.0.0.1:5000", &["SET".to_string(), "a b".to_string()]);
+        assert!(overflowed.is_empty());
+        let line = rx.try_recv().unwrap();
+        assert!(line.starts_with('+'));
+        assert!(line.ends_with(" [0 127.0.0.1:5000] \"SET\" \"a b\"\r\n"));
+
+        // a monitor doesn't see its own commands, and closed ones are dropped
+        monitors.feed(1, 0, "127.0.0.1:5000", &["PING".to_string()]);
+        assert!(rx.try_recv().is_err());
+        monitors.remove(1);
+        assert!(monitors.is_empty());
+    }
+
+    #[test]
+    fn full_monitors_are_dropped() {
+        let monitors = Monitors::new();
+        let (tx, _rx) = sync_channel(1);
+        monitors.add(1, tx);
+
+        assert!(monitors.feed(2, 0, "", &["PING".to_string()]).is_empty());
+        assert_eq!(monitors.feed(2, 0, "", &["PING".to_string()]), vec![1]);
+        assert!(monitors.is_empty());
+    }
+}
@@ -0,0 +1,140 @@
+//! The registry of channel and pattern subscriptions.
+//!
+//! Messages are delivered through the sender of each subscribed connection,
+//! whose writer thread pushes them to the client, so publishing never waits
+//! on a slow subscriber. A subscriber whose output buffer is full is
+//! reported instead, to be disconnected.
+
+use std::collections::HashMap;
+use std::sync::mpsc::{SyncSender, TrySendError};
+
+use glob;
+use respwriter::RespWriter;
+
+type Subscribers = HashMap<u64, SyncSender<String>>;
+
+#[derive(Default)]
+pub struct PubSub {
+    channels: HashMap<String, Subscribers>,
+    patterns: HashMap<String, Subscribers>,
+}
+
+impl PubSub {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn subscribe(&mut self, channel: &str, id: u64, sender: SyncSender<String>) {
+        self.channels.entry(channel.to_string()).or_default().insert(id, sender);
+    }
+
+    pub fn unsubscribe(&mut self, channel: &str, id: u64) {
+        remove(&mut self.channels, channel, id);
+    }
+
+    pub fn psubscribe(&mut self, pattern: &str, id: u64, sender: SyncSender<String>) {
+        self.patterns.entry(pattern.to_string()).or_default().insert(id, sender);
+    }
+
+    pub fn punsubscribe(&mut self, pattern: &str, id: u64) {
+        remove(&mut self.patterns, pattern, id);
+    }
+
+    /// Sends a message to the subscribers of `channel` and of the patterns
+    /// matching it. Returns the number of messages sent, so a client that
+    /// subscribed to both is counted twice, and the IDs of the subscribers
+    /// whose output buffer was full.
+    pub fn publish(&self, channel: &str, message: &str) -> (usize, Vec<u64>) {
+        let mut receivers = 0;
+        let mut overflowed = Vec::new();
+
+        if let Some(subscribers) = self.channels.get(channel) {
+            let push = RespWriter::to_array(&["message", channel, message]);
+            receivers += send(subscribers, &push, &mut overflowed);
+        }
+
+        for (pattern, subscribers) in &self.patterns {
+            if glob::matches(pattern.as_bytes(), channel.as_bytes()) {
+                let push = RespWriter::to_array(&["pmessage", pattern, channel, message]);
+                receivers += send(subscribers, &push, &mut overflowed);
+            }
+        }
+
+        (receivers, overflowed)
+    }
+}
+
+fn remove(map: &mut HashMap<String, Subscribers>, name: &str, id: u64) {
+    if let Some(subscribers) = map.get_mut(name) {
+        subscribers.remove(&id);
+        if subscribers.is_empty() {
+            map.remove(name);
+        }
+    }
+}
+
+/// Sends `push` to every subscriber, adding those whose output buffer is
+/// full to `overflowed`. A subscriber whose connection is gone still counts,
+/// as it will be unsubscribed when its thread finishes.
+fn send(subscribers: &Subscribers, push: &str, overflowed: &mut Vec<u64>) -> usize {
+    for (&id, sender) in subscribers {
+        if let Err(TrySendError::Full(_)) = sender.try_send(push.to_string()) {
+            overflowed.push(id);
+        }
+    }
+    subscribers.len()
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use std::sync::mpsc::sync_channel;
+
+    #[test]
+    fn publish_to_channels_and_patterns() {
+        let mut pubsub = PubSub::new();
+        let (tx1, rx1) = sync_channel(16);
+        let (tx2, rx2) = sync_channel(16);
+
+        pubsub.subscribe("news", 1, tx1.clone());
+        pubsub.psubscribe("news.*", 1, tx1);
+        pubsub.psubscribe("news.*", 2, tx2);
+
+        assert_eq!(pubsub.publish("news", "hello"), (1, vec![]));
+        assert_eq!(rx1.try_recv(), Ok(RespWriter::to_array(&["message", "news", "hello"])));
+        assert!(rx2.try_recv().is_err());
+
+        assert_eq!(pubsub.publish("news.sports", "goal"), (2, vec![]));
+        let push = RespWriter::to_array(&["pmessage", "news.*", "news.sports", "goal"]);
+        assert_eq!(rx1.try_recv(), Ok(push.clone()));
+        assert_eq!(rx2.try_recv(), Ok(push));
+
+        assert_eq!(pubsub.publish("weather", "rain"), (0, vec![]));
+    }
+
+    #[test]
+    fn full_output_buffers_are_reported() {
+        let mut pubsub = PubSub::new();
+        let (tx, _rx) = sync_channel(1);
+        pubsub.subscribe("news", 1, tx);
+
+        assert_eq!(pubsub.publish("news", "first"), (1, vec![]));
+        assert_eq!(pubsub.publish("news", "second"), (1, vec![1]));
+    }
+
+    #[test]
+    fn unsubscribe_removes_empty_channels() {
+        let mut pubsub = PubSub::new();
+        let (tx, _rx) = sync_channel(16);
+
+        pubsub.subscribe("news", 1, tx.clone());
+        pubsub.psubscribe("n*", 1, tx);
+        pubsub.unsubscribe("news", 1);
+        pubsub.punsubscribe("n*", 1);
+
+        assert!(pubsub.channels.is_empty());
+        assert!(pubsub.patterns.is_empty());
+        assert_eq!(pubsub.publish("news", "hello"), (0, vec![]));
+    }
+}
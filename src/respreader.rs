@@ -16,6 +16,12 @@ pub struct RespReader {
     pub value: Value,
 }
 
+impl Default for RespReader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl RespReader {
     pub fn new() -> Self {
         RespReader {
@@ -26,10 +32,16 @@ impl RespReader {
         }
     }
 
+    /// Reads the next message into `value`. Bytes read past its end are kept
+    /// for the next call, so the reader can be reused for pipelined messages.
     pub fn frame_message<T: Read>(&mut self, stream: &mut T) -> Result<()> {
+        self.message.drain(..self.index);
+        self.index = 0;
 
         self.stack.push(State::GetType);
-        self.read(stream)?;
+        if self.message.is_empty() {
+            self.read(stream)?;
+        }
 
         loop {
             let get_fn = match self.current_state() {
@@ -103,7 +115,8 @@ impl RespReader {
                 self.transition_to(State::GetBulkString(SubState::GetSize, 0)),
             Some(b'*') =>
                 self.transition_to(State::GetArray(GetArray::new())),
-            _ => return Err(RespError::InvalidType),
+            Some(_) => return Err(RespError::InvalidType),
+            None => return Ok(None),
         }
 
         self.index += 1;
@@ -130,11 +143,9 @@ impl RespReader {
             }
         }
 
-        if substate == SubState::CheckLF {
-            if self.check_lf()?.is_some() {
-                self.stack.pop();
-                return Ok(Some(()));
-            }
+        if substate == SubState::CheckLF && self.check_lf()?.is_some() {
+            self.stack.pop();
+            return Ok(Some(()));
         }
 
         Ok(None)
@@ -160,11 +171,9 @@ impl RespReader {
             }
         }
 
-        if substate == SubState::CheckLF {
-            if self.check_lf()?.is_some() {
-                self.stack.pop();
-                return Ok(Some(()));
-            }
+        if substate == SubState::CheckLF && self.check_lf()?.is_some() {
+            self.stack.pop();
+            return Ok(Some(()));
         }
 
         Ok(None)
@@ -187,11 +196,9 @@ impl RespReader {
             }
         }
 
-        if substate == SubState::CheckLF {
-            if self.check_lf()?.is_some() {
-                self.stack.pop();
-                return Ok(Some(()));
-            }
+        if substate == SubState::CheckLF && self.check_lf()?.is_some() {
+            self.stack.pop();
+            return Ok(Some(()));
         }
 
         Ok(None)
@@ -206,24 +213,27 @@ impl RespReader {
         if substate == SubState::GetSize {
             let start_index = self.index;
             if let Some(n) = self.get_size(start_index)? {
-                size = n;
-                substate = SubState::CheckLF;
+                // a negative size is a Null Bulk String with no data that follows
+                substate = if n < 0 { SubState::CheckNullLF } else { SubState::CheckLF };
+                size = n.max(0) as usize;
                 self.transition_to(State::GetBulkString(substate, size));
             }
         }
 
-        if substate == SubState::CheckLF {
-            if self.check_lf()?.is_some() {
-                substate = SubState::BuildString;
-                self.transition_to(State::GetBulkString(substate, size));
-            }
+        if substate == SubState::CheckNullLF && self.check_lf()?.is_some() {
+            self.set_value(Value::Nil);
+            self.stack.pop();
+            return Ok(Some(()));
         }
 
-        if substate == SubState::BuildString {
-            if self.build_string(size as usize)?.is_some() {
-                self.stack.pop();
-                return Ok(Some(()));
-            }
+        if substate == SubState::CheckLF && self.check_lf()?.is_some() {
+            substate = SubState::BuildString;
+            self.transition_to(State::GetBulkString(substate, size));
+        }
+
+        if substate == SubState::BuildString && self.build_string(size)?.is_some() {
+            self.stack.pop();
+            return Ok(Some(()));
         }
 
         Ok(None)
@@ -238,8 +248,9 @@ impl RespReader {
         if substate == SubState::GetSize {
             let start_index = self.index;
             if let Some(n) = self.get_size(start_index)? {
-                size = n;
-                substate = SubState::CheckLF;
+                // a negative size is a Null Array with no elements that follow
+                substate = if n < 0 { SubState::CheckNullLF } else { SubState::CheckLF };
+                size = n.max(0) as usize;
                 self.get_array_change(|sm| {
                     sm.size = size;
                     sm.substate = substate;
@@ -247,13 +258,17 @@ impl RespReader {
             }
         }
 
-        if substate == SubState::CheckLF {
-            if self.check_lf()?.is_some() {
-                substate = SubState::GetElements;
-                self.get_array_change(|sm| {
-                    sm.substate = substate;
-                });
-            }
+        if substate == SubState::CheckNullLF && self.check_lf()?.is_some() {
+            self.set_value(Value::Nil);
+            self.stack.pop();
+            return Ok(Some(()));
+        }
+
+        if substate == SubState::CheckLF && self.check_lf()?.is_some() {
+            substate = SubState::GetElements;
+            self.get_array_change(|sm| {
+                sm.substate = substate;
+            });
         }
 
         if substate == SubState::GetElements {
@@ -284,11 +299,11 @@ impl RespReader {
         change(state_machine);
     }
 
-    fn get_size(&mut self, start_index: usize) -> Result<Option<usize>> {
+    fn get_size(&mut self, start_index: usize) -> Result<Option<i64>> {
         if let Some(i) = self.find_break(start_index) {
             let n = self.parse_int(start_index, i)?;
             self.index = i + 1;
-            return Ok(Some(n as usize));
+            return Ok(Some(n));
         }
 
         Ok(None)
@@ -348,6 +363,7 @@ impl RespReader {
 }
 
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 enum State {
     GetType,
     GetSimpleString(SubState, usize),
@@ -385,6 +401,8 @@ impl GetArray {
 enum SubState {
     CheckCR,
     CheckLF,
+    // null bulk string or null array:
+    CheckNullLF,
     // bulk string:
     GetSize,
     BuildString,
@@ -422,18 +440,18 @@ mod test {
             let end_pos = if self.pos + buf.len() > len {
                 len
             } else {
-                buf.len()
+                self.pos + buf.len()
             };
 
             for (i, j) in (self.pos..end_pos).enumerate() {
                 buf[i] = self.message[j];
             }
 
-            for i in end_pos..buf.len() {
-                buf[i] = 0;
+            let size = end_pos - self.pos;
+            for b in buf.iter_mut().skip(size) {
+                *b = 0;
             }
 
-            let size = end_pos - self.pos;
             self.pos += size;
             Ok(size)
         }
@@ -511,6 +529,9 @@ mod test {
         let incomplete = "$12\r\nHello";
         let err = RespError::UnexpectedEof;
         check_invalid(incomplete, err);
+
+        let null = "$-1\r\n";
+        check_valid(null);
     }
 
     #[test]
@@ -523,6 +544,9 @@ mod test {
 
         let mixed = "*3\r\n$12\r\nHello World!\r\n+OK\r\n:25\r\n";
         check_valid(mixed);
+
+        let null = "*-1\r\n";
+        check_valid(null);
     }
 
     #[test]
@@ -540,6 +564,31 @@ mod test {
         reader.value
     }
 
+    #[test]
+    fn element_type_in_next_read() {
+        // the first read of 20 bytes ends right before the type of the element
+        let mut reader = RespReader::new();
+        let mut stream = MockStream::from("+0123456789ABC\r\n*1\r\n$5\r\nhello\r\n");
+
+        reader.frame_message(&mut stream).unwrap();
+        reader.frame_message(&mut stream).unwrap();
+        assert_eq!(reader.value, vec!["hello".to_string()].into());
+    }
+
+    #[test]
+    fn pipelined_messages() {
+        let mut reader = RespReader::new();
+        let mut stream = MockStream::from("+OK\r\n*2\r\n$3\r\nGET\r\n$5\r\nhello\r\n:1\r\n");
+
+        reader.frame_message(&mut stream).unwrap();
+        assert_eq!(reader.value.take(), Value::SimpleString("OK".to_string()));
+        reader.frame_message(&mut stream).unwrap();
+        assert_eq!(reader.value.take(), vec!["GET".to_string(), "hello".to_string()].into());
+        reader.frame_message(&mut stream).unwrap();
+        assert_eq!(reader.value.take(), Value::Integer(1));
+        assert_eq!(reader.frame_message(&mut stream), Err(RespError::UnexpectedEof));
+    }
+
     #[test]
     fn check_simple_string_val() {
         let empty = "+\r\n";
@@ -570,6 +619,10 @@ mod test {
         let simple = "$12\r\nHello World!\r\n";
         let v = get_value(simple);
         assert_eq!(v, Value::BulkString("Hello World!".to_string()));
+
+        let null = "$-1\r\n";
+        let v = get_value(null);
+        assert_eq!(v, Value::Nil);
     }
 
     #[test]
@@ -594,5 +647,15 @@ mod test {
         ]);
         let v = get_value(nested_array);
         assert_eq!(v, expected);
+
+        // [nil, $"A"]
+        let with_nil = "*2\r\n$-1\r\n$1\r\nA\r\n";
+        let expected = Value::Array(vec![Value::Nil, Value::BulkString("A".to_string())]);
+        let v = get_value(with_nil);
+        assert_eq!(v, expected);
+
+        let null = "*-1\r\n";
+        let v = get_value(null);
+        assert_eq!(v, Value::Nil);
     }
 }
@@ -26,6 +26,11 @@ impl RespWriter {
         format!(":{}\r\n", i)
     }
 
+    /// Serializes an integer that may be negative.
+    pub fn to_signed_integer(i: i64) -> String {
+        format!(":{}\r\n", i)
+    }
+
     pub fn to_bulk_string(s: &str) -> String {
         format!("${}\r\n{}\r\n", s.len(), s)
     }
@@ -34,6 +39,10 @@ impl RespWriter {
         "$-1\r\n".to_string()
     }
 
+    pub fn null_array() -> String {
+        "*-1\r\n".to_string()
+    }
+
     pub fn to_array(strings: &[&str]) -> String {
 
         let mut msg = format!("*{}\r\n", strings.len());
@@ -43,6 +52,18 @@ impl RespWriter {
         }
         msg
     }
+
+    /// Builds an array from elements that are already serialized, which
+    /// allows mixing types, nil elements and nested arrays.
+    pub fn to_raw_array(elements: &[String]) -> String {
+
+        let mut msg = format!("*{}\r\n", elements.len());
+
+        for e in elements {
+            msg.push_str(e);
+        }
+        msg
+    }
 }
 
 #[cfg(test)]
@@ -65,6 +86,7 @@ mod test {
     #[test]
     fn check_integer() {
         assert_eq!(":100\r\n", RespWriter::to_integer(100));
+        assert_eq!(":-1\r\n", RespWriter::to_signed_integer(-1));
     }
 
     #[test]
@@ -86,4 +108,21 @@ mod test {
         assert_eq!("*2\r\n$3\r\nfoo\r\n$3\r\nbar\r\n", RespWriter::to_array(&v));
     }
 
+    #[test]
+    fn check_null_array() {
+        assert_eq!("*-1\r\n", RespWriter::null_array());
+    }
+
+    #[test]
+    fn check_raw_array() {
+        let v = vec![
+            RespWriter::to_bulk_string("foo"),
+            RespWriter::null_bulk_string(),
+            RespWriter::to_array(&["bar"]),
+            RespWriter::to_integer(1),
+        ];
+        let expected = "*4\r\n$3\r\nfoo\r\n$-1\r\n*1\r\n$3\r\nbar\r\n:1\r\n";
+        assert_eq!(expected, RespWriter::to_raw_array(&v));
+    }
+
 }
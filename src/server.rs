@@ -1,58 +1,207 @@
 use std::io;
-use std::io::Write;
-use std::collections::HashMap;
+use std::io::{Read, Write};
 
-use std::fs::File;
+use std::fs::{self, File};
 use std::path::PathBuf;
 use std::process;
-use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::net::{SocketAddr, TcpListener};
+#[cfg(unix)]
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+#[cfg(unix)]
+use std::path::Path;
 use std::thread;
-use std::sync::{RwLock, Arc};
+use std::time::{Duration, Instant};
+use std::sync::{Arc, RwLock};
+use std::sync::mpsc::{self, Receiver};
 
+use rustls::ServerConfig;
+
+use acl::Acl;
+use command;
+use command::{CommandError, Handler};
 use config::Config;
+use eviction;
+use metrics;
 use respreader::RespReader;
 use respwriter::RespWriter;
 use response::Response;
+use session::{OUTPUT_BUFFER_LIMIT, Session, Shared};
+use slowlog;
+use socket::Socket;
+use store::Store;
+use time;
+use tls::{self, TlsStream};
 use value::Value;
 
-type KvStore = Arc<RwLock<HashMap<String, Vec<u8>>>>;
+/// The commands a connection can run while subscribed to channels.
+const SUBSCRIBE_MODE_COMMANDS: &[&str] = &["SUBSCRIBE", "UNSUBSCRIBE", "PSUBSCRIBE", "PUNSUBSCRIBE", "PING"];
+
+/// The commands not shown to monitors, as their arguments may hold passwords.
+const UNMONITORED_COMMANDS: &[&str] = &["AUTH", "ACL", "CONFIG"];
+
+/// The commands that run immediately instead of being queued after MULTI.
+const TRANSACTION_COMMANDS: &[&str] = &["MULTI", "EXEC", "DISCARD", "WATCH"];
 
 pub struct Server;
 
 impl Server {
     pub fn run(config: Config) {
-        create_pidfile(config.pidfile);
-
-        let data = Arc::new(RwLock::new(HashMap::new()));
+        create_pidfile(config.pidfile.as_ref());
 
-        let addr = SocketAddr::new(config.ip, config.port);
-        let listener = match TcpListener::bind(addr) {
-            Ok(l) => l,
-            Err(e) => {
-                eprintln!("Couldn't bind to address: {:?}", e);
+        let databases = (0..config.databases).map(|_| Store::new()).collect();
+        let mut acl = Acl::new(config.requirepass.as_ref().map(String::as_ref));
+        if let Some(path) = &config.aclfile {
+            if let Err(e) = acl.load(path) {
+                eprintln!("Couldn't load ACL file: {}", e);
                 return;
             }
+        }
+
+        let mut shared = Shared::new(databases);
+        shared.acl = RwLock::new(acl);
+        shared.config = RwLock::new(config.clone());
+        let shared = Arc::new(shared);
+
+        let tls = match &config.tls {
+            Some(tls) => {
+                let tls_config = tls::server_config(&tls.cert_file, &tls.key_file, tls.ca_cert_file.as_ref().map(PathBuf::as_ref));
+                match tls_config {
+                    Ok(tls_config) => Some((tls.port, tls_config)),
+                    Err(e) => {
+                        eprintln!("Couldn't load TLS certificates: {}", e);
+                        return;
+                    },
+                }
+            },
+            None => None,
         };
-        println!("Listening on {}", addr);
-
-        for stream in listener.incoming() {
-            match stream {
-                Ok(stream) => {
-                    let data = Arc::clone(&data);
-                    thread::spawn(move || {
-                        handle_client(stream, data)
-                    });
-                },
-                Err(e) => println!("connection failed: {:?}", e),
+
+        let mut listeners = Vec::new();
+
+        // Port 0 disables the TCP listener, e.g. to only use a Unix socket.
+        if config.port != 0 {
+            let listener = match bind(SocketAddr::new(config.ip, config.port)) {
+                Some(listener) => listener,
+                None => return,
+            };
+            let shared = Arc::clone(&shared);
+            listeners.push(thread::spawn(move || {
+                let incoming = listener.incoming().map(|stream| stream.map(Socket::Tcp));
+                accept(incoming, shared, None)
+            }));
+        }
+
+        if let Some((port, tls_config)) = tls {
+            let listener = match bind(SocketAddr::new(config.ip, port)) {
+                Some(listener) => listener,
+                None => return,
+            };
+            let shared = Arc::clone(&shared);
+            listeners.push(thread::spawn(move || {
+                let incoming = listener.incoming().map(|stream| stream.map(Socket::Tcp));
+                accept(incoming, shared, Some(tls_config))
+            }));
+        }
+
+        #[cfg(unix)]
+        {
+            if let Some(path) = &config.unixsocket {
+                let listener = match bind_unix(path, config.unixsocketperm) {
+                    Some(listener) => listener,
+                    None => return,
+                };
+                let shared = Arc::clone(&shared);
+                listeners.push(thread::spawn(move || {
+                    let incoming = listener.incoming().map(|stream| stream.map(Socket::Unix));
+                    accept(incoming, shared, None)
+                }));
             }
         }
+
+        if let Some(port) = config.metrics_port {
+            let listener = match bind(SocketAddr::new(config.ip, port)) {
+                Some(listener) => listener,
+                None => return,
+            };
+            let shared = Arc::clone(&shared);
+            listeners.push(thread::spawn(move || metrics::serve(listener, shared)));
+        }
+
+        if listeners.is_empty() {
+            eprintln!("Nothing to listen on: the port is 0 and there's no Unix socket.");
+        }
+        for listener in listeners {
+            let _ = listener.join();
+        }
+    }
+}
+
+fn bind(addr: SocketAddr) -> Option<TcpListener> {
+    match TcpListener::bind(addr) {
+        Ok(listener) => {
+            println!("Listening on {}", addr);
+            Some(listener)
+        },
+        Err(e) => {
+            eprintln!("Couldn't bind to address: {:?}", e);
+            None
+        },
+    }
+}
+
+/// Binds a Unix socket, replacing the socket file a previous run may have
+/// left behind, and sets its permissions.
+#[cfg(unix)]
+fn bind_unix(path: &Path, permissions: Option<u32>) -> Option<UnixListener> {
+    if fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+        let _ = fs::remove_file(path);
+    }
+
+    let listener = match UnixListener::bind(path) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("Couldn't bind to Unix socket {}: {:?}", path.display(), e);
+            return None;
+        },
+    };
+
+    if let Some(mode) = permissions {
+        if let Err(e) = fs::set_permissions(path, fs::Permissions::from_mode(mode)) {
+            eprintln!("Couldn't set the permissions of Unix socket {}: {:?}", path.display(), e);
+            return None;
+        }
+    }
+
+    println!("Listening on {}", path.display());
+    Some(listener)
+}
+
+/// Serves each connection in its own thread, over TLS if a configuration is
+/// given.
+fn accept<I>(incoming: I, shared: Arc<Shared>, tls: Option<Arc<ServerConfig>>)
+where
+    I: Iterator<Item = io::Result<Socket>>,
+{
+    for stream in incoming {
+        match stream {
+            Ok(stream) => {
+                let shared = Arc::clone(&shared);
+                let tls = tls.clone();
+                thread::spawn(move || {
+                    handle_client(stream, shared, tls)
+                });
+            },
+            Err(e) => println!("connection failed: {:?}", e),
+        }
     }
 }
 
 /// Attempts to create a PID file if the pidfile option was provided.
 ///
 /// This function fails silently if it's unable to create or write to the file.
-fn create_pidfile(pidfile: Option<PathBuf>) {
+fn create_pidfile(pidfile: Option<&PathBuf>) {
     if let Some(p) = pidfile {
         if let Ok(mut f) = File::create(p) {
             let _ = f.write_all(process::id().to_string().as_bytes());
@@ -60,27 +209,63 @@ fn create_pidfile(pidfile: Option<PathBuf>) {
     }
 }
 
-fn handle_client(mut stream: TcpStream, data: KvStore) -> io::Result<()> {
-    let mut write_stream = stream.try_clone()?;
-    write_stream.set_nodelay(true)?;
+fn handle_client(socket: Socket, shared: Arc<Shared>, tls: Option<Arc<ServerConfig>>) -> io::Result<()> {
+    shared.stats.connected();
+    let result = serve_client(socket, Arc::clone(&shared), tls);
+    shared.stats.disconnected();
+    result
+}
 
+fn serve_client(socket: Socket, shared: Arc<Shared>, tls: Option<Arc<ServerConfig>>) -> io::Result<()> {
+    if let Socket::Tcp(stream) = &socket {
+        stream.set_nodelay(true)?;
+    }
+
+    let (mut read_stream, write_stream): (Box<dyn Read>, Box<dyn Write + Send>) = match (tls, &socket) {
+        (Some(tls), Socket::Tcp(stream)) => {
+            let tls_stream = TlsStream::accept(tls, stream.try_clone()?)?;
+            (Box::new(tls_stream.try_clone()?), Box::new(tls_stream))
+        },
+        _ => (Box::new(socket.try_clone()?), Box::new(socket.try_clone()?)),
+    };
+
+    // Replies and messages published by other connections go through the
+    // same channel, so they are never interleaved.
+    let (sender, receiver) = mpsc::sync_channel(OUTPUT_BUFFER_LIMIT);
+    thread::spawn(move || write_client(write_stream, receiver));
+
+    let mut session = Session::new(shared, Some(socket), sender);
+
+    let mut reader = RespReader::new();
     loop {
+        // Idle clients are disconnected after the timeout, except subscribers
+        // which are expected to wait for messages.
+        let timeout = session.shared.config.read().unwrap().timeout;
+        if let Some(socket) = session.client.socket() {
+            let timeout = if timeout > 0 && session.subscriptions() == 0 {
+                Some(Duration::from_secs(timeout))
+            } else {
+                None
+            };
+            socket.set_read_timeout(timeout)?;
+        }
+
         // Read command.
-        let mut reader = RespReader::new();
-        match reader.frame_message(&mut stream) {
+        match reader.frame_message(&mut read_stream) {
             Ok(_) => {
-                let command = reader.value;
+                let command = reader.value.take();
 
-                let data = Arc::clone(&data);
-                match process_command(data, command) {
-                    Response::KeepAlive(response) => write_stream.write(response.as_bytes())?,
+                match process_command(&mut session, command) {
+                    Response::KeepAlive(response) => {
+                        if session.sender.send(response).is_err() {
+                            return Ok(());
+                        }
+                    },
                     Response::Close(response) => {
-                        write_stream.write(response.as_bytes())?;
+                        let _ = session.sender.send(response);
                         return Ok(());
                     },
                 };
-
-                
             },
             Err(e) => {
                 println!("{:?}", e);
@@ -90,104 +275,140 @@ fn handle_client(mut stream: TcpStream, data: KvStore) -> io::Result<()> {
     }
 }
 
-fn process_command(data: KvStore, command: Value) -> Response {
-    let mut v = match command {
+/// Writes everything sent for a client until every sender is dropped, which
+/// happens once its session ends.
+fn write_client<W: Write>(mut stream: W, receiver: Receiver<String>) -> io::Result<()> {
+    for message in receiver {
+        stream.write_all(message.as_bytes())?;
+    }
+    Ok(())
+}
+
+fn process_command(session: &mut Session, command: Value) -> Response {
+    let v = match command {
         Value::Array(values) => values,
         _ => return Response::build_error("ERROR: Command must be an array"),
     };
 
-    if v.len() == 0 {
+    if v.is_empty() {
         return Response::build_error("ERROR: Missing command");
     }
 
-    if v.iter().any(|value| match value {
-        Value::BulkString(_) => false,
-        _ => true,
-    }) {
+    if v.iter().any(|value| !matches!(value, Value::BulkString(_))) {
         return Response::build_error("ERROR: Command must be an array of BulkString");
     }
 
-    let command = v[0].take().to_string().to_ascii_uppercase();
+    let mut args: Vec<String> = v.into_iter().map(Value::into_string).collect();
+    args[0].make_ascii_uppercase();
 
-    match command.as_ref() {
+    if (args[0] == "QUIT" || args[0] == "EXIT") && args.len() == 1 {
+        return Response::build_close_ok();
+    }
 
-        "SET" if v.len() == 3 => {
-            let mut data = data.write().unwrap();
-            data.insert(v[1].take().to_string(), v[2].take().to_string().into_bytes());
-            Response::build_ok()
-        },
+    if !session.authenticated && args[0] != "AUTH" {
+        return Response::build_error(&CommandError::NoAuth.to_string());
+    }
 
-        "GET" if v.len() == 2 => {
-            let data = data.read().unwrap();
-            if let Some(value) = data.get(&v[1].take().to_string()) {
-                let value = String::from_utf8_lossy(value).into_owned();
-                Response::KeepAlive(RespWriter::to_bulk_string(&value))
-            } else {
-                Response::build_error("ERROR: Key not found")
-            }
+    let cmd = match command::lookup(&args[0]) {
+        Some(cmd) => cmd,
+        None => {
+            session.fail_transaction();
+            return Response::build_error("ERROR: Command not recognized");
         },
+    };
 
-        "DELETE" if v.len() == 2 => {
-            let mut data = data.write().unwrap();
-            if let Some(_) = data.remove(&v[1].take().to_string()) {
-                Response::build_ok()
-            } else {
-                Response::build_error("ERROR: Key not found")
-            }
-        },
+    if session.subscriptions() > 0 && !SUBSCRIBE_MODE_COMMANDS.contains(&cmd.name) {
+        let e = CommandError::SubscribeMode(cmd.name);
+        return Response::build_error(&e.to_string());
+    }
 
-        "EXISTS" if v.len() == 2 => {
-            let data = data.read().unwrap();
-            if data.contains_key(&v[1].take().to_string()) {
-                Response::KeepAlive(RespWriter::to_integer(1))
-            } else {
-                Response::KeepAlive(RespWriter::to_integer(0))
-            }
-        },
+    if !cmd.check_arity(args.len()) {
+        session.fail_transaction();
+        let e = CommandError::WrongNumberOfArguments(cmd.name);
+        return Response::build_error(&e.to_string());
+    }
 
-        "COUNT" if v.len() == 1 => {
-            let data = data.read().unwrap();
-            Response::KeepAlive(RespWriter::to_integer(data.len()))
-        },
+    if cmd.name != "AUTH" {
+        if let Err(e) = session.check_permissions(cmd, &args) {
+            session.fail_transaction();
+            return Response::build_error(&e.to_string());
+        }
+    }
 
-        "DESTROY" if v.len() == 1 => {
-            let mut data = data.write().unwrap();
-            data.clear();
-            Response::build_ok()
-        },
+    if cmd.uses_memory() && !eviction::evict(&session.shared) {
+        session.fail_transaction();
+        return Response::build_error(&CommandError::OutOfMemory.to_string());
+    }
 
-        "QUIT" | "EXIT" if v.len() == 1 => {
-            Response::build_close_ok()
-        },
+    if !UNMONITORED_COMMANDS.contains(&cmd.name) {
+        let overflowed = session.shared.monitors.feed(session.id, session.db, &session.client.addr, &args);
+        for id in overflowed {
+            session.shared.clients.kill(id);
+        }
+    }
 
-        _ => {
-            Response::build_error("ERROR: Command not recognized")
-        },
+    if let Some(transaction) = &mut session.transaction {
+        if !TRANSACTION_COMMANDS.contains(&cmd.name) {
+            session.client.touch(session.db, cmd.name);
+            transaction.commands.push((cmd, args));
+            return Response::KeepAlive(RespWriter::to_simple_string("QUEUED").unwrap());
+        }
     }
+
+    let start = Instant::now();
+    let response = cmd.execute(session, &args);
+    let duration = start.elapsed();
+    session.shared.stats.record(cmd.name, duration);
+    session.client.touch(session.db, cmd.name);
+
+    // Blocking commands are left out of the slow log, as their time includes
+    // waiting. The entry is only built once the command turns out to be slow.
+    let (slower_than, max_len) = {
+        let config = session.shared.config.read().unwrap();
+        (config.slowlog_log_slower_than, config.slowlog_max_len)
+    };
+    let usec = duration.as_micros() as u64;
+    if slower_than >= 0 && usec >= slower_than as u64 && !matches!(cmd.handler, Handler::Blocking(_)) {
+        let timestamp = time::now_ms() / 1000;
+        let args = slowlog::truncate(&args);
+        session.shared.slowlog.lock().unwrap().push(timestamp, usec, args, &session.client.addr, max_len);
+    }
+    response
 }
 
 #[cfg(test)]
 mod test {
 
     use super::*;
+    use client;
+    use clients::Client;
+    use std::net::TcpStream;
+    use std::env;
+    use store::Data;
+    use tls::test_certs::TestCerts;
+
+    fn new_session(store: Store) -> Session {
+        let (sender, _) = mpsc::sync_channel(16);
+        Session::new(Arc::new(Shared::new(vec![store, Store::new()])), None, sender)
+    }
 
-    fn init_data() -> KvStore {
-        let mut data = HashMap::new();
-        data.insert("hello".to_string(), "world".to_string().into_bytes());
-        Arc::new(RwLock::new(data))
+    fn init_session() -> Session {
+        let mut data = Store::new();
+        data.insert("hello".to_string(), Data::String("world".to_string().into_bytes()));
+        new_session(data)
     }
 
     #[test]
     fn invalid_command() {
-        let data = Arc::new(RwLock::new(HashMap::new()));
+        let mut session = new_session(Store::new());
 
         let command = Value::BulkString("DESTROY".to_string());
-        let response = process_command(Arc::clone(&data), command);
+        let response = process_command(&mut session, command);
         let expected = Response::build_error("ERROR: Command must be an array");
         assert_eq!(response, expected);
 
         let command = Vec::new().into();
-        let response = process_command(Arc::clone(&data), command);
+        let response = process_command(&mut session, command);
         let expected = Response::build_error("ERROR: Missing command");
         assert_eq!(response, expected);
 
@@ -195,7 +416,7 @@ mod test {
             Value::BulkString("EXISTS".to_string()),
             Value::SimpleString("hello".to_string()),
         ]);
-        let response = process_command(Arc::clone(&data), command);
+        let response = process_command(&mut session, command);
         let expected = Response::build_error("ERROR: Command must be an array of BulkString");
         assert_eq!(response, expected);
     }
@@ -203,24 +424,24 @@ mod test {
     #[test]
     fn set_command() {
         let command = vec!["SET".to_string(), "hello".to_string(), "world".to_string()].into();
-        let data = Arc::new(RwLock::new(HashMap::new()));
+        let mut session = new_session(Store::new());
 
-        let response = process_command(Arc::clone(&data), command);
+        let response = process_command(&mut session, command);
         let expected = Response::build_ok();
         assert_eq!(response, expected);
 
-        let r = data.read().unwrap();
+        let r = session.store().read().unwrap();
         let value = r.get("hello").unwrap();
-        let expected = &"world".to_string().into_bytes();
+        let expected = &Data::String("world".to_string().into_bytes());
         assert_eq!(value, expected);
     }
 
     #[test]
     fn get_command() {
         let command = vec!["GET".to_string(), "hello".to_string()].into();
-        let data = init_data();
+        let mut session = init_session();
 
-        let response = process_command(Arc::clone(&data), command);
+        let response = process_command(&mut session, command);
         let expected = Response::KeepAlive(RespWriter::to_bulk_string("world"));
         assert_eq!(response, expected);
     }
@@ -228,15 +449,15 @@ mod test {
     #[test]
     fn lowercase_get_set() {
         let command = vec!["set".to_string(), "hello".to_string(), "world".to_string()].into();
-        let data = Arc::new(RwLock::new(HashMap::new()));
+        let mut session = new_session(Store::new());
 
-        let response = process_command(Arc::clone(&data), command);
+        let response = process_command(&mut session, command);
         let expected = Response::build_ok();
         assert_eq!(response, expected);
 
         let command = vec!["get".to_string(), "hello".to_string()].into();
 
-        let response = process_command(Arc::clone(&data), command);
+        let response = process_command(&mut session, command);
         let expected = Response::KeepAlive(RespWriter::to_bulk_string("world"));
         assert_eq!(response, expected);
     }
@@ -244,15 +465,15 @@ mod test {
     #[test]
     fn delete_command() {
         let command = vec!["DELETE".to_string(), "hello".to_string()].into();
-        let data = init_data();
+        let mut session = init_session();
 
-        let response = process_command(Arc::clone(&data), command);
+        let response = process_command(&mut session, command);
         let expected = Response::build_ok();
         assert_eq!(response, expected);
 
         let command = vec!["DELETE".to_string(), "hello".to_string()].into();
 
-        let response = process_command(Arc::clone(&data), command);
+        let response = process_command(&mut session, command);
         let expected = Response::build_error("ERROR: Key not found");
         assert_eq!(response, expected);
     }
@@ -260,48 +481,452 @@ mod test {
     #[test]
     fn exists_command() {
         let command = vec!["EXISTS".to_string(), "hello".to_string()].into();
-        let data = init_data();
+        let mut session = init_session();
 
-        let response = process_command(Arc::clone(&data), command);
-        let expected = Response::KeepAlive(RespWriter::to_integer(1));
+        let response = process_command(&mut session, command);
+        let expected = Response::KeepAlive(RespWriter::to_signed_integer(1));
         assert_eq!(response, expected);
 
         let command = vec!["EXISTS".to_string(), "nonexistent".to_string()].into();
 
-        let response = process_command(Arc::clone(&data), command);
-        let expected = Response::KeepAlive(RespWriter::to_integer(0));
+        let response = process_command(&mut session, command);
+        let expected = Response::KeepAlive(RespWriter::to_signed_integer(0));
         assert_eq!(response, expected);
     }
 
     #[test]
     fn count_command() {
         let command = vec!["COUNT".to_string()].into();
-        let data = init_data();
+        let mut session = init_session();
 
-        let response = process_command(Arc::clone(&data), command);
-        let expected = Response::KeepAlive(RespWriter::to_integer(1));
+        let response = process_command(&mut session, command);
+        let expected = Response::KeepAlive(RespWriter::to_signed_integer(1));
         assert_eq!(response, expected);
     }
 
     #[test]
     fn destroy_command() {
         let command = vec!["DESTROY".to_string()].into();
-        let data = init_data();
+        let mut session = init_session();
 
-        let response = process_command(Arc::clone(&data), command);
+        let response = process_command(&mut session, command);
         let expected = Response::build_ok();
         assert_eq!(response, expected);
 
-        let r = data.read().unwrap();
+        let r = session.store().read().unwrap();
         assert_eq!(r.len(), 0);
     }
 
+    #[test]
+    fn list_commands() {
+        let mut session = init_session();
+
+        let command = vec!["RPUSH".to_string(), "list".to_string(), "a".to_string()].into();
+        let response = process_command(&mut session, command);
+        let expected = Response::KeepAlive(RespWriter::to_signed_integer(1));
+        assert_eq!(response, expected);
+
+        let command = vec!["LRANGE".to_string(), "list".to_string(), "0".to_string(), "-1".to_string()].into();
+        let response = process_command(&mut session, command);
+        let expected = Response::KeepAlive(RespWriter::to_array(&["a"]));
+        assert_eq!(response, expected);
+    }
+
+    #[test]
+    fn wrong_type() {
+        let mut session = init_session();
+
+        let command = vec!["LPUSH".to_string(), "hello".to_string(), "a".to_string()].into();
+        let response = process_command(&mut session, command);
+        let expected = Response::build_error("WRONGTYPE Operation against a key holding the wrong kind of value");
+        assert_eq!(response, expected);
+
+        let command = vec!["RPUSH".to_string(), "list".to_string(), "a".to_string()].into();
+        process_command(&mut session, command);
+
+        let command = vec!["GET".to_string(), "list".to_string()].into();
+        let response = process_command(&mut session, command);
+        assert_eq!(response, expected);
+    }
+
+    #[test]
+    fn wrong_number_of_arguments() {
+        let command = vec!["GET".to_string()].into();
+        let mut session = init_session();
+
+        let response = process_command(&mut session, command);
+        let expected = Response::build_error("ERROR: Wrong number of arguments for 'GET' command");
+        assert_eq!(response, expected);
+    }
+
+    #[test]
+    fn subscribe_mode() {
+        let mut session = init_session();
+
+        let command = vec!["SUBSCRIBE".to_string(), "news".to_string()].into();
+        process_command(&mut session, command);
+
+        let command = vec!["GET".to_string(), "hello".to_string()].into();
+        let response = process_command(&mut session, command);
+        let expected = Response::build_error(
+            "ERROR: Can't execute 'GET': only (P)SUBSCRIBE / (P)UNSUBSCRIBE / PING / QUIT are allowed in this context"
+        );
+        assert_eq!(response, expected);
+
+        let command = vec!["UNSUBSCRIBE".to_string()].into();
+        process_command(&mut session, command);
+
+        let command = vec!["GET".to_string(), "hello".to_string()].into();
+        let response = process_command(&mut session, command);
+        let expected = Response::KeepAlive(RespWriter::to_bulk_string("world"));
+        assert_eq!(response, expected);
+    }
+
+    #[test]
+    fn transaction() {
+        let mut session = init_session();
+
+        let command = vec!["MULTI".to_string()].into();
+        assert_eq!(process_command(&mut session, command), Response::build_ok());
+
+        let queued = Response::KeepAlive(RespWriter::to_simple_string("QUEUED").unwrap());
+        let command = vec!["SET".to_string(), "hello".to_string(), "there".to_string()].into();
+        assert_eq!(process_command(&mut session, command), queued);
+        let command = vec!["GET".to_string(), "hello".to_string()].into();
+        assert_eq!(process_command(&mut session, command), queued);
+
+        let command = vec!["EXEC".to_string()].into();
+        let response = process_command(&mut session, command);
+        let expected = RespWriter::to_raw_array(&[
+            RespWriter::to_simple_string("OK").unwrap(),
+            RespWriter::to_bulk_string("there"),
+        ]);
+        assert_eq!(response, Response::KeepAlive(expected));
+    }
+
+    #[test]
+    fn transaction_with_queueing_error() {
+        let mut session = init_session();
+
+        let command = vec!["MULTI".to_string()].into();
+        process_command(&mut session, command);
+
+        let command = vec!["SET".to_string(), "hello".to_string(), "there".to_string()].into();
+        process_command(&mut session, command);
+        let command = vec!["GET".to_string()].into();
+        let response = process_command(&mut session, command);
+        let expected = Response::build_error("ERROR: Wrong number of arguments for 'GET' command");
+        assert_eq!(response, expected);
+
+        let command = vec!["EXEC".to_string()].into();
+        let response = process_command(&mut session, command);
+        let expected = Response::build_error("EXECABORT Transaction discarded because of previous errors");
+        assert_eq!(response, expected);
+
+        let command = vec!["GET".to_string(), "hello".to_string()].into();
+        let response = process_command(&mut session, command);
+        let expected = Response::KeepAlive(RespWriter::to_bulk_string("world"));
+        assert_eq!(response, expected);
+    }
+
+    #[test]
+    fn destroy_selected_database() {
+        let mut session = init_session();
+
+        let command = vec!["SELECT".to_string(), "1".to_string()].into();
+        assert_eq!(process_command(&mut session, command), Response::build_ok());
+        let command = vec!["SET".to_string(), "hello".to_string(), "there".to_string()].into();
+        process_command(&mut session, command);
+        let command = vec!["DESTROY".to_string()].into();
+        process_command(&mut session, command);
+
+        let command = vec!["SELECT".to_string(), "0".to_string()].into();
+        process_command(&mut session, command);
+        let command = vec!["GET".to_string(), "hello".to_string()].into();
+        let response = process_command(&mut session, command);
+        let expected = Response::KeepAlive(RespWriter::to_bulk_string("world"));
+        assert_eq!(response, expected);
+    }
+
+    #[test]
+    fn requires_authentication() {
+        let mut shared = Shared::new(vec![Store::new()]);
+        shared.acl = RwLock::new(Acl::new(Some("secret")));
+        let (sender, _) = mpsc::sync_channel(16);
+        let mut session = Session::new(Arc::new(shared), None, sender);
+
+        let command = vec!["DESTROY".to_string()].into();
+        let response = process_command(&mut session, command);
+        assert_eq!(response, Response::build_error("NOAUTH Authentication required"));
+
+        let command = vec!["UNKNOWN".to_string()].into();
+        let response = process_command(&mut session, command);
+        assert_eq!(response, Response::build_error("NOAUTH Authentication required"));
+
+        let command = vec!["auth".to_string(), "secret".to_string()].into();
+        assert_eq!(process_command(&mut session, command), Response::build_ok());
+
+        let command = vec!["DESTROY".to_string()].into();
+        assert_eq!(process_command(&mut session, command), Response::build_ok());
+    }
+
+    #[test]
+    fn acl_permissions() {
+        let mut session = init_session();
+        let command = vec!["ACL", "SETUSER", "analytics", "on", ">secret", "~metrics:*", "+@read", "+multi"];
+        let command = command.into_iter().map(String::from).collect::<Vec<_>>().into();
+        assert_eq!(process_command(&mut session, command), Response::build_ok());
+
+        let command = vec!["AUTH".to_string(), "analytics".to_string(), "secret".to_string()].into();
+        assert_eq!(process_command(&mut session, command), Response::build_ok());
+
+        let command = vec!["GET".to_string(), "metrics:cpu".to_string()].into();
+        let response = process_command(&mut session, command);
+        assert_eq!(response, Response::build_error("ERROR: Key not found"));
+
+        let command = vec!["GET".to_string(), "hello".to_string()].into();
+        let response = process_command(&mut session, command);
+        assert_eq!(response, Response::build_error("NOPERM No permissions to access a key"));
+
+        let command = vec!["DESTROY".to_string()].into();
+        let response = process_command(&mut session, command);
+        let expected = Response::build_error("NOPERM User analytics has no permissions to run the 'DESTROY' command");
+        assert_eq!(response, expected);
+
+        // denied commands fail the transaction
+        let command = vec!["MULTI".to_string()].into();
+        assert_eq!(process_command(&mut session, command), Response::build_ok());
+        let command = vec!["SET".to_string(), "metrics:cpu".to_string(), "1".to_string()].into();
+        process_command(&mut session, command);
+        assert!(session.transaction.as_ref().unwrap().failed);
+    }
+
+    #[test]
+    fn out_of_memory() {
+        let mut session = init_session();
+        let command = vec!["CONFIG".to_string(), "SET".to_string(), "maxmemory".to_string(), "1".to_string()].into();
+        assert_eq!(process_command(&mut session, command), Response::build_ok());
+
+        let command = vec!["SET".to_string(), "hello".to_string(), "there".to_string()].into();
+        let response = process_command(&mut session, command);
+        assert_eq!(response, Response::build_error("OOM command not allowed when used memory > 'maxmemory'"));
+
+        // commands that free memory still run
+        let command = vec!["DELETE".to_string(), "hello".to_string()].into();
+        assert_eq!(process_command(&mut session, command), Response::build_ok());
+
+        let command = vec!["CONFIG".to_string(), "SET".to_string(), "maxmemory-policy".to_string(), "allkeys-lru".to_string()].into();
+        process_command(&mut session, command);
+        let command = vec!["SET".to_string(), "hello".to_string(), "there".to_string()].into();
+        assert_eq!(process_command(&mut session, command), Response::build_ok());
+        let command = vec!["SET".to_string(), "other".to_string(), "key".to_string()].into();
+        assert_eq!(process_command(&mut session, command), Response::build_ok());
+        assert_eq!(session.store().read().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn slow_commands_are_logged() {
+        let mut session = init_session();
+        session.client = Arc::new(Client::new(session.id, "127.0.0.1:5000".to_string(), None));
+        session.shared.config.write().unwrap().slowlog_log_slower_than = 0;
+
+        let command = vec!["GET".to_string(), "hello".to_string()].into();
+        process_command(&mut session, command);
+
+        let slowlog = session.shared.slowlog.lock().unwrap();
+        let entry = slowlog.get(1).next().unwrap();
+        assert_eq!(entry.args, ["GET", "hello"]);
+        assert_eq!(entry.addr, "127.0.0.1:5000");
+    }
+
+    #[test]
+    fn monitor_command() {
+        let mut session = init_session();
+        let (sender, receiver) = mpsc::sync_channel(16);
+        let mut monitor = Session::new(Arc::clone(&session.shared), None, sender);
+
+        let command = vec!["MONITOR".to_string()].into();
+        assert_eq!(process_command(&mut monitor, command), Response::build_ok());
+
+        let command = vec!["GET".to_string(), "hello".to_string()].into();
+        process_command(&mut session, command);
+        let command = vec!["AUTH".to_string(), "secret".to_string()].into();
+        process_command(&mut session, command);
+
+        assert!(receiver.try_recv().unwrap().ends_with("[0 ] \"GET\" \"hello\"\r\n"));
+        assert!(receiver.try_recv().is_err());
+
+        drop(monitor);
+        assert!(session.shared.monitors.is_empty());
+    }
+
+    /// Starts a TLS server with the certificates and returns the configuration
+    /// to connect to it.
+    fn spawn_tls_server(certs: &TestCerts) -> client::config::Config {
+        let tls_config = tls::server_config(&certs.server_cert, &certs.server_key, Some(&certs.ca_cert)).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let incoming = listener.incoming().map(|stream| stream.map(Socket::Tcp));
+            accept(incoming, Arc::new(Shared::new(vec![Store::new()])), Some(tls_config))
+        });
+
+        client::config::Config {
+            ip: addr.ip(),
+            port: addr.port(),
+            tls: Some(client::TlsConfig {
+                ca_cert_file: certs.ca_cert.clone(),
+                cert_file: Some(certs.client_cert.clone()),
+                key_file: Some(certs.client_key.clone()),
+                server_name: None,
+            }),
+            socket: None,
+        }
+    }
+
+    #[test]
+    fn tls_connection() {
+        let certs = TestCerts::generate("server");
+        let mut stream = client::connect(&spawn_tls_server(&certs)).unwrap();
+
+        let mut send = |command: &[&str]| {
+            stream.write_all(RespWriter::to_array(command).as_bytes()).unwrap();
+            let mut reader = RespReader::new();
+            reader.frame_message(&mut stream).unwrap();
+            reader.value
+        };
+        assert_eq!(send(&["SET", "hello", "world"]), Value::SimpleString("OK".to_string()));
+        assert_eq!(send(&["GET", "hello"]), Value::BulkString("world".to_string()));
+    }
+
+    #[test]
+    fn tls_pipelining() {
+        let certs = TestCerts::generate("pipelining");
+        let mut stream = client::connect(&spawn_tls_server(&certs)).unwrap();
+
+        // Neither the commands nor the replies fit in the socket buffers, so
+        // the server must keep reading while its writer waits for the client.
+        let value = "a".repeat(1 << 14);
+        let count = 1000;
+        let (done, finished) = mpsc::channel();
+        thread::spawn(move || {
+            let pipeline = RespWriter::to_array(&["SET", "key", &value]) + &RespWriter::to_array(&["GET", "key"]);
+            for _ in 0..count {
+                stream.write_all(pipeline.as_bytes()).unwrap();
+            }
+
+            let mut reader = RespReader::new();
+            for _ in 0..count {
+                reader.frame_message(&mut stream).unwrap();
+                assert_eq!(reader.value.take(), Value::SimpleString("OK".to_string()));
+                reader.frame_message(&mut stream).unwrap();
+                assert_eq!(reader.value.take(), Value::BulkString(value.clone()));
+            }
+            done.send(()).unwrap();
+        });
+        finished.recv_timeout(Duration::from_secs(30)).expect("pipelining over TLS stalled");
+    }
+
+    #[test]
+    fn client_kill_closes_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let incoming = listener.incoming().map(|stream| stream.map(Socket::Tcp));
+            accept(incoming, Arc::new(Shared::new(vec![Store::new()])), None)
+        });
+
+        let send = |stream: &mut TcpStream, command: &[&str]| {
+            stream.write_all(RespWriter::to_array(command).as_bytes()).unwrap();
+            let mut reader = RespReader::new();
+            reader.frame_message(stream).unwrap();
+            reader.value
+        };
+        let mut killer = TcpStream::connect(addr).unwrap();
+        let mut victim = TcpStream::connect(addr).unwrap();
+        let id = match send(&mut victim, &["CLIENT", "ID"]) {
+            Value::Integer(id) => id.to_string(),
+            value => panic!("unexpected reply {:?}", value),
+        };
+
+        match send(&mut killer, &["CLIENT", "LIST"]) {
+            Value::BulkString(list) => assert!(list.contains(&format!("addr={}", victim.local_addr().unwrap()))),
+            value => panic!("unexpected reply {:?}", value),
+        }
+        assert_eq!(send(&mut killer, &["CLIENT", "KILL", "ID", &id]), Value::Integer(1));
+        assert_eq!(victim.read(&mut [0; 1]).unwrap(), 0);
+    }
+
+    #[test]
+    fn large_reply_before_blocking_pop() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let incoming = listener.incoming().map(|stream| stream.map(Socket::Tcp));
+            accept(incoming, Arc::new(Shared::new(vec![Store::new()])), None)
+        });
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let mut reader = RespReader::new();
+        let value = "a".repeat(8 << 20);
+        stream.write_all(RespWriter::to_array(&["SET", "big", &value]).as_bytes()).unwrap();
+        reader.frame_message(&mut stream).unwrap();
+
+        // the reply fills the socket while BLPOP checks the connection
+        let pipeline = RespWriter::to_array(&["GET", "big"]) + &RespWriter::to_array(&["BLPOP", "list", "1"]);
+        stream.write_all(pipeline.as_bytes()).unwrap();
+        thread::sleep(Duration::from_millis(500));
+
+        reader.frame_message(&mut stream).unwrap();
+        assert_eq!(reader.value, Value::BulkString(value));
+        reader.frame_message(&mut stream).unwrap();
+        assert_eq!(reader.value, Value::Nil);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unix_socket_connection() {
+        let path = env::temp_dir().join(format!("hanbaiki-test-{}.sock", process::id()));
+        let listener = bind_unix(&path, Some(0o700)).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o700);
+
+        thread::spawn(move || {
+            let incoming = listener.incoming().map(|stream| stream.map(Socket::Unix));
+            accept(incoming, Arc::new(Shared::new(vec![Store::new()])), None)
+        });
+
+        let send = |stream: &mut client::Stream, command: &[&str]| {
+            stream.write_all(RespWriter::to_array(command).as_bytes()).unwrap();
+            let mut reader = RespReader::new();
+            reader.frame_message(stream).unwrap();
+            reader.value
+        };
+        let config = client::config::Config { ip: "127.0.0.1".parse().unwrap(), port: 0, tls: None, socket: Some(path.clone()) };
+        let mut stream = client::connect(&config).unwrap();
+        assert_eq!(send(&mut stream, &["PING"]), Value::SimpleString("PONG".to_string()));
+
+        // each client has its own address
+        let mut victim = client::connect(&config).unwrap();
+        let id = match send(&mut victim, &["CLIENT", "ID"]) {
+            Value::Integer(id) => id,
+            value => panic!("unexpected reply {:?}", value),
+        };
+        let addr = format!("{}:{}", path.display(), id);
+        assert_eq!(send(&mut stream, &["CLIENT", "KILL", &addr]), Value::SimpleString("OK".to_string()));
+        assert_eq!(victim.read(&mut [0; 1]).unwrap(), 0);
+        assert_eq!(send(&mut stream, &["PING"]), Value::SimpleString("PONG".to_string()));
+
+        // a socket file left behind is replaced
+        assert!(bind_unix(&path, None).is_some());
+        fs::remove_file(&path).unwrap();
+    }
+
     #[test]
     fn quit_command() {
         let command = vec!["QUIT".to_string()].into();
-        let data = init_data();
+        let mut session = init_session();
 
-        let response = process_command(Arc::clone(&data), command);
+        let response = process_command(&mut session, command);
         let expected = Response::build_close_ok();
         assert_eq!(response, expected);
     }
@@ -0,0 +1,262 @@
+//! The state shared by all connections and the state of each connection.
+
+use std::collections::BTreeSet;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::SyncSender;
+
+use acl::{Acl, DEFAULT_USER};
+use clients::{Client, Clients};
+use command::{Blocked, Command, CommandError};
+use config::Config;
+use monitor::Monitors;
+use pubsub::PubSub;
+use slowlog::SlowLog;
+use socket::Socket;
+use stats::Stats;
+use store::Store;
+
+/// The most replies and messages waiting to be written to a client. A
+/// subscriber or monitor that falls this far behind is disconnected, instead
+/// of using memory without limit.
+pub const OUTPUT_BUFFER_LIMIT: usize = 10_000;
+
+/// The state shared by all connections.
+pub struct Shared {
+    /// The numbered databases, each with its own keyspace. They're written
+    /// through `write` and `write_all`, so that the used memory is kept up
+    /// to date.
+    pub databases: Vec<RwLock<Store>>,
+
+    /// The estimated memory used by all databases, in bytes.
+    pub used_memory: Arc<AtomicUsize>,
+
+    pub pubsub: Mutex<PubSub>,
+    pub blocked: Mutex<Blocked>,
+
+    /// The users and their permissions.
+    pub acl: RwLock<Acl>,
+
+    /// The settings the server runs with, which CONFIG SET changes.
+    pub config: RwLock<Config>,
+
+    pub stats: Stats,
+    pub slowlog: Mutex<SlowLog>,
+    pub monitors: Monitors,
+    pub clients: Clients,
+
+    next_id: AtomicU64,
+}
+
+impl Shared {
+    pub fn new(databases: Vec<Store>) -> Self {
+        let used_memory = Arc::new(AtomicUsize::new(0));
+        let databases = databases.into_iter()
+            .map(|mut store| {
+                store.share_memory_counter(Arc::clone(&used_memory));
+                RwLock::new(store)
+            })
+            .collect();
+
+        Shared {
+            databases,
+            used_memory,
+            pubsub: Mutex::new(PubSub::new()),
+            blocked: Mutex::new(Blocked::new()),
+            acl: RwLock::new(Acl::new(None)),
+            config: RwLock::new(Config::default()),
+            stats: Stats::new(),
+            slowlog: Mutex::new(SlowLog::new()),
+            monitors: Monitors::new(),
+            clients: Clients::new(),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    /// Locks every database for reading, in the same order as `write_all`.
+    pub fn read_all(&self) -> Vec<RwLockReadGuard<'_, Store>> {
+        self.databases.iter().map(|db| db.read().unwrap()).collect()
+    }
+
+    /// Locks a database for writing.
+    pub fn write(&self, db: usize) -> StoreGuard<'_> {
+        StoreGuard(self.databases[db].write().unwrap())
+    }
+
+    /// Locks every database for writing, always in the same order so that
+    /// threads doing the same can't deadlock.
+    pub fn write_all(&self) -> Vec<StoreGuard<'_>> {
+        (0..self.databases.len()).map(|db| self.write(db)).collect()
+    }
+}
+
+/// A write lock on a database, which measures the keys modified under it
+/// when released.
+pub struct StoreGuard<'a>(RwLockWriteGuard<'a, Store>);
+
+impl<'a> Deref for StoreGuard<'a> {
+    type Target = Store;
+
+    fn deref(&self) -> &Store {
+        &self.0
+    }
+}
+
+impl<'a> DerefMut for StoreGuard<'a> {
+    fn deref_mut(&mut self) -> &mut Store {
+        &mut self.0
+    }
+}
+
+impl<'a> Drop for StoreGuard<'a> {
+    fn drop(&mut self) {
+        self.0.update_used_memory();
+    }
+}
+
+/// Commands queued between MULTI and EXEC.
+#[derive(Default)]
+pub struct Transaction {
+    pub commands: Vec<(&'static Command, Vec<String>)>,
+
+    /// Set when a command fails to queue, so EXEC discards the transaction.
+    pub failed: bool,
+}
+
+/// The state of a client connection.
+pub struct Session {
+    pub id: u64,
+    pub shared: Arc<Shared>,
+
+    /// Whether the client may run commands other than AUTH.
+    pub authenticated: bool,
+
+    /// The user the client is authenticated as, or will be once it
+    /// authenticates without a username.
+    pub user: String,
+
+    /// The index of the selected database.
+    pub db: usize,
+
+    /// The entry of the connection in the registry of clients.
+    pub client: Arc<Client>,
+
+    /// Sends replies and pushed messages to the writer of the connection,
+    /// holding at most `OUTPUT_BUFFER_LIMIT` of them.
+    pub sender: SyncSender<String>,
+
+    pub channels: BTreeSet<String>,
+    pub patterns: BTreeSet<String>,
+
+    /// Whether the connection receives the commands of the others.
+    pub monitoring: bool,
+
+    /// The transaction being queued after MULTI.
+    pub transaction: Option<Transaction>,
+
+    /// The databases and keys watched by WATCH, with the versions of the
+    /// keys at the time.
+    pub watched: Vec<(usize, String, u64)>,
+}
+
+impl Session {
+    /// Creates the session of a connection and registers its client, with the
+    /// address of the socket if any.
+    pub fn new(shared: Arc<Shared>, socket: Option<Socket>, sender: SyncSender<String>) -> Self {
+        let id = shared.next_id.fetch_add(1, Ordering::Relaxed);
+        // Without a password on the default user, there's no need for AUTH.
+        let authenticated = shared.acl.read().unwrap()
+            .user(DEFAULT_USER)
+            .is_some_and(|user| user.enabled && user.nopass);
+        let addr = socket.as_ref().map_or(String::new(), |socket| socket.peer_addr(id));
+        let client = Arc::new(Client::new(id, addr, socket));
+        shared.clients.register(Arc::clone(&client));
+
+        Session {
+            id,
+            shared,
+            authenticated,
+            user: DEFAULT_USER.to_string(),
+            db: 0,
+            client,
+            sender,
+            channels: BTreeSet::new(),
+            patterns: BTreeSet::new(),
+            monitoring: false,
+            transaction: None,
+            watched: Vec::new(),
+        }
+    }
+
+    /// Returns the selected database.
+    pub fn store(&self) -> &RwLock<Store> {
+        &self.shared.databases[self.db]
+    }
+
+    /// Returns the number of channels and patterns subscribed to.
+    pub fn subscriptions(&self) -> usize {
+        self.channels.len() + self.patterns.len()
+    }
+
+    /// Returns false if the client closed the connection, without consuming
+    /// any data it sent.
+    pub fn is_connected(&self) -> bool {
+        self.client.socket().is_none_or(Socket::is_connected)
+    }
+
+    /// Stops watching all keys.
+    pub fn unwatch(&mut self) {
+        for (db, key, _) in self.watched.drain(..) {
+            self.shared.write(db).unwatch(&key);
+        }
+    }
+
+    /// Checks that the user may run the command on its keys.
+    pub fn check_permissions(&self, cmd: &Command, args: &[String]) -> Result<(), CommandError> {
+        let acl = self.shared.acl.read().unwrap();
+        let user = match acl.user(&self.user) {
+            Some(user) if user.enabled => user,
+            // The user was deleted or disabled after authenticating.
+            _ => return Err(CommandError::NoAuth),
+        };
+
+        if !user.can_run(cmd) {
+            return Err(CommandError::CommandNotAllowed(self.user.clone(), cmd.name));
+        }
+        if !cmd.keys(args).iter().all(|key| user.can_access(key)) {
+            return Err(CommandError::KeyNotAllowed);
+        }
+        Ok(())
+    }
+
+    /// Marks the transaction being queued, if any, as failed.
+    pub fn fail_transaction(&mut self) {
+        if let Some(transaction) = &mut self.transaction {
+            transaction.failed = true;
+        }
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.unwatch();
+        self.shared.clients.unregister(self.id);
+
+        if self.monitoring {
+            self.shared.monitors.remove(self.id);
+        }
+
+        if self.subscriptions() == 0 {
+            return;
+        }
+
+        let mut pubsub = self.shared.pubsub.lock().unwrap();
+        for channel in &self.channels {
+            pubsub.unsubscribe(channel, self.id);
+        }
+        for pattern in &self.patterns {
+            pubsub.punsubscribe(pattern, self.id);
+        }
+    }
+}
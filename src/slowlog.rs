@@ -0,0 +1,121 @@
+//! The slow log, keeping the last commands that took longer than
+//! `slowlog-log-slower-than` to run.
+
+use std::collections::VecDeque;
+
+/// Commands are logged with at most this many arguments.
+const MAX_ARGS: usize = 32;
+
+/// Arguments are logged with at most this many bytes.
+const MAX_ARG_LEN: usize = 128;
+
+#[derive(Debug, PartialEq)]
+pub struct Entry {
+    pub id: u64,
+
+    /// Seconds since the UNIX epoch when the command ran.
+    pub timestamp: u64,
+
+    /// How long the command took to run, in microseconds.
+    pub duration: u64,
+
+    /// The command and its arguments, truncated.
+    pub args: Vec<String>,
+
+    /// The address of the client that sent the command.
+    pub addr: String,
+}
+
+/// The most recent slow commands, newest first.
+#[derive(Default)]
+pub struct SlowLog {
+    entries: VecDeque<Entry>,
+    next_id: u64,
+}
+
+impl SlowLog {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Adds an entry, dropping the oldest ones beyond `max_len`. The
+    /// arguments should be truncated first.
+    pub fn push(&mut self, timestamp: u64, duration: u64, args: Vec<String>, addr: &str, max_len: usize) {
+        let entry = Entry { id: self.next_id, timestamp, duration, args, addr: addr.to_string() };
+        self.next_id += 1;
+        self.entries.push_front(entry);
+        self.entries.truncate(max_len);
+    }
+
+    /// Returns the `count` most recent entries, newest first.
+    pub fn get(&self, count: usize) -> impl Iterator<Item = &Entry> {
+        self.entries.iter().take(count)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn reset(&mut self) {
+        self.entries.clear();
+    }
+}
+
+/// Truncates long commands and arguments, noting how much was left out.
+pub fn truncate(args: &[String]) -> Vec<String> {
+    let mut truncated: Vec<String> = args.iter()
+        .take(if args.len() > MAX_ARGS { MAX_ARGS - 1 } else { MAX_ARGS })
+        .map(|arg| {
+            if arg.len() <= MAX_ARG_LEN {
+                return arg.clone();
+            }
+            let mut end = MAX_ARG_LEN;
+            while !arg.is_char_boundary(end) {
+                end -= 1;
+            }
+            format!("{}... ({} more bytes)", &arg[..end], arg.len() - end)
+        })
+        .collect();
+
+    if args.len() > MAX_ARGS {
+        truncated.push(format!("... ({} more arguments)", args.len() - MAX_ARGS + 1));
+    }
+    truncated
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn bounded_and_newest_first() {
+        let mut slowlog = SlowLog::new();
+        for i in 0..5 {
+            slowlog.push(100, i, vec!["GET".to_string(), format!("key{}", i)], "127.0.0.1:5000", 3);
+        }
+        assert_eq!(slowlog.len(), 3);
+
+        let ids: Vec<u64> = slowlog.get(10).map(|entry| entry.id).collect();
+        assert_eq!(ids, [4, 3, 2]);
+        let entry = slowlog.get(1).next().unwrap();
+        assert_eq!(entry.args, ["GET", "key4"]);
+
+        slowlog.reset();
+        assert_eq!(slowlog.len(), 0);
+        slowlog.push(100, 1, Vec::new(), "", 3);
+        assert_eq!(slowlog.get(1).next().unwrap().id, 5);
+    }
+
+    #[test]
+    fn truncate_long_commands() {
+        let args: Vec<String> = (0..40).map(|i| i.to_string()).collect();
+        let truncated = truncate(&args);
+        assert_eq!(truncated.len(), MAX_ARGS);
+        assert_eq!(truncated[30], "30");
+        assert_eq!(truncated[31], "... (9 more arguments)");
+
+        let truncated = truncate(&["x".repeat(200)]);
+        assert_eq!(truncated[0], format!("{}... (72 more bytes)", "x".repeat(128)));
+    }
+}
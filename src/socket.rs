@@ -0,0 +1,181 @@
+//! The sockets clients connect through: TCP, or Unix domain sockets on
+//! platforms that have them.
+
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::time::Duration;
+#[cfg(unix)]
+use std::os::unix::io::{AsRawFd, RawFd};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+
+#[cfg(unix)]
+use libc;
+
+pub enum Socket {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Socket {
+    pub fn try_clone(&self) -> io::Result<Socket> {
+        match self {
+            Socket::Tcp(stream) => stream.try_clone().map(Socket::Tcp),
+            #[cfg(unix)]
+            Socket::Unix(stream) => stream.try_clone().map(Socket::Unix),
+        }
+    }
+
+    /// Returns the address of the peer as `ip:port`. Peers of a Unix socket
+    /// have no address, so theirs is the path of the socket followed by the
+    /// ID of the connection, to tell them apart.
+    pub fn peer_addr(&self, id: u64) -> String {
+        match self {
+            Socket::Tcp(stream) => stream.peer_addr().map(|addr| addr.to_string()).unwrap_or_default(),
+            #[cfg(unix)]
+            Socket::Unix(stream) => stream.local_addr().ok()
+                .and_then(|addr| addr.as_pathname().map(|path| format!("{}:{}", path.display(), id)))
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Sets how long reads wait for data before failing, or None to wait
+    /// forever.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            Socket::Tcp(stream) => stream.set_read_timeout(timeout),
+            #[cfg(unix)]
+            Socket::Unix(stream) => stream.set_read_timeout(timeout),
+        }
+    }
+
+    /// Shuts down reads, writes or both, also for every clone of the socket.
+    pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        match self {
+            Socket::Tcp(stream) => stream.shutdown(how),
+            #[cfg(unix)]
+            Socket::Unix(stream) => stream.shutdown(how),
+        }
+    }
+
+    /// Returns false if the peer closed the connection, without consuming
+    /// any data it sent.
+    pub fn is_connected(&self) -> bool {
+        match self {
+            #[cfg(unix)]
+            Socket::Tcp(stream) => peek_connected(stream.as_raw_fd()),
+            // Peeking without MSG_DONTWAIT would need the socket switched to
+            // non-blocking, which its clones share.
+            #[cfg(not(unix))]
+            Socket::Tcp(_) => true,
+            #[cfg(unix)]
+            Socket::Unix(stream) => peek_connected(stream.as_raw_fd()),
+        }
+    }
+}
+
+/// Peeks at the socket without waiting. Unlike `set_nonblocking`, this
+/// leaves the socket in blocking mode for the writer, which shares it.
+#[cfg(unix)]
+fn peek_connected(fd: RawFd) -> bool {
+    let mut buf = [0u8; 1];
+    let flags = libc::MSG_PEEK | libc::MSG_DONTWAIT;
+    let n = unsafe { libc::recv(fd, buf.as_mut_ptr() as *mut libc::c_void, 1, flags) };
+    match n {
+        0 => false,
+        n if n > 0 => true,
+        _ => io::Error::last_os_error().kind() == io::ErrorKind::WouldBlock,
+    }
+}
+
+impl Read for Socket {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Socket::Tcp(stream) => stream.read(buf),
+            #[cfg(unix)]
+            Socket::Unix(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Socket {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Socket::Tcp(stream) => stream.write(buf),
+            #[cfg(unix)]
+            Socket::Unix(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Socket::Tcp(stream) => stream.flush(),
+            #[cfg(unix)]
+            Socket::Unix(stream) => stream.flush(),
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn tcp_is_connected() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let server = Socket::Tcp(listener.accept().unwrap().0);
+        assert!(server.is_connected());
+
+        client.write_all(b"a").unwrap();
+        assert!(server.is_connected());
+        drop(client);
+
+        let mut server = server;
+        let mut buf = [0; 1];
+        assert_eq!(server.read(&mut buf).unwrap(), 1);
+        assert!(!server.is_connected());
+    }
+
+    #[test]
+    fn is_connected_keeps_clones_blocking() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let server = Socket::Tcp(listener.accept().unwrap().0);
+
+        let mut writer = server.try_clone().unwrap();
+        let writer = thread::spawn(move || {
+            (0..200).try_for_each(|_| writer.write_all(&[b'a'; 1 << 16]))
+        });
+        let reader = thread::spawn(move || io::copy(&mut client, &mut io::sink()));
+
+        while !writer.is_finished() {
+            assert!(server.is_connected());
+        }
+        writer.join().unwrap().unwrap();
+        server.shutdown(Shutdown::Write).unwrap();
+        assert_eq!(reader.join().unwrap().unwrap(), 200 << 16);
+    }
+
+    #[test]
+    fn unix_is_connected() {
+        let (mut client, server) = UnixStream::pair().unwrap();
+        let mut server = Socket::Unix(server);
+        assert!(server.is_connected());
+
+        // pending data isn't consumed
+        client.write_all(b"a").unwrap();
+        assert!(server.is_connected());
+        assert!(server.is_connected());
+        drop(client);
+        assert!(server.is_connected());
+
+        let mut buf = [0; 1];
+        assert_eq!(server.read(&mut buf).unwrap(), 1);
+        assert!(!server.is_connected());
+    }
+}
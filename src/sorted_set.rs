@@ -0,0 +1,456 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::mem;
+
+use store;
+
+/// A score that can be totally ordered. Scores are never NaN.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Score(f64);
+
+impl Eq for Score {}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Score {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.partial_cmp(&other.0).expect("Score must not be NaN")
+    }
+}
+
+/// A boundary of a score range, e.g. `1`, `(1` or `+inf` in ZRANGEBYSCORE.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScoreBound {
+    Inclusive(f64),
+    Exclusive(f64),
+}
+
+/// A set of unique members ordered by score, with ties ordered by member.
+///
+/// Members are indexed both by name, to look up a score in constant time, and
+/// by (score, member) in a tree that counts the members under each node, to
+/// find a member by rank or the rank of a member in O(log n).
+#[derive(Debug, Default)]
+pub struct SortedSet {
+    scores: HashMap<Vec<u8>, f64>,
+    ordered: Link,
+
+    /// Hashes members into tree priorities, with keys random to this set.
+    priorities: RandomState,
+}
+
+impl PartialEq for SortedSet {
+    fn eq(&self, other: &Self) -> bool {
+        // The shape of the tree depends on the keys of `priorities`.
+        self.scores == other.scores
+    }
+}
+
+impl SortedSet {
+    pub fn new() -> Self {
+        SortedSet {
+            scores: HashMap::new(),
+            ordered: None,
+            priorities: RandomState::new(),
+        }
+    }
+
+    /// Estimates the memory used, in bytes. Members are stored twice, once
+    /// by member and once ordered by score.
+    pub fn memory_usage(&self) -> usize {
+        let sizes = self.scores.keys().map(|member| {
+            mem::size_of::<(Vec<u8>, f64)>() + mem::size_of::<Node>() + 2 * member.capacity()
+        });
+        store::estimate_size(self.len(), sizes)
+    }
+
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    pub fn score(&self, member: &[u8]) -> Option<f64> {
+        self.scores.get(member).cloned()
+    }
+
+    /// Adds the member or updates its score. Returns true if the member is new.
+    pub fn insert(&mut self, member: Vec<u8>, score: f64) -> bool {
+        debug_assert!(!score.is_nan());
+
+        let is_new = match self.scores.insert(member.clone(), score) {
+            Some(old) => {
+                remove(&mut self.ordered, &(Score(old), member.clone()));
+                false
+            },
+            None => true,
+        };
+        let priority = self.priorities.hash_one(&member);
+        insert(&mut self.ordered, (Score(score), member), priority);
+        is_new
+    }
+
+    /// Removes the member. Returns true if the member was present.
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        match self.scores.remove(member) {
+            Some(score) => {
+                remove(&mut self.ordered, &(Score(score), member.to_vec()));
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Returns the 0-based position of the member in ascending order.
+    pub fn rank(&self, member: &[u8]) -> Option<usize> {
+        let key = (Score(self.score(member)?), member.to_vec());
+        Some(self.count_before(|other| *other < key))
+    }
+
+    /// Iterates over the members and their scores in ascending order.
+    /// Skipping members with `skip` or `nth` takes O(log n), from either end.
+    pub fn iter(&self) -> Iter<'_> {
+        Iter::new(&self.ordered, 0, self.len())
+    }
+
+    /// Iterates in ascending order over the members with scores between
+    /// `min` and `max`.
+    pub fn range_by_score(&self, min: ScoreBound, max: ScoreBound) -> Iter<'_> {
+        let start = self.count_before(|&(score, _)| match min {
+            ScoreBound::Inclusive(min) => score.0 < min,
+            ScoreBound::Exclusive(min) => score.0 <= min,
+        });
+        let end = self.count_before(|&(score, _)| match max {
+            ScoreBound::Inclusive(max) => score.0 <= max,
+            ScoreBound::Exclusive(max) => score.0 < max,
+        });
+        Iter::new(&self.ordered, start, end.max(start))
+    }
+
+    /// Counts the members whose keys satisfy `before`, which must hold for
+    /// every key up to some point in the order and for none after it.
+    fn count_before<F: Fn(&Key) -> bool>(&self, before: F) -> usize {
+        let mut count = 0;
+        let mut link = &self.ordered;
+        while let Some(node) = link {
+            if before(&node.key) {
+                count += size(&node.left) + 1;
+                link = &node.right;
+            } else {
+                link = &node.left;
+            }
+        }
+        count
+    }
+}
+
+type Key = (Score, Vec<u8>);
+type Link = Option<Box<Node>>;
+
+/// A node of a treap: a binary search tree on keys that is also a heap on
+/// priorities that clients can't predict, which keeps it balanced in
+/// expectation.
+#[derive(Debug)]
+struct Node {
+    key: Key,
+    priority: u64,
+    /// The number of nodes in the subtree rooted here.
+    size: usize,
+    left: Link,
+    right: Link,
+}
+
+impl Node {
+    fn update_size(&mut self) {
+        self.size = 1 + size(&self.left) + size(&self.right);
+    }
+}
+
+fn size(link: &Link) -> usize {
+    link.as_ref().map_or(0, |node| node.size)
+}
+
+/// Splits the tree into the keys less than `key` and the rest.
+fn split(link: Link, key: &Key) -> (Link, Link) {
+    match link {
+        None => (None, None),
+        Some(mut node) => {
+            if node.key < *key {
+                let (less, rest) = split(node.right.take(), key);
+                node.right = less;
+                node.update_size();
+                (Some(node), rest)
+            } else {
+                let (less, rest) = split(node.left.take(), key);
+                node.left = rest;
+                node.update_size();
+                (less, Some(node))
+            }
+        },
+    }
+}
+
+/// Joins two trees, where every key of `left` is less than those of `right`.
+fn merge(left: Link, right: Link) -> Link {
+    match (left, right) {
+        (None, link) | (link, None) => link,
+        (Some(mut left), Some(mut right)) => {
+            if left.priority > right.priority {
+                left.right = merge(left.right.take(), Some(right));
+                left.update_size();
+                Some(left)
+            } else {
+                right.left = merge(Some(left), right.left.take());
+                right.update_size();
+                Some(right)
+            }
+        },
+    }
+}
+
+fn insert(root: &mut Link, key: Key, priority: u64) {
+    let (less, rest) = split(root.take(), &key);
+    let node = Box::new(Node { key, priority, size: 1, left: None, right: None });
+    *root = merge(merge(less, Some(node)), rest);
+}
+
+/// Removes the key, which must be in the tree.
+fn remove(link: &mut Link, key: &Key) {
+    let ordering = match link {
+        Some(node) => key.cmp(&node.key),
+        None => return,
+    };
+    if ordering == Ordering::Equal {
+        let node = *link.take().unwrap();
+        *link = merge(node.left, node.right);
+        return;
+    }
+
+    let node = link.as_mut().unwrap();
+    if ordering == Ordering::Less {
+        remove(&mut node.left, key);
+    } else {
+        remove(&mut node.right, key);
+    }
+    node.update_size();
+}
+
+/// An iterator over the members ranked from `front` up to `back`, exclusive.
+///
+/// Each stack holds the nodes left to visit from its end, nearest on top,
+/// whose subtrees on the far side have not been visited.
+pub struct Iter<'a> {
+    root: &'a Link,
+    front: usize,
+    back: usize,
+    front_stack: Vec<&'a Node>,
+    back_stack: Vec<&'a Node>,
+}
+
+impl<'a> Iter<'a> {
+    fn new(root: &'a Link, front: usize, back: usize) -> Self {
+        let mut iter = Iter { root, front, back, front_stack: vec![], back_stack: vec![] };
+        if front < back {
+            iter.seek_front();
+            iter.seek_back();
+        }
+        iter
+    }
+
+    /// Fills the front stack with the path to the member ranked `front`.
+    fn seek_front(&mut self) {
+        self.front_stack.clear();
+        let mut rank = self.front;
+        let mut link = self.root;
+        while let Some(node) = link {
+            let left = size(&node.left);
+            if rank < left {
+                self.front_stack.push(node);
+                link = &node.left;
+            } else if rank == left {
+                self.front_stack.push(node);
+                return;
+            } else {
+                rank -= left + 1;
+                link = &node.right;
+            }
+        }
+    }
+
+    /// Fills the back stack with the path to the member ranked `back - 1`.
+    fn seek_back(&mut self) {
+        self.back_stack.clear();
+        let mut rank = self.back - 1;
+        let mut link = self.root;
+        while let Some(node) = link {
+            let left = size(&node.left);
+            if rank < left {
+                link = &node.left;
+            } else if rank == left {
+                self.back_stack.push(node);
+                return;
+            } else {
+                self.back_stack.push(node);
+                rank -= left + 1;
+                link = &node.right;
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (&'a [u8], f64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.front == self.back {
+            return None;
+        }
+        let node = self.front_stack.pop()?;
+        let mut link = &node.right;
+        while let Some(next) = link {
+            self.front_stack.push(next);
+            link = &next.left;
+        }
+        self.front += 1;
+        Some((node.key.1.as_slice(), (node.key.0).0))
+    }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        if n >= self.back - self.front {
+            self.front = self.back;
+            return None;
+        }
+        if n > 0 {
+            self.front += n;
+            self.seek_front();
+        }
+        self.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.back - self.front;
+        (len, Some(len))
+    }
+}
+
+impl<'a> DoubleEndedIterator for Iter<'a> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.front == self.back {
+            return None;
+        }
+        let node = self.back_stack.pop()?;
+        let mut link = &node.left;
+        while let Some(next) = link {
+            self.back_stack.push(next);
+            link = &next.right;
+        }
+        self.back -= 1;
+        Some((node.key.1.as_slice(), (node.key.0).0))
+    }
+
+    fn nth_back(&mut self, n: usize) -> Option<Self::Item> {
+        if n >= self.back - self.front {
+            self.back = self.front;
+            return None;
+        }
+        if n > 0 {
+            self.back -= n;
+            self.seek_back();
+        }
+        self.next_back()
+    }
+}
+
+impl<'a> ExactSizeIterator for Iter<'a> {}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    fn members<'a, I: Iterator<Item = (&'a [u8], f64)>>(iter: I) -> Vec<&'a str> {
+        iter.map(|(m, _)| ::std::str::from_utf8(m).unwrap()).collect()
+    }
+
+    fn init_set() -> SortedSet {
+        let mut set = SortedSet::new();
+        set.insert(b"c".to_vec(), 2.0);
+        set.insert(b"a".to_vec(), 1.0);
+        set.insert(b"b".to_vec(), 2.0);
+        set.insert(b"d".to_vec(), 3.5);
+        set
+    }
+
+    #[test]
+    fn insert_and_remove() {
+        let mut set = init_set();
+
+        assert!(!set.insert(b"a".to_vec(), 5.0));
+        assert_eq!(set.score(b"a"), Some(5.0));
+        assert_eq!(members(set.iter()), vec!["b", "c", "d", "a"]);
+
+        assert!(set.remove(b"b"));
+        assert!(!set.remove(b"b"));
+        assert_eq!(set.len(), 3);
+        assert_eq!(members(set.iter()), vec!["c", "d", "a"]);
+    }
+
+    #[test]
+    fn ties_ordered_by_member() {
+        let set = init_set();
+
+        assert_eq!(members(set.iter()), vec!["a", "b", "c", "d"]);
+        assert_eq!(members(set.iter().rev()), vec!["d", "c", "b", "a"]);
+        assert_eq!(set.rank(b"b"), Some(1));
+        assert_eq!(set.rank(b"c"), Some(2));
+        assert_eq!(set.rank(b"z"), None);
+    }
+
+    #[test]
+    fn range_by_score() {
+        let set = init_set();
+
+        let range = set.range_by_score(ScoreBound::Inclusive(2.0), ScoreBound::Inclusive(3.5));
+        assert_eq!(members(range), vec!["b", "c", "d"]);
+
+        let range = set.range_by_score(ScoreBound::Exclusive(1.0), ScoreBound::Exclusive(3.5));
+        assert_eq!(members(range), vec!["b", "c"]);
+
+        let range = set.range_by_score(ScoreBound::Exclusive(2.0), ScoreBound::Inclusive(f64::INFINITY));
+        assert_eq!(members(range), vec!["d"]);
+
+        let range = set.range_by_score(ScoreBound::Inclusive(f64::NEG_INFINITY), ScoreBound::Inclusive(0.0));
+        assert!(members(range).is_empty());
+    }
+
+    #[test]
+    fn rank_and_skip_in_large_set() {
+        let mut set = SortedSet::new();
+        for i in (0..1000).rev() {
+            set.insert(format!("m{:04}", i).into_bytes(), i as f64);
+        }
+        for i in (0..1000).step_by(2) {
+            assert!(set.remove(format!("m{:04}", i).as_bytes()));
+        }
+
+        assert_eq!(set.rank(b"m0001"), Some(0));
+        assert_eq!(set.rank(b"m0999"), Some(499));
+        assert_eq!(set.rank(b"m0500"), None);
+        assert_eq!(members(set.iter().skip(250).take(2)), vec!["m0501", "m0503"]);
+        assert_eq!(members(set.iter().rev().skip(250).take(2)), vec!["m0499", "m0497"]);
+        assert_eq!(set.iter().skip(499).count(), 1);
+        assert_eq!(set.iter().nth(500), None);
+
+        let range = set.range_by_score(ScoreBound::Exclusive(101.0), ScoreBound::Inclusive(111.0));
+        assert_eq!(range.len(), 5);
+        assert_eq!(members(range.skip(3)), vec!["m0109", "m0111"]);
+    }
+}
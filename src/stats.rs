@@ -0,0 +1,111 @@
+//! Counters about connections and commands, reported by INFO.
+//!
+//! They're atomics updated without locks, so keeping them costs little
+//! more than reading the clock around each command.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use command;
+
+/// The upper bounds of the latency histogram buckets, in microseconds.
+pub const LATENCY_BUCKETS: [u64; 12] = [10, 50, 100, 250, 500, 1_000, 2_500, 5_000, 10_000, 50_000, 100_000, 1_000_000];
+
+/// The calls of a command and the time spent running them.
+#[derive(Default)]
+pub struct CommandStats {
+    pub calls: AtomicU64,
+    pub usec: AtomicU64,
+
+    /// The number of calls in each latency bucket, not cumulative. Calls
+    /// slower than the last bound are only counted in `calls`.
+    pub latencies: [AtomicU64; 12],
+}
+
+pub struct Stats {
+    pub started: Instant,
+    pub connected_clients: AtomicUsize,
+    pub total_connections: AtomicU64,
+    pub total_commands: AtomicU64,
+    pub evicted_keys: AtomicU64,
+
+    /// The stats of every command, by name.
+    commands: BTreeMap<&'static str, CommandStats>,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Stats {
+            started: Instant::now(),
+            connected_clients: AtomicUsize::new(0),
+            total_connections: AtomicU64::new(0),
+            total_commands: AtomicU64::new(0),
+            evicted_keys: AtomicU64::new(0),
+            commands: command::names().map(|name| (name, CommandStats::default())).collect(),
+        }
+    }
+
+    pub fn connected(&self) {
+        self.connected_clients.fetch_add(1, Ordering::Relaxed);
+        self.total_connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn disconnected(&self) {
+        self.connected_clients.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// Counts a call of the command that took `duration` to run.
+    pub fn record(&self, name: &str, duration: Duration) {
+        self.total_commands.fetch_add(1, Ordering::Relaxed);
+        if let Some(stats) = self.commands.get(name) {
+            stats.calls.fetch_add(1, Ordering::Relaxed);
+            let usec = duration.as_micros() as u64;
+            stats.usec.fetch_add(usec, Ordering::Relaxed);
+            if let Some(bucket) = LATENCY_BUCKETS.iter().position(|&bound| usec <= bound) {
+                stats.latencies[bucket].fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Returns the stats of every command, ordered by name.
+    pub fn commands(&self) -> impl Iterator<Item = (&'static str, &CommandStats)> {
+        self.commands.iter().map(|(&name, stats)| (name, stats))
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn record_calls() {
+        let stats = Stats::new();
+        stats.record("GET", Duration::from_micros(10));
+        stats.record("GET", Duration::from_micros(5));
+        stats.record("UNKNOWN", Duration::from_micros(5));
+
+        let (_, get) = stats.commands().find(|&(name, _)| name == "GET").unwrap();
+        assert_eq!(get.calls.load(Ordering::Relaxed), 2);
+        assert_eq!(get.usec.load(Ordering::Relaxed), 15);
+        assert_eq!(get.latencies[0].load(Ordering::Relaxed), 2);
+        assert_eq!(stats.total_commands.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn count_connections() {
+        let stats = Stats::new();
+        stats.connected();
+        stats.connected();
+        stats.disconnected();
+        assert_eq!(stats.connected_clients.load(Ordering::Relaxed), 1);
+        assert_eq!(stats.total_connections.load(Ordering::Relaxed), 2);
+    }
+}
@@ -0,0 +1,558 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::mem;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, AtomicU8, AtomicUsize, Ordering};
+
+use rand::{self, Rng};
+
+use hyperloglog::HyperLogLog;
+use sorted_set::SortedSet;
+use stream::Stream;
+use time;
+
+/// A typed value held by a key in the store.
+#[derive(Debug, PartialEq)]
+pub enum Data {
+    /// A binary-safe string.
+    String(Vec<u8>),
+
+    /// A list of strings ordered by insertion, pushed and popped at both ends.
+    List(VecDeque<Vec<u8>>),
+
+    /// A map of fields to string values.
+    Hash(HashMap<String, Vec<u8>>),
+
+    /// An unordered collection of unique strings.
+    Set(HashSet<Vec<u8>>),
+
+    /// A collection of unique strings ordered by their scores.
+    SortedSet(SortedSet),
+
+    /// A probabilistic estimate of the number of unique strings added.
+    HyperLogLog(HyperLogLog),
+
+    /// An append-only log of entries, kept even when empty so its last ID
+    /// and consumer groups survive trimming.
+    Stream(Stream),
+}
+
+impl Data {
+    /// Returns true if this is a collection type with no more elements.
+    ///
+    /// Keys holding an empty collection are removed from the store, so an
+    /// empty list is never observed by clients.
+    pub fn is_empty_collection(&self) -> bool {
+        match self {
+            Data::String(_) | Data::HyperLogLog(_) | Data::Stream(_) => false,
+            Data::List(l) => l.is_empty(),
+            Data::Hash(h) => h.is_empty(),
+            Data::Set(s) => s.is_empty(),
+            Data::SortedSet(z) => z.is_empty(),
+        }
+    }
+
+    /// Estimates the memory used by the value, in bytes. The size of large
+    /// collections is extrapolated from a sample of their elements, see
+    /// `estimate_size`.
+    pub fn memory_usage(&self) -> usize {
+        let bytes = |v: &Vec<u8>| mem::size_of::<Vec<u8>>() + v.capacity();
+        match self {
+            Data::String(s) => s.capacity(),
+            Data::List(l) => estimate_size(l.len(), l.iter().map(bytes)),
+            Data::Hash(h) => {
+                estimate_size(h.len(), h.iter().map(|(field, value)| mem::size_of::<String>() + field.capacity() + bytes(value)))
+            },
+            Data::Set(s) => estimate_size(s.len(), s.iter().map(bytes)),
+            Data::SortedSet(z) => z.memory_usage(),
+            Data::HyperLogLog(h) => h.memory_usage(),
+            Data::Stream(s) => s.memory_usage(),
+        }
+    }
+}
+
+/// The number of elements whose size is measured to estimate the size of a
+/// collection. Measuring every element would make each write to a large
+/// collection slow, so collections whose elements vary in size are only
+/// accounted approximately.
+const SIZE_SAMPLES: usize = 64;
+
+/// Estimates the total size of `len` elements from the sizes of the first
+/// ones.
+pub fn estimate_size<I: Iterator<Item = usize>>(len: usize, sizes: I) -> usize {
+    let (count, total) = sizes.take(SIZE_SAMPLES).fold((0, 0), |(count, total), size| (count + 1, total + size));
+    (total * len).checked_div(count).unwrap_or(0)
+}
+
+/// The access counter of new keys, so they aren't evicted by LFU before they
+/// get a chance to be accessed.
+const LFU_INIT: u8 = 5;
+
+/// How much slower the access counter grows as it gets higher. With 10, it
+/// takes about a million accesses to reach 255.
+const LFU_LOG_FACTOR: f64 = 10.0;
+
+/// The access counter decreases by one for every minute without access.
+const LFU_DECAY_MS: u64 = 60_000;
+
+/// A value with what's needed to account for its memory and evict it.
+#[derive(Debug)]
+struct Entry {
+    data: Data,
+
+    /// The position of the key in `Store::keys`.
+    index: usize,
+
+    /// The estimated memory used by the key and value, in bytes.
+    size: usize,
+
+    /// Milliseconds since the UNIX epoch of the last access.
+    access: AtomicU64,
+
+    /// A logarithmic access counter, for LFU eviction.
+    frequency: AtomicU8,
+}
+
+impl Entry {
+    fn new(data: Data, index: usize) -> Self {
+        Entry {
+            data,
+            index,
+            size: 0,
+            access: AtomicU64::new(time::now_ms()),
+            frequency: AtomicU8::new(LFU_INIT),
+        }
+    }
+
+    /// Returns the access counter, after the decay since the last access.
+    fn frequency(&self, now: u64) -> u8 {
+        let periods = now.saturating_sub(self.access.load(Ordering::Relaxed)) / LFU_DECAY_MS;
+        (self.frequency.load(Ordering::Relaxed) as u64).saturating_sub(periods) as u8
+    }
+
+    /// Records an access. Concurrent readers may race, which only makes the
+    /// counter a little less accurate.
+    fn access(&self) {
+        let now = time::now_ms();
+        let mut counter = self.frequency(now);
+        if counter < u8::MAX {
+            let base = counter.saturating_sub(LFU_INIT) as f64;
+            if rand::thread_rng().gen::<f64>() < 1.0 / (base * LFU_LOG_FACTOR + 1.0) {
+                counter += 1;
+            }
+        }
+        self.frequency.store(counter, Ordering::Relaxed);
+        self.access.store(now, Ordering::Relaxed);
+    }
+}
+
+/// Returns the estimated memory used by an entry, including the key which is
+/// stored twice.
+fn entry_size(key: &str, data: &Data) -> usize {
+    mem::size_of::<(String, Entry)>() + mem::size_of::<String>() + 2 * key.len() + data.memory_usage()
+}
+
+/// A randomly picked key, with what eviction policies rank keys by.
+#[derive(Debug)]
+pub struct KeySample {
+    pub key: String,
+
+    /// Milliseconds since the last access.
+    pub idle: u64,
+
+    /// The logarithmic access counter.
+    pub frequency: u8,
+}
+
+/// The last version given to a key. It's shared by all stores, so versions
+/// stay unique when keys move between them.
+static LAST_VERSION: AtomicU64 = AtomicU64::new(0);
+
+/// The keyspace, mapping keys to their typed values.
+///
+/// Every modification of a key gives it a new version, so that WATCH can
+/// tell whether a key changed. Versions of deleted keys are only kept while
+/// they're watched.
+///
+/// The size of a modified entry is computed again by `update_used_memory`,
+/// once the write lock is released, so a command modifying a value many
+/// times measures it once.
+#[derive(Debug, Default)]
+pub struct Store {
+    entries: HashMap<String, Entry>,
+
+    /// The keys in no particular order, to pick random ones.
+    keys: Vec<String>,
+
+    /// The keys modified since the used memory was last computed.
+    resized: HashSet<String>,
+    used_memory: usize,
+
+    /// The memory used by this store and any other sharing the counter, so
+    /// it can be read without locking the stores.
+    memory_counter: Arc<AtomicUsize>,
+
+    versions: HashMap<String, u64>,
+    watchers: HashMap<String, usize>,
+}
+
+impl Store {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Data> {
+        self.entries.get(key).map(|entry| {
+            entry.access();
+            &entry.data
+        })
+    }
+
+    /// Returns the value at `key` for modification. Callers must `touch` the
+    /// key if they modify it.
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut Data> {
+        self.entries.get_mut(key).map(|entry| {
+            entry.access();
+            &mut entry.data
+        })
+    }
+
+    /// Returns the value at `key`, inserting the value returned by `default`
+    /// if the key doesn't exist yet. Like with `get_mut`, callers must
+    /// `touch` the key if they modify it, and an inserted empty collection
+    /// left unmodified is removed by `remove_if_empty`.
+    pub fn get_or_insert_with<F: FnOnce() -> Data>(&mut self, key: &str, default: F) -> &mut Data {
+        if !self.entries.contains_key(key) {
+            let entry = Entry::new(default(), self.keys.len());
+            self.keys.push(key.to_string());
+            self.entries.insert(key.to_string(), entry);
+        }
+        self.get_mut(key).unwrap()
+    }
+
+    pub fn insert(&mut self, key: String, data: Data) {
+        self.touch(&key);
+        match self.entries.get_mut(&key) {
+            Some(entry) => {
+                entry.data = data;
+                entry.access();
+            },
+            None => {
+                let entry = Entry::new(data, self.keys.len());
+                self.keys.push(key.clone());
+                self.entries.insert(key, entry);
+            },
+        }
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<Data> {
+        let entry = self.remove_entry(key)?;
+        if self.watchers.contains_key(key) {
+            self.touch(key);
+        }
+        Some(entry.data)
+    }
+
+    /// Removes `key` if it holds a collection that no longer has elements.
+    /// Its version is left as is, as the callers that emptied the collection
+    /// already touched it.
+    pub fn remove_if_empty(&mut self, key: &str) {
+        if self.entries.get(key).is_some_and(|entry| entry.data.is_empty_collection()) {
+            self.remove_entry(key);
+        }
+    }
+
+    /// Removes the entry of `key`, forgetting its version unless it's
+    /// watched.
+    fn remove_entry(&mut self, key: &str) -> Option<Entry> {
+        let entry = self.entries.remove(key)?;
+        self.used_memory -= entry.size;
+        self.memory_counter.fetch_sub(entry.size, Ordering::Relaxed);
+        self.keys.swap_remove(entry.index);
+        if let Some(moved) = self.keys.get(entry.index) {
+            self.entries.get_mut(moved).unwrap().index = entry.index;
+        }
+
+        if !self.watchers.contains_key(key) {
+            self.versions.remove(key);
+        }
+        Some(entry)
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn clear(&mut self) {
+        let keys: Vec<String> = self.entries.keys().cloned().collect();
+        for key in keys {
+            self.remove(&key);
+        }
+    }
+
+    /// Swaps the keys with those of `other`. Every watched key of both is
+    /// considered modified.
+    pub fn swap(&mut self, other: &mut Store) {
+        self.memory_counter.fetch_sub(self.used_memory, Ordering::Relaxed);
+        other.memory_counter.fetch_sub(other.used_memory, Ordering::Relaxed);
+        mem::swap(&mut self.entries, &mut other.entries);
+        mem::swap(&mut self.keys, &mut other.keys);
+        mem::swap(&mut self.resized, &mut other.resized);
+        mem::swap(&mut self.used_memory, &mut other.used_memory);
+        self.memory_counter.fetch_add(self.used_memory, Ordering::Relaxed);
+        other.memory_counter.fetch_add(other.used_memory, Ordering::Relaxed);
+        mem::swap(&mut self.versions, &mut other.versions);
+        self.touch_watched();
+        other.touch_watched();
+    }
+
+    fn touch_watched(&mut self) {
+        let watched: Vec<String> = self.watchers.keys().cloned().collect();
+        for key in watched {
+            self.touch(&key);
+        }
+
+        let (entries, watchers) = (&self.entries, &self.watchers);
+        self.versions.retain(|key, _| entries.contains_key(key) || watchers.contains_key(key));
+    }
+
+    /// Returns the version of `key`, which is 0 if it was never modified.
+    pub fn version(&self, key: &str) -> u64 {
+        self.versions.get(key).cloned().unwrap_or(0)
+    }
+
+    /// Keeps track of the version of `key` even if it's deleted, until a
+    /// matching call to `unwatch`. Returns its current version.
+    pub fn watch(&mut self, key: &str) -> u64 {
+        *self.watchers.entry(key.to_string()).or_insert(0) += 1;
+        self.version(key)
+    }
+
+    pub fn unwatch(&mut self, key: &str) {
+        if let Some(count) = self.watchers.get_mut(key) {
+            *count -= 1;
+            if *count == 0 {
+                self.watchers.remove(key);
+                if !self.entries.contains_key(key) {
+                    self.versions.remove(key);
+                }
+            }
+        }
+    }
+
+    /// Measures the entries modified since the last call, and updates the
+    /// used memory and the counter with the difference.
+    pub fn update_used_memory(&mut self) {
+        for key in mem::take(&mut self.resized) {
+            if let Some(entry) = self.entries.get_mut(&key) {
+                let size = entry_size(&key, &entry.data);
+                self.used_memory = self.used_memory - entry.size + size;
+                if size > entry.size {
+                    self.memory_counter.fetch_add(size - entry.size, Ordering::Relaxed);
+                } else {
+                    self.memory_counter.fetch_sub(entry.size - size, Ordering::Relaxed);
+                }
+                entry.size = size;
+            }
+        }
+    }
+
+    /// Counts the used memory in `counter` instead, which may be shared
+    /// with other stores to keep their total.
+    pub fn share_memory_counter(&mut self, counter: Arc<AtomicUsize>) {
+        self.update_used_memory();
+        counter.fetch_add(self.used_memory, Ordering::Relaxed);
+        self.memory_counter = counter;
+    }
+
+    /// Picks `count` random keys, possibly the same key more than once.
+    /// Doesn't count as an access.
+    pub fn sample(&self, count: usize) -> Vec<KeySample> {
+        if self.keys.is_empty() {
+            return Vec::new();
+        }
+
+        let mut rng = rand::thread_rng();
+        let now = time::now_ms();
+        (0..count)
+            .map(|_| {
+                let key = &self.keys[rng.gen_range(0, self.keys.len())];
+                let entry = &self.entries[key];
+                KeySample {
+                    key: key.clone(),
+                    idle: now.saturating_sub(entry.access.load(Ordering::Relaxed)),
+                    frequency: entry.frequency(now),
+                }
+            })
+            .collect()
+    }
+
+    /// Gives `key` a new version and measures it again, after its value was
+    /// modified in place. Commands that fail or change nothing don't touch
+    /// the key, so they don't abort the transactions watching it.
+    pub fn touch(&mut self, key: &str) {
+        if !self.resized.contains(key) {
+            self.resized.insert(key.to_string());
+        }
+
+        let version = LAST_VERSION.fetch_add(1, Ordering::Relaxed) + 1;
+        match self.versions.get_mut(key) {
+            Some(v) => *v = version,
+            None => {
+                self.versions.insert(key.to_string(), version);
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    fn string(s: &str) -> Data {
+        Data::String(s.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn modifications_change_version() {
+        let mut store = Store::new();
+        assert_eq!(store.version("a"), 0);
+
+        store.insert("a".to_string(), string("1"));
+        let version = store.version("a");
+        assert!(version > 0);
+
+        store.get("a");
+        assert_eq!(store.version("a"), version);
+
+        // modifications in place count once the key is touched
+        store.get_mut("a");
+        assert_eq!(store.version("a"), version);
+        store.touch("a");
+        assert!(store.version("a") > version);
+
+        // an unmodified empty collection is removed without a new version
+        let version = store.watch("b");
+        store.get_or_insert_with("b", || Data::List(VecDeque::new()));
+        store.remove_if_empty("b");
+        assert_eq!(store.version("b"), version);
+        store.unwatch("b");
+
+        // deleted keys that aren't watched are forgotten
+        store.remove("a");
+        assert_eq!(store.version("a"), 0);
+    }
+
+    #[test]
+    fn watched_keys_keep_version() {
+        let mut store = Store::new();
+        assert_eq!(store.watch("a"), 0);
+
+        store.insert("a".to_string(), string("1"));
+        store.remove("a");
+        let version = store.version("a");
+        assert!(version > 0);
+
+        store.insert("b".to_string(), string("1"));
+        store.watch("b");
+        store.clear();
+        assert!(store.version("b") > version);
+
+        store.unwatch("a");
+        store.unwatch("b");
+        assert_eq!(store.version("a"), 0);
+        assert_eq!(store.version("b"), 0);
+    }
+
+    #[test]
+    fn swap_touches_watched_keys() {
+        let mut a = Store::new();
+        let mut b = Store::new();
+        a.insert("x".to_string(), string("1"));
+        b.insert("y".to_string(), string("2"));
+        let version = a.watch("x");
+        a.watch("unchanged");
+
+        a.swap(&mut b);
+        assert_eq!(a.get("y"), Some(&string("2")));
+        assert_eq!(b.get("x"), Some(&string("1")));
+        assert!(a.version("x") > version);
+        assert!(a.version("unchanged") > 0);
+        assert_eq!(b.watch("x"), b.version("x"));
+    }
+
+    #[test]
+    fn used_memory_follows_modifications() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let mut store = Store::new();
+        store.share_memory_counter(Arc::clone(&counter));
+        assert_eq!(store.used_memory, 0);
+
+        store.insert("a".to_string(), string("1"));
+        assert_eq!(store.used_memory, 0);
+        store.update_used_memory();
+        let small = store.used_memory;
+        assert!(small > 0);
+        assert_eq!(counter.load(Ordering::Relaxed), small);
+
+        if let Some(Data::String(s)) = store.get_mut("a") {
+            s.extend_from_slice(&[0; 1000]);
+        }
+        store.touch("a");
+        store.update_used_memory();
+        assert!(store.used_memory >= small + 1000);
+
+        // the counter keeps the total of the stores sharing it
+        let mut other = Store::new();
+        other.insert("c".to_string(), string("1"));
+        other.share_memory_counter(Arc::clone(&counter));
+        store.swap(&mut other);
+        assert_eq!(counter.load(Ordering::Relaxed), store.used_memory + other.used_memory);
+        store.swap(&mut other);
+
+        store.get_or_insert_with("b", || Data::List(VecDeque::new()));
+        store.update_used_memory();
+        store.remove("a");
+        store.remove("b");
+        assert_eq!(store.used_memory, 0);
+        assert_eq!(counter.load(Ordering::Relaxed), other.used_memory);
+    }
+
+    #[test]
+    fn sample_after_removals() {
+        let mut store = Store::new();
+        assert!(store.sample(5).is_empty());
+
+        for key in &["a", "b", "c", "d"] {
+            store.insert(key.to_string(), string("1"));
+        }
+        store.remove("a");
+        store.remove("c");
+
+        let samples = store.sample(50);
+        assert_eq!(samples.len(), 50);
+        assert!(samples.iter().all(|sample| sample.key == "b" || sample.key == "d"));
+        assert!(samples.iter().all(|sample| sample.frequency == LFU_INIT));
+    }
+
+    #[test]
+    fn access_counts_frequency() {
+        let mut store = Store::new();
+        store.insert("a".to_string(), string("1"));
+        for _ in 0..100 {
+            store.get("a");
+        }
+        assert!(store.sample(1)[0].frequency > LFU_INIT);
+    }
+
+    #[test]
+    fn estimate_size_of_large_collections() {
+        assert_eq!(estimate_size(0, Vec::new().into_iter()), 0);
+        assert_eq!(estimate_size(3, vec![1, 2, 3].into_iter()), 6);
+        assert_eq!(estimate_size(1000, vec![10; 1000].into_iter()), 10_000);
+    }
+}
@@ -0,0 +1,394 @@
+use std::collections::{BTreeMap, HashMap};
+use std::collections::Bound;
+use std::fmt;
+use std::mem;
+use std::str::FromStr;
+
+use store;
+
+/// Identifies a stream entry by the millisecond it was added and a sequence
+/// number for entries added within the same millisecond.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct StreamId {
+    pub ms: u64,
+    pub seq: u64,
+}
+
+impl StreamId {
+    pub const MAX: StreamId = StreamId { ms: u64::MAX, seq: u64::MAX };
+
+    pub fn new(ms: u64, seq: u64) -> Self {
+        StreamId { ms, seq }
+    }
+}
+
+impl fmt::Display for StreamId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+impl FromStr for StreamId {
+    type Err = ();
+
+    /// Parses `ms-seq`, or `ms` alone with a sequence number of 0.
+    fn from_str(s: &str) -> Result<Self, ()> {
+        let mut parts = s.splitn(2, '-');
+        let ms = parts.next().unwrap().parse().map_err(|_| ())?;
+        let seq = match parts.next() {
+            Some(seq) => seq.parse().map_err(|_| ())?,
+            None => 0,
+        };
+        Ok(StreamId { ms, seq })
+    }
+}
+
+/// The fields and values of a stream entry, in the order they were given.
+pub type Fields = Vec<(Vec<u8>, Vec<u8>)>;
+
+/// An entry delivered to a consumer that hasn't been acknowledged yet.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingEntry {
+    pub consumer: String,
+
+    /// Milliseconds since the UNIX epoch of the last delivery.
+    pub delivery_time: u64,
+
+    pub delivery_count: u64,
+}
+
+/// Workers reading a stream together, where each entry is delivered to only
+/// one of the consumers and stays pending until acknowledged.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ConsumerGroup {
+    pub last_delivered: StreamId,
+    pub pending: BTreeMap<StreamId, PendingEntry>,
+}
+
+impl ConsumerGroup {
+    /// Counts the pending entries of each consumer.
+    pub fn consumer_counts(&self) -> BTreeMap<&str, usize> {
+        let mut counts = BTreeMap::new();
+        for entry in self.pending.values() {
+            *counts.entry(entry.consumer.as_ref()).or_insert(0) += 1;
+        }
+        counts
+    }
+}
+
+/// An append-only log of entries ordered by ID.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Stream {
+    entries: BTreeMap<StreamId, Fields>,
+    last_id: StreamId,
+    groups: HashMap<String, ConsumerGroup>,
+}
+
+impl Stream {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Estimates the memory used by the entries and the pending entries of
+    /// the consumer groups, in bytes.
+    pub fn memory_usage(&self) -> usize {
+        let entry_size = |fields: &Fields| {
+            let bytes: usize = fields.iter().map(|(field, value)| field.capacity() + value.capacity()).sum();
+            mem::size_of::<(StreamId, Fields)>() + fields.capacity() * mem::size_of::<(Vec<u8>, Vec<u8>)>() + bytes
+        };
+        let entries = store::estimate_size(self.entries.len(), self.entries.values().map(entry_size));
+
+        let groups: usize = self.groups.iter()
+            .map(|(name, group)| name.capacity() + group.pending.len() * mem::size_of::<(StreamId, PendingEntry)>())
+            .sum();
+        entries + groups
+    }
+
+    pub fn last_id(&self) -> StreamId {
+        self.last_id
+    }
+
+    /// Returns an ID greater than any in the stream, based on the current time
+    /// in `now` milliseconds, or on the last ID if the clock went backwards.
+    /// Returns None if the sequence numbers of that millisecond are used up.
+    pub fn next_id(&self, now: u64) -> Option<StreamId> {
+        if now > self.last_id.ms {
+            Some(StreamId::new(now, 0))
+        } else {
+            self.next_id_in(self.last_id.ms)
+        }
+    }
+
+    /// Returns the next ID in millisecond `ms`, or None if its sequence
+    /// numbers are used up.
+    pub fn next_id_in(&self, ms: u64) -> Option<StreamId> {
+        if ms == self.last_id.ms {
+            self.last_id.seq.checked_add(1).map(|seq| StreamId::new(ms, seq))
+        } else {
+            Some(StreamId::new(ms, 0))
+        }
+    }
+
+    /// Appends an entry. Returns false if the ID isn't greater than the last
+    /// ID, which also rules out 0-0.
+    pub fn insert(&mut self, id: StreamId, fields: Fields) -> bool {
+        if id <= self.last_id {
+            return false;
+        }
+
+        self.entries.insert(id, fields);
+        self.last_id = id;
+        true
+    }
+
+    pub fn range(&self, start: Bound<StreamId>, end: Bound<StreamId>)
+        -> impl DoubleEndedIterator<Item = (&StreamId, &Fields)>
+    {
+        let empty = match (start, end) {
+            (Bound::Included(s), Bound::Included(e)) => s > e,
+            (Bound::Included(s), Bound::Excluded(e))
+            | (Bound::Excluded(s), Bound::Included(e))
+            | (Bound::Excluded(s), Bound::Excluded(e)) => s >= e,
+            _ => false,
+        };
+
+        // BTreeMap::range panics on an inverted range.
+        let (start, end) = if empty {
+            (Bound::Excluded(StreamId::MAX), Bound::Unbounded)
+        } else {
+            (start, end)
+        };
+        self.entries.range((start, end))
+    }
+
+    /// Removes the oldest entries so that at most `maxlen` remain. Returns the
+    /// number of entries removed.
+    pub fn trim_maxlen(&mut self, maxlen: usize) -> usize {
+        let excess = self.entries.len().saturating_sub(maxlen);
+        let ids: Vec<StreamId> = self.entries.keys().take(excess).cloned().collect();
+        for id in &ids {
+            self.entries.remove(id);
+        }
+        excess
+    }
+
+    /// Removes the entries with IDs lower than `min_id`. Returns the number of
+    /// entries removed.
+    pub fn trim_minid(&mut self, min_id: StreamId) -> usize {
+        let kept = self.entries.split_off(&min_id);
+        let removed = self.entries.len();
+        self.entries = kept;
+        removed
+    }
+
+    /// Creates a consumer group that delivers the entries after
+    /// `last_delivered`. Returns false if the group already exists.
+    pub fn create_group(&mut self, name: &str, last_delivered: StreamId) -> bool {
+        if self.groups.contains_key(name) {
+            return false;
+        }
+
+        let group = ConsumerGroup { last_delivered, pending: BTreeMap::new() };
+        self.groups.insert(name.to_string(), group);
+        true
+    }
+
+    pub fn destroy_group(&mut self, name: &str) -> bool {
+        self.groups.remove(name).is_some()
+    }
+
+    pub fn group(&self, name: &str) -> Option<&ConsumerGroup> {
+        self.groups.get(name)
+    }
+
+    /// Delivers up to `count` entries that were never delivered to the group
+    /// to `consumer`, adding them to the pending entries.
+    ///
+    /// Returns `None` if the group doesn't exist.
+    pub fn read_new(&mut self, group: &str, consumer: &str, count: usize, now: u64)
+        -> Option<Vec<(StreamId, Fields)>>
+    {
+        let group = self.groups.get_mut(group)?;
+
+        let start = Bound::Excluded(group.last_delivered);
+        let entries: Vec<(StreamId, Fields)> = self.entries.range((start, Bound::Unbounded))
+            .take(count)
+            .map(|(id, fields)| (*id, fields.clone()))
+            .collect();
+
+        for (id, _) in &entries {
+            let pending = PendingEntry {
+                consumer: consumer.to_string(),
+                delivery_time: now,
+                delivery_count: 1,
+            };
+            group.pending.insert(*id, pending);
+            group.last_delivered = *id;
+        }
+
+        Some(entries)
+    }
+
+    /// Returns up to `count` entries after `after` that were delivered to
+    /// `consumer` but not acknowledged yet. Entries that were trimmed from the
+    /// stream since have no fields.
+    ///
+    /// Returns `None` if the group doesn't exist.
+    pub fn read_pending(&self, group: &str, consumer: &str, after: StreamId, count: usize)
+        -> Option<Vec<(StreamId, Option<&Fields>)>>
+    {
+        let group = self.groups.get(group)?;
+
+        let entries = group.pending.range((Bound::Excluded(after), Bound::Unbounded))
+            .filter(|(_, pending)| pending.consumer == consumer)
+            .take(count)
+            .map(|(id, _)| (*id, self.entries.get(id)))
+            .collect();
+
+        Some(entries)
+    }
+
+    /// Removes entries from the pending entries of the group. Returns the
+    /// number of entries acknowledged, or `None` if the group doesn't exist.
+    pub fn ack(&mut self, group: &str, ids: &[StreamId]) -> Option<usize> {
+        let group = self.groups.get_mut(group)?;
+        Some(ids.iter().filter(|id| group.pending.remove(id).is_some()).count())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    fn fields(s: &str) -> Fields {
+        vec![(b"event".to_vec(), s.as_bytes().to_vec())]
+    }
+
+    fn init_stream() -> Stream {
+        let mut stream = Stream::new();
+        for seq in 1..=5 {
+            stream.insert(StreamId::new(1000, seq), fields(&seq.to_string()));
+        }
+        stream
+    }
+
+    fn ids<'a, I: Iterator<Item = (&'a StreamId, &'a Fields)>>(iter: I) -> Vec<String> {
+        iter.map(|(id, _)| id.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_stream_id() {
+        assert_eq!("1526919030474-55".parse(), Ok(StreamId::new(1526919030474, 55)));
+        assert_eq!("1526919030474".parse(), Ok(StreamId::new(1526919030474, 0)));
+        assert_eq!("abc".parse::<StreamId>(), Err(()));
+        assert_eq!("1-2-3".parse::<StreamId>(), Err(()));
+    }
+
+    #[test]
+    fn generated_ids_increase() {
+        let mut stream = Stream::new();
+
+        let id = stream.next_id(1000).unwrap();
+        assert_eq!(id, StreamId::new(1000, 0));
+        assert!(stream.insert(id, fields("a")));
+
+        // same millisecond, or a clock that went backwards
+        assert_eq!(stream.next_id(1000), Some(StreamId::new(1000, 1)));
+        assert_eq!(stream.next_id(900), Some(StreamId::new(1000, 1)));
+        assert_eq!(stream.next_id(1001), Some(StreamId::new(1001, 0)));
+
+        assert_eq!(stream.next_id_in(1000), Some(StreamId::new(1000, 1)));
+        assert_eq!(stream.next_id_in(2000), Some(StreamId::new(2000, 0)));
+
+        // no sequence number left in the millisecond
+        assert!(stream.insert(StreamId::new(1000, u64::MAX), fields("b")));
+        assert_eq!(stream.next_id(1000), None);
+        assert_eq!(stream.next_id_in(1000), None);
+        assert_eq!(stream.next_id(1001), Some(StreamId::new(1001, 0)));
+    }
+
+    #[test]
+    fn insert_requires_greater_id() {
+        let mut stream = init_stream();
+
+        assert!(!stream.insert(StreamId::new(1000, 5), fields("x")));
+        assert!(!stream.insert(StreamId::new(999, 9), fields("x")));
+        assert!(!Stream::new().insert(StreamId::new(0, 0), fields("x")));
+        assert_eq!(stream.len(), 5);
+    }
+
+    #[test]
+    fn range() {
+        let stream = init_stream();
+
+        let r = stream.range(Bound::Included(StreamId::new(1000, 2)), Bound::Included(StreamId::new(1000, 3)));
+        assert_eq!(ids(r), vec!["1000-2", "1000-3"]);
+
+        let r = stream.range(Bound::Excluded(StreamId::new(1000, 3)), Bound::Unbounded);
+        assert_eq!(ids(r.rev()), vec!["1000-5", "1000-4"]);
+
+        let r = stream.range(Bound::Included(StreamId::new(1000, 4)), Bound::Excluded(StreamId::new(1000, 4)));
+        assert!(ids(r).is_empty());
+    }
+
+    #[test]
+    fn trim() {
+        let mut stream = init_stream();
+
+        assert_eq!(stream.trim_maxlen(3), 2);
+        assert_eq!(stream.trim_maxlen(3), 0);
+        assert_eq!(stream.trim_minid(StreamId::new(1000, 5)), 2);
+        assert_eq!(ids(stream.range(Bound::Unbounded, Bound::Unbounded)), vec!["1000-5"]);
+
+        // the last ID is kept, so IDs are never reused
+        assert_eq!(stream.trim_maxlen(0), 1);
+        assert_eq!(stream.last_id(), StreamId::new(1000, 5));
+    }
+
+    #[test]
+    fn consumer_group() {
+        let mut stream = init_stream();
+
+        assert!(stream.create_group("workers", StreamId::new(1000, 1)));
+        assert!(!stream.create_group("workers", StreamId::new(0, 0)));
+
+        let read = stream.read_new("workers", "alice", 2, 5000).unwrap();
+        let read_ids: Vec<String> = read.iter().map(|(id, _)| id.to_string()).collect();
+        assert_eq!(read_ids, vec!["1000-2", "1000-3"]);
+
+        let read = stream.read_new("workers", "bob", 10, 5000).unwrap();
+        assert_eq!(read.len(), 2);
+        assert!(stream.read_new("workers", "bob", 10, 5000).unwrap().is_empty());
+
+        let pending = stream.read_pending("workers", "alice", StreamId::new(0, 0), 10).unwrap();
+        assert_eq!(pending.len(), 2);
+        assert_eq!(pending[0].1, Some(&fields("2")));
+
+        assert_eq!(stream.ack("workers", &[StreamId::new(1000, 2), StreamId::new(1000, 9)]), Some(1));
+        assert_eq!(stream.group("workers").unwrap().pending.len(), 3);
+
+        let counts = stream.group("workers").unwrap().consumer_counts();
+        assert_eq!(counts.get("alice"), Some(&1));
+        assert_eq!(counts.get("bob"), Some(&2));
+
+        assert!(stream.read_new("missing", "alice", 1, 5000).is_none());
+        assert!(stream.destroy_group("workers"));
+        assert!(stream.group("workers").is_none());
+    }
+
+    #[test]
+    fn pending_entries_of_trimmed_entries() {
+        let mut stream = init_stream();
+        stream.create_group("workers", StreamId::new(0, 0));
+        stream.read_new("workers", "alice", 2, 5000);
+        stream.trim_maxlen(4);
+
+        let pending = stream.read_pending("workers", "alice", StreamId::new(0, 0), 10).unwrap();
+        assert_eq!(pending, vec![(StreamId::new(1000, 1), None), (StreamId::new(1000, 2), Some(&fields("2")))]);
+    }
+}
@@ -0,0 +1,9 @@
+//! The wall clock, for stream IDs, key access times and log timestamps.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Returns the milliseconds since the UNIX epoch.
+pub fn now_ms() -> u64 {
+    let elapsed = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    elapsed.as_secs() * 1000 + elapsed.subsec_millis() as u64
+}
@@ -0,0 +1,401 @@
+//! TLS for server and client connections, using rustls.
+//!
+//! A `TlsStream` can be cloned so that one thread reads from it while another
+//! writes, the same as a `TcpStream`. The clones share the TLS state behind a
+//! mutex, which is never held while reading from or writing to the socket:
+//! records are encrypted into a queue under it, then written out in order by
+//! one thread at a time.
+
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::path::Path;
+use std::mem;
+use std::sync::{Arc, Mutex, TryLockError};
+
+use rustls::{ClientConfig, ClientConnection, Connection, RootCertStore, ServerConfig, ServerConnection};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::server::WebPkiClientVerifier;
+use rustls_pemfile;
+
+fn invalid_data<E: ToString>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+fn load_certs(path: &Path) -> io::Result<Vec<CertificateDer<'static>>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let certs = rustls_pemfile::certs(&mut reader).collect::<io::Result<Vec<_>>>()?;
+    if certs.is_empty() {
+        return Err(invalid_data(format!("No certificates found in {}", path.display())));
+    }
+    Ok(certs)
+}
+
+fn load_key(path: &Path) -> io::Result<PrivateKeyDer<'static>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    rustls_pemfile::private_key(&mut reader)?
+        .ok_or_else(|| invalid_data(format!("No private key found in {}", path.display())))
+}
+
+fn load_roots(path: &Path) -> io::Result<Arc<RootCertStore>> {
+    let mut roots = RootCertStore::empty();
+    for cert in load_certs(path)? {
+        roots.add(cert).map_err(invalid_data)?;
+    }
+    Ok(Arc::new(roots))
+}
+
+/// Builds the server configuration from PEM files. With a CA certificate,
+/// clients must present a certificate signed by it.
+pub fn server_config(cert_file: &Path, key_file: &Path, ca_cert_file: Option<&Path>) -> io::Result<Arc<ServerConfig>> {
+    let builder = ServerConfig::builder();
+    let builder = match ca_cert_file {
+        Some(path) => {
+            let verifier = WebPkiClientVerifier::builder(load_roots(path)?).build().map_err(invalid_data)?;
+            builder.with_client_cert_verifier(verifier)
+        },
+        None => builder.with_no_client_auth(),
+    };
+
+    let config = builder.with_single_cert(load_certs(cert_file)?, load_key(key_file)?).map_err(invalid_data)?;
+    Ok(Arc::new(config))
+}
+
+/// Builds the client configuration from PEM files. The server certificate
+/// must be signed by the CA certificate. The client certificate and key are
+/// needed if the server verifies clients.
+pub fn client_config(ca_cert_file: &Path, identity: Option<(&Path, &Path)>) -> io::Result<Arc<ClientConfig>> {
+    let builder = ClientConfig::builder().with_root_certificates(load_roots(ca_cert_file)?);
+    let config = match identity {
+        Some((cert_file, key_file)) => builder
+            .with_client_auth_cert(load_certs(cert_file)?, load_key(key_file)?)
+            .map_err(invalid_data)?,
+        None => builder.with_no_client_auth(),
+    };
+    Ok(Arc::new(config))
+}
+
+/// The state shared by the clones of a `TlsStream`.
+struct Shared {
+    conn: Mutex<Connection>,
+
+    /// Encrypted records waiting to be written, in the order they were
+    /// encrypted.
+    outbox: Mutex<Vec<u8>>,
+
+    /// Held while writing records to the socket.
+    sending: Mutex<()>,
+}
+
+pub struct TlsStream {
+    shared: Arc<Shared>,
+    socket: TcpStream,
+
+    /// Records read from the socket that rustls has no room for yet, as
+    /// it holds a limited amount of received data. Only one clone reads.
+    unprocessed: Vec<u8>,
+}
+
+impl TlsStream {
+    fn new(conn: Connection, socket: TcpStream) -> Self {
+        let shared = Shared { conn: Mutex::new(conn), outbox: Mutex::new(vec![]), sending: Mutex::new(()) };
+        TlsStream { shared: Arc::new(shared), socket, unprocessed: vec![] }
+    }
+
+    /// Accepts a TLS connection. The handshake happens on the first read.
+    pub fn accept(config: Arc<ServerConfig>, socket: TcpStream) -> io::Result<Self> {
+        let conn = ServerConnection::new(config).map_err(invalid_data)?;
+        Ok(TlsStream::new(conn.into(), socket))
+    }
+
+    /// Starts a TLS connection to a server, which must have a certificate for
+    /// `server_name`, either a domain name or an IP address. The handshake
+    /// happens on the first write.
+    pub fn connect(config: Arc<ClientConfig>, server_name: &str, socket: TcpStream) -> io::Result<Self> {
+        let server_name = ServerName::try_from(server_name.to_string()).map_err(invalid_data)?;
+        let conn = ClientConnection::new(config, server_name).map_err(invalid_data)?;
+        Ok(TlsStream::new(conn.into(), socket))
+    }
+
+    pub fn try_clone(&self) -> io::Result<Self> {
+        Ok(TlsStream { shared: Arc::clone(&self.shared), socket: self.socket.try_clone()?, unprocessed: vec![] })
+    }
+
+    /// Exchanges records with the peer until the handshake is complete.
+    fn complete_handshake(&mut self) -> io::Result<()> {
+        loop {
+            {
+                let mut conn = self.shared.conn.lock().unwrap();
+                if !conn.is_handshaking() {
+                    return Ok(());
+                }
+                queue_pending(&mut conn, &self.shared.outbox)?;
+            }
+            self.send_queued(true)?;
+
+            if !self.receive()? {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+        }
+    }
+
+    /// Passes records from the socket to rustls, reading more if none are
+    /// left over, and sends any records in reply. Returns false if the
+    /// socket is closed.
+    fn receive(&mut self) -> io::Result<bool> {
+        if self.unprocessed.is_empty() {
+            let mut received = [0; 4096];
+            let n = self.socket.read(&mut received)?;
+            self.unprocessed.extend_from_slice(&received[..n]);
+        }
+
+        let mut unprocessed = &self.unprocessed[..];
+        // An empty read tells rustls the socket closed.
+        let open = !unprocessed.is_empty();
+        let processed = process_received(&mut self.shared.conn.lock().unwrap(), &mut unprocessed, &self.shared.outbox);
+        let consumed = self.unprocessed.len() - unprocessed.len();
+        self.unprocessed.drain(..consumed);
+
+        // The writer may be blocked on a client that isn't reading, so
+        // don't wait for it to send replies to handshakes and alerts.
+        self.send_queued(false)?;
+        processed?;
+        Ok(open)
+    }
+
+    /// Writes the queued records. If another clone is already writing and
+    /// `wait` is false, leaves them to it.
+    fn send_queued(&self, wait: bool) -> io::Result<()> {
+        loop {
+            let sending = if wait {
+                self.shared.sending.lock().unwrap()
+            } else {
+                match self.shared.sending.try_lock() {
+                    Ok(sending) => sending,
+                    Err(TryLockError::WouldBlock) => return Ok(()),
+                    Err(TryLockError::Poisoned(e)) => panic!("{}", e),
+                }
+            };
+            loop {
+                let records = mem::take(&mut *self.shared.outbox.lock().unwrap());
+                if records.is_empty() {
+                    break;
+                }
+                (&self.socket).write_all(&records)?;
+            }
+            drop(sending);
+
+            // Records queued after the last check are written by whoever
+            // queued them, unless they found this clone still writing.
+            if self.shared.outbox.lock().unwrap().is_empty() {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Encrypts all pending TLS records into the queue, including handshake
+/// messages and alerts.
+fn queue_pending(conn: &mut Connection, outbox: &Mutex<Vec<u8>>) -> io::Result<()> {
+    while conn.wants_write() {
+        conn.write_tls(&mut *outbox.lock().unwrap())?;
+    }
+    Ok(())
+}
+
+/// Processes the records received, queueing any records to send in reply.
+/// Stops early once rustls has received data waiting to be read.
+fn process_received(conn: &mut Connection, received: &mut &[u8], outbox: &Mutex<Vec<u8>>) -> io::Result<()> {
+    loop {
+        conn.read_tls(received)?;
+        let state = conn.process_new_packets();
+        // Even on error, there may be an alert to send.
+        queue_pending(conn, outbox)?;
+        state.map_err(invalid_data)?;
+
+        if received.is_empty() || !conn.wants_read() {
+            return Ok(());
+        }
+    }
+}
+
+impl Read for TlsStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            match self.shared.conn.lock().unwrap().reader().read(buf) {
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => (),
+                result => return result,
+            }
+
+            // Needs more data.
+            self.receive()?;
+        }
+    }
+}
+
+impl Write for TlsStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Until then rustls only buffers a little data, and writing more
+        // would fail.
+        self.complete_handshake()?;
+
+        let n = {
+            let mut conn = self.shared.conn.lock().unwrap();
+            let n = conn.writer().write(buf)?;
+            queue_pending(&mut conn, &self.shared.outbox)?;
+            n
+        };
+        self.send_queued(true)?;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        queue_pending(&mut self.shared.conn.lock().unwrap(), &self.shared.outbox)?;
+        self.send_queued(true)?;
+        self.socket.flush()
+    }
+}
+
+/// Generates self-signed certificates for tests.
+#[cfg(test)]
+pub mod test_certs {
+
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+
+    use rcgen::{BasicConstraints, CertificateParams, IsCa, KeyPair};
+
+    /// The PEM files of a CA, a server certificate for 127.0.0.1 and a client
+    /// certificate, both signed by the CA, in a temporary directory.
+    pub struct TestCerts {
+        pub dir: PathBuf,
+        pub ca_cert: PathBuf,
+        pub server_cert: PathBuf,
+        pub server_key: PathBuf,
+        pub client_cert: PathBuf,
+        pub client_key: PathBuf,
+    }
+
+    impl TestCerts {
+        pub fn generate(name: &str) -> Self {
+            let dir = env::temp_dir().join(format!("hanbaiki-tls-{}-{}", name, std::process::id()));
+            fs::create_dir_all(&dir).unwrap();
+
+            let mut params = CertificateParams::new(Vec::new()).unwrap();
+            params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+            let ca_key = KeyPair::generate().unwrap();
+            let ca = params.self_signed(&ca_key).unwrap();
+
+            let write = |file: &str, pem: String| {
+                let path = dir.join(file);
+                fs::write(&path, pem).unwrap();
+                path
+            };
+
+            let server_key = KeyPair::generate().unwrap();
+            let params = CertificateParams::new(vec!["127.0.0.1".to_string(), "localhost".to_string()]).unwrap();
+            let server = params.signed_by(&server_key, &ca, &ca_key).unwrap();
+
+            let client_key = KeyPair::generate().unwrap();
+            let params = CertificateParams::new(vec!["client".to_string()]).unwrap();
+            let client = params.signed_by(&client_key, &ca, &ca_key).unwrap();
+
+            TestCerts {
+                ca_cert: write("ca.crt", ca.pem()),
+                server_cert: write("server.crt", server.pem()),
+                server_key: write("server.key", server_key.serialize_pem()),
+                client_cert: write("client.crt", client.pem()),
+                client_key: write("client.key", client_key.serialize_pem()),
+                dir,
+            }
+        }
+    }
+
+    impl Drop for TestCerts {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use super::test_certs::TestCerts;
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Accepts one connection and echoes what it reads until it closes.
+    fn echo_server(config: Arc<ServerConfig>) -> (u16, thread::JoinHandle<io::Result<()>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let handle = thread::spawn(move || {
+            let (socket, _) = listener.accept()?;
+            let mut reader = TlsStream::accept(config, socket)?;
+            let mut writer = reader.try_clone()?;
+            let mut buf = [0; 64];
+            loop {
+                match reader.read(&mut buf)? {
+                    0 => return Ok(()),
+                    n => writer.write_all(&buf[..n])?,
+                }
+            }
+        });
+        (port, handle)
+    }
+
+    fn connect(port: u16, config: Arc<ClientConfig>) -> io::Result<TlsStream> {
+        TlsStream::connect(config, "127.0.0.1", TcpStream::connect(("127.0.0.1", port))?)
+    }
+
+    #[test]
+    fn echo() {
+        let certs = TestCerts::generate("echo");
+        let server = server_config(&certs.server_cert, &certs.server_key, None).unwrap();
+        let (port, _) = echo_server(server);
+
+        let mut stream = connect(port, client_config(&certs.ca_cert, None).unwrap()).unwrap();
+        stream.write_all(b"hello").unwrap();
+        let mut buf = [0; 5];
+        stream.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"hello");
+    }
+
+    #[test]
+    fn client_certificate_verification() {
+        let certs = TestCerts::generate("client-auth");
+        let server = server_config(&certs.server_cert, &certs.server_key, Some(&certs.ca_cert)).unwrap();
+
+        // without a client certificate
+        let (port, handle) = echo_server(Arc::clone(&server));
+        let mut stream = connect(port, client_config(&certs.ca_cert, None).unwrap()).unwrap();
+        let _ = stream.write_all(b"hello");
+        assert!(stream.read(&mut [0; 5]).is_err());
+        assert!(handle.join().unwrap().is_err());
+
+        // with a client certificate
+        let (port, _) = echo_server(server);
+        let identity = Some((certs.client_cert.as_ref(), certs.client_key.as_ref()));
+        let mut stream = connect(port, client_config(&certs.ca_cert, identity).unwrap()).unwrap();
+        stream.write_all(b"hello").unwrap();
+        let mut buf = [0; 5];
+        stream.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"hello");
+    }
+
+    #[test]
+    fn untrusted_server() {
+        let certs = TestCerts::generate("untrusted");
+        let other = TestCerts::generate("untrusted-other");
+        let server = server_config(&certs.server_cert, &certs.server_key, None).unwrap();
+        let (port, _) = echo_server(server);
+
+        let mut stream = connect(port, client_config(&other.ca_cert, None).unwrap()).unwrap();
+        let _ = stream.write_all(b"hello");
+        assert!(stream.read(&mut [0; 5]).is_err());
+    }
+}
@@ -20,12 +20,22 @@ pub enum Value {
 
     /// Represents a RESP Array.
     Array(Vec<Value>),
+
+    /// Represents a RESP Null Bulk String or Null Array.
+    Nil,
 }
 
 use std::mem;
 
 impl Value {
+    /// Kept for compatibility, as it consumes the value like `into_string`.
+    #[deprecated(note = "use `into_string`")]
+    #[allow(clippy::wrong_self_convention, clippy::inherent_to_string)]
     pub fn to_string(self) -> String {
+        self.into_string()
+    }
+
+    pub fn into_string(self) -> String {
         match self {
             Value::SimpleString(s) => s,
             Value::Error(s) => s,
@@ -44,7 +54,7 @@ impl Value {
 
 impl From<Vec<String>> for Value {
     fn from(v: Vec<String>) -> Self {
-        let v = v.into_iter().map(|s| Value::BulkString(s)).collect();
+        let v = v.into_iter().map(Value::BulkString).collect();
         Value::Array(v)
     }
 }